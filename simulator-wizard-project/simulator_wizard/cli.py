@@ -34,7 +34,7 @@ def create_simulator(name):
     try:
         if not SILENT:
             click.echo(f"About to create simulator '{name}' with the following steps:")
-            click.echo(f"1. Create UI assets")
+            click.echo(f"1. Create simulator config")
             click.echo(f"2. Create backend plugin")
             click.echo(f"3. Extend Tauri configuration")
             click.echo(f"4. Extend build script")
@@ -91,14 +91,15 @@ def remove_backup_files(backup_files):
         click.echo('Removed backup files.')
 
 def create_ui_assets(simulator_name):
-    """Step 1: Create UI assets for the new simulator."""
-    src = os.path.join(ASSETS_PATH, "asset-barcode")
+    """Step 1: Create the config for the new simulator, the UI in assets/ui is shared."""
+    src = os.path.join(ASSETS_PATH, "asset-barcode", "config.json")
     dest = os.path.join(ASSETS_PATH, f"asset-{simulator_name}")
     if not SILENT:
-        click.echo(f'Creating UI assets at {dest}')
-    shutil.copytree(src, dest)
+        click.echo(f'Creating simulator config at {dest}')
+    os.makedirs(dest)
+    shutil.copyfile(src, os.path.join(dest, "config.json"))
     if not SILENT:
-        click.echo(f'UI assets created at {dest}')
+        click.echo(f'Simulator config created at {dest}')
 
 def create_backend_plugin(simulator_name):
     """Step 2: Create the backend plugin for the new simulator."""
@@ -254,8 +255,9 @@ def modify_tauri_conf(simulator_name):
         config = json.load(file)
     
     # Update fields with the new simulator name
-    config['build']['devPath'] = f"../assets/asset-{simulator_name}"
-    config['build']['distDir'] = f"../assets/asset-{simulator_name}"
+    config['build']['devPath'] = "../assets/ui"
+    config['build']['distDir'] = "../assets/ui"
+    config['tauri']['bundle']['resources'] = [f"../assets/asset-{simulator_name}/config.json"]
     config['package']['productName'] = f"{simulator_name}_simulator"
     config['tauri']['bundle']['identifier'] = f"{simulator_name}sim"
     config['tauri']['windows'][0]['title'] = f"{simulator_name}_simulator"
@@ -0,0 +1,302 @@
+// Generic simulator UI: the page is built from the UI description the plugin gives
+// through get_ui_description (see plugin_interface::ui), so every device gets the same
+// front end. In the Tauri window it talks to the plugin over IPC: actions go through
+// send_js_message and what the plugin sends on the js port arrives as js_message events.
+// Served over HTTP (see simulator_server/src/http.rs) it uses the /api endpoints and
+// the js WebSocket instead.
+
+// the event log keeps this many messages, newest first
+const LOG_SIZE = 50;
+// and the message inspector this many
+const INSPECTOR_SIZE = 500;
+
+function tauriBackend() {
+    const { invoke } = window.__TAURI__.tauri;
+    const { listen } = window.__TAURI__.event;
+    return {
+        uiDescription: () => invoke('get_ui_description'),
+        faultProfiles: () => Promise.all([invoke('list_fault_profiles'), invoke('get_fault_profile')]),
+        setFaultProfile: name => invoke('set_fault_profile', { name }),
+        connect: onMessage => {
+            listen('js_message', event => onMessage(event.payload));
+            return message => invoke('send_js_message', { message });
+        },
+        inspect: onRecord => listen('inspector', event => onRecord(event.payload))
+            .then(() => (direction, port, message) => invoke('inspector_send', { direction, port, message })),
+    };
+}
+
+function httpBackend() {
+    const getJson = path => fetch(path).then(response => {
+        if (!response.ok) {
+            throw new Error(`${path}: ${response.status}`);
+        }
+        return response.json();
+    });
+    return {
+        uiDescription: () => getJson('/api/ui'),
+        faultProfiles: () => getJson('/api/fault-profiles').then(({ profiles, current }) => [profiles, current]),
+        setFaultProfile: name => fetch('/api/fault-profile', { method: 'POST', body: name }),
+        connect: onMessage => {
+            const queue = [];
+            let ws = null;
+            getJson('/api/js-port').then(jsPort => {
+                ws = new WebSocket(`ws://${window.location.hostname}:${jsPort}`);
+                ws.onopen = () => queue.splice(0).forEach(message => ws.send(message));
+                ws.onmessage = event => onMessage(event.data);
+            });
+            return message => {
+                if (ws && ws.readyState === WebSocket.OPEN) {
+                    ws.send(message);
+                } else {
+                    queue.push(message);
+                }
+                return Promise.resolve();
+            };
+        },
+        // through the control port, see simulator_server/src/control.rs
+        inspect: onRecord => getJson('/api/control-port').then(controlPort => {
+            if (controlPort === null) {
+                throw new Error('set control_port to inspect messages');
+            }
+            return new Promise((resolve, reject) => {
+                const ws = new WebSocket(`ws://${window.location.hostname}:${controlPort}`);
+                ws.onerror = reject;
+                ws.onopen = () => {
+                    ws.send(JSON.stringify({ command: 'inspect' }));
+                    resolve((direction, port, message) => {
+                        ws.send(JSON.stringify({ command: 'send', direction, port, message }));
+                        return Promise.resolve();
+                    });
+                };
+                ws.onmessage = event => {
+                    const data = JSON.parse(event.data);
+                    if (data.event === 'inspect') {
+                        onRecord(data.record);
+                    }
+                };
+            });
+        }),
+    };
+}
+
+const backend = window.__TAURI__ ? tauriBackend() : httpBackend();
+
+function row(labelText, element) {
+    const div = document.createElement('div');
+    div.className = 'control';
+    if (labelText) {
+        const label = document.createElement('label');
+        label.textContent = `${labelText}:`;
+        div.appendChild(label);
+    }
+    div.appendChild(element);
+    return div;
+}
+
+// Creates the controls in `container` and returns what incoming messages update
+function buildControls(description, container, send) {
+    const page = { inputs: {}, badges: [], buttons: [], logs: [] };
+
+    description.controls.forEach(control => {
+        switch (control.type) {
+            case 'status_badge': {
+                const badge = document.createElement('span');
+                badge.className = 'badge';
+                badge.textContent = '-';
+                page.badges.push({ control, element: badge });
+                container.appendChild(row(control.label, badge));
+                break;
+            }
+            case 'text_input': {
+                const input = document.createElement('input');
+                input.type = 'text';
+                input.placeholder = control.placeholder || '';
+                if (control.max_length) {
+                    input.maxLength = control.max_length;
+                }
+                page.inputs[control.field] = input;
+                container.appendChild(row(control.label, input));
+                break;
+            }
+            case 'select': {
+                const select = document.createElement('select');
+                control.options.forEach(option => select.add(new Option(option, option)));
+                page.inputs[control.field] = select;
+                container.appendChild(row(control.label, select));
+                break;
+            }
+            case 'button': {
+                const button = document.createElement('button');
+                button.textContent = control.label;
+                // buttons tied to a status wait for the first one
+                button.disabled = control.enabled_in.length > 0;
+                button.addEventListener('click', () => {
+                    const message = { action: control.action };
+                    control.fields.forEach(field => {
+                        if (page.inputs[field]) {
+                            message[field] = page.inputs[field].value;
+                        }
+                    });
+                    send(message);
+                });
+                page.buttons.push({ control, element: button });
+                container.appendChild(button);
+                break;
+            }
+            case 'event_log': {
+                const list = document.createElement('ul');
+                list.className = 'log';
+                page.logs.push(list);
+                container.appendChild(row(control.label, list));
+                break;
+            }
+            default:
+                console.warn('Unknown control:', control);
+        }
+    });
+
+    return page;
+}
+
+function update(page, data) {
+    page.badges.forEach(({ control, element }) => {
+        if (data.event !== control.event || data[control.field] === undefined) {
+            return;
+        }
+        const status = String(data[control.field]);
+        element.textContent = status;
+        element.className = `badge status-${status}`;
+        page.buttons.forEach(({ control, element }) => {
+            if (control.enabled_in.length > 0) {
+                element.disabled = !control.enabled_in.includes(status);
+            }
+        });
+    });
+
+    page.logs.forEach(list => {
+        const item = document.createElement('li');
+        item.textContent = JSON.stringify(data);
+        list.prepend(item);
+        while (list.children.length > LOG_SIZE) {
+            list.lastChild.remove();
+        }
+    });
+}
+
+function recordLine(record) {
+    const time = new Date(record.timestamp_ms).toISOString().slice(11, 23);
+    const arrow = record.direction === 'inbound' ? '->' : '<-';
+    return `${time} ${arrow} ${record.port} #${record.client_id} ${record.message}`;
+}
+
+// Message inspector: every message in or out of the js and external ports, newest
+// first. Pausing stops the list, not the recording; Copy gives the shown records as
+// JSON Lines, the format of the session trace.
+function setupInspector() {
+    const log = document.getElementById('inspectorLog');
+    const filter = document.getElementById('inspectorFilter');
+    const pause = document.getElementById('inspectorPause');
+    const target = document.getElementById('inspectorTarget');
+    const editor = document.getElementById('inspectorMessage');
+    const status = document.getElementById('inspectorStatus');
+
+    const records = [];
+    let paused = false;
+
+    const visible = record => recordLine(record).toLowerCase().includes(filter.value.toLowerCase());
+    const item = record => {
+        const li = document.createElement('li');
+        li.className = `${record.direction} port-${record.port}`;
+        li.textContent = recordLine(record);
+        return li;
+    };
+    const render = () => log.replaceChildren(...records.filter(visible).map(item));
+
+    const onRecord = record => {
+        records.unshift(record);
+        records.length = Math.min(records.length, INSPECTOR_SIZE);
+        if (!paused && visible(record)) {
+            log.prepend(item(record));
+            while (log.children.length > INSPECTOR_SIZE) {
+                log.lastChild.remove();
+            }
+        }
+    };
+
+    filter.addEventListener('input', render);
+    pause.addEventListener('click', () => {
+        paused = !paused;
+        pause.textContent = paused ? 'Resume' : 'Pause';
+        if (!paused) {
+            render();
+        }
+    });
+    document.getElementById('inspectorClear').addEventListener('click', () => {
+        records.length = 0;
+        render();
+    });
+    document.getElementById('inspectorCopy').addEventListener('click', () => {
+        const lines = records.filter(visible).reverse().map(record => JSON.stringify(record));
+        navigator.clipboard.writeText(lines.join('\n'))
+            .then(() => status.textContent = `Copied ${lines.length} messages`)
+            .catch(error => status.textContent = `Unable to copy: ${error}`);
+    });
+
+    backend.inspect(onRecord)
+        .then(sendAs => {
+            document.getElementById('inspectorSend').addEventListener('click', () => {
+                let message;
+                try {
+                    message = JSON.stringify(JSON.parse(editor.value));
+                } catch (error) {
+                    status.textContent = `Invalid JSON: ${error.message}`;
+                    return;
+                }
+                const [direction, port] = target.value.split(':');
+                sendAs(direction, port, message)
+                    .then(() => status.textContent = '')
+                    .catch(error => status.textContent = `Unable to send: ${error}`);
+            });
+        })
+        .catch(error => {
+            console.warn('Message inspector unavailable:', error);
+            document.getElementById('inspector').hidden = true;
+        });
+}
+
+document.addEventListener('DOMContentLoaded', () => {
+    setupInspector();
+
+    // Fault injection profiles for the WebSocket connections (see fault_profiles in config.json)
+    const faultSelect = document.getElementById('faultSelect');
+
+    backend.faultProfiles()
+        .then(([profiles, current]) => {
+            profiles.forEach(name => faultSelect.add(new Option(name, name, false, name === current)));
+        })
+        .catch(error => console.error('Error fetching fault profiles:', error));
+
+    faultSelect.addEventListener('change', () => {
+        backend.setFaultProfile(faultSelect.value)
+            .catch(error => console.error('Error setting fault profile:', error));
+    });
+
+    backend.uiDescription()
+        .then(description => {
+            document.title = description.title;
+            document.getElementById('title').textContent = description.title;
+
+            let sendText = null;
+            const send = message => sendText(JSON.stringify(message))
+                .catch(error => console.error('Error sending message:', error));
+            const page = buildControls(description, document.getElementById('controls'), send);
+
+            sendText = backend.connect(text => {
+                const data = JSON.parse(text);
+                console.log('Received message:', data);
+                update(page, data);
+            });
+        })
+        .catch(error => console.error('Error building the simulator UI:', error));
+});
@@ -15,8 +15,30 @@ switch ($feature) {
     "card" { 
         $CONFIG_FILE = "src-tauri/tauri-card.conf.json"
     }
+    "declarative" { 
+        $CONFIG_FILE = "src-tauri/tauri-declarative.conf.json"
+    }
+    "script" { 
+        $CONFIG_FILE = "src-tauri/tauri-script.conf.json"
+    }
+    # plugins are loaded from the plugins directory next to the executable, build them with
+    # cargo build --release --manifest-path plugins/dynamic/Cargo.toml
+    "dynamic" { 
+        $CONFIG_FILE = "src-tauri/tauri-dynamic.conf.json"
+    }
+    # the device module (device.wasm) goes next to the executable
+    "wasm" { 
+        $CONFIG_FILE = "src-tauri/tauri-wasm.conf.json"
+    }
     default {
-        Write-Output "Usage: .\build.ps1 [assets1|assets2|assets3]"
+        Write-Output "Usage: .\build.ps1 [barcode|bna|card|declarative|script|wasm|dynamic]"
+        Write-Output ""
+        Write-Output "  barcode, bna, card  a bundled device compiled into the simulator"
+        Write-Output "  declarative         a device described in device.toml next to the executable"
+        Write-Output "  script              a device written as a Rhai script, device.rhai next to the executable"
+        Write-Output "  wasm                a device compiled to WebAssembly, device.wasm next to the executable"
+        Write-Output "  dynamic             a device loaded from a shared library in the plugins directory,"
+        Write-Output "                      build them with cargo build --release --manifest-path plugins/dynamic/Cargo.toml"
         exit 1
     }
 }
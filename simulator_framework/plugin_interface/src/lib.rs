@@ -1,24 +1,976 @@
 
 pub mod interface_for_server
 {
+    use std::time::Duration;
     use tokio_tungstenite::tungstenite::protocol::Message;
 
+    pub use tracing::Level;
+
     // Add Send + Sync bounds to the trait definition
 
     pub trait CommunicationInterface{
         fn send_to_js_clients(&self, message: Message);
         fn send_to_external(&self, message: Message);
+
+        // Timers, provided by the framework on top of its clock: when a timer expires
+        // the plugin's handle_timer is called with its name. Setting a timer that is
+        // already running restarts it.
+        fn now(&self) -> Duration {
+            Duration::ZERO
+        }
+        fn set_timer(&self, _name: &str, _delay: Duration) {}
+        fn cancel_timer(&self, _name: &str) {}
+
+        // The plugin's logger: what it logs goes to the simulator's log under the
+        // "plugin" target, inside the span of the message being handled
+        fn log(&self, level: Level, message: &str) {
+            log_event(level, message);
+        }
+
+        // The plugin's own metrics, served with the simulator's on /metrics: counters only
+        // go up, gauges are set. Labels split a metric in series, e.g.
+        // count("bna_reads_total", &[("denomination", "20")], 1.0)
+        fn count(&self, _name: &str, _labels: &[(&str, &str)], _by: f64) {}
+        fn gauge(&self, _name: &str, _labels: &[(&str, &str)], _value: f64) {}
+    }
+
+    pub fn log_event(level: Level, message: &str) {
+        match level {
+            Level::ERROR => tracing::error!(target: "plugin", "{}", message),
+            Level::WARN => tracing::warn!(target: "plugin", "{}", message),
+            Level::INFO => tracing::info!(target: "plugin", "{}", message),
+            Level::DEBUG => tracing::debug!(target: "plugin", "{}", message),
+            Level::TRACE => tracing::trace!(target: "plugin", "{}", message),
+        }
+    }
+
+    // Levels as plugins outside Rust give them, 1 (error) to 5 (trace)
+    pub fn level_from_number(level: i64) -> Option<Level> {
+        match level {
+            1 => Some(Level::ERROR),
+            2 => Some(Level::WARN),
+            3 => Some(Level::INFO),
+            4 => Some(Level::DEBUG),
+            5 => Some(Level::TRACE),
+            _ => None,
+        }
+    }
+
+    // Labels as plugins outside Rust give them, a JSON object of strings: {"denomination": "20"}.
+    // Empty text means no labels.
+    pub fn labels_from_json(text: &str) -> Result<Vec<(String, String)>, String> {
+        if text.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        let labels: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(text).map_err(|e| format!("labels are not a JSON object: {}", e))?;
+        // numbers and the like are taken as their text
+        Ok(labels
+            .into_iter()
+            .map(|(name, value)| match value {
+                serde_json::Value::String(value) => (name, value),
+                value => (name, value.to_string()),
+            })
+            .collect())
+    }
+
+    pub fn labels_to_json(labels: &[(&str, &str)]) -> String {
+        let labels: serde_json::Map<String, serde_json::Value> =
+            labels.iter().map(|(name, value)| (name.to_string(), serde_json::Value::from(*value))).collect();
+        serde_json::Value::Object(labels).to_string()
+    }
+
+    pub fn level_number(level: Level) -> i64 {
+        match level {
+            Level::ERROR => 1,
+            Level::WARN => 2,
+            Level::INFO => 3,
+            Level::DEBUG => 4,
+            Level::TRACE => 5,
+        }
+    }
+}
+
+pub mod clock
+{
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    // Time as seen by plugins, measured from the moment the clock was created
+    pub trait Clock: Send + Sync {
+        fn now(&self) -> Duration;
+    }
+
+    pub struct RealClock {
+        start: Instant,
+    }
+
+    impl RealClock {
+        pub fn new() -> Self {
+            RealClock { start: Instant::now() }
+        }
+    }
+
+    impl Default for RealClock {
+        fn default() -> Self {
+            RealClock::new()
+        }
+    }
+
+    impl Clock for RealClock {
+        fn now(&self) -> Duration {
+            self.start.elapsed()
+        }
+    }
+
+    // A clock that only moves when told to, for tests and scenario runs
+    #[derive(Default)]
+    pub struct VirtualClock {
+        now: Mutex<Duration>,
+    }
+
+    impl VirtualClock {
+        pub fn new() -> Self {
+            VirtualClock::default()
+        }
+
+        // The clock never goes backwards
+        pub fn advance_to(&self, time: Duration) {
+            let mut now = self.now.lock().unwrap();
+            if time > *now {
+                *now = time;
+            }
+        }
+    }
+
+    impl Clock for VirtualClock {
+        fn now(&self) -> Duration {
+            *self.now.lock().unwrap()
+        }
+    }
+}
+
+// The messages exchanged with the host on the external port and with the simulator
+// UI on the js port, shared by the plugins and by simulator_client
+pub mod protocol
+{
+    use crate::metadata::Metadata;
+    use serde::{Deserialize, Serialize};
+    use tokio_tungstenite::tungstenite::protocol::Message;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "UPPERCASE")]
+    pub enum DeviceStatus {
+        Armed,
+        Disabled,
+        Error,
+    }
+
+    impl DeviceStatus {
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                DeviceStatus::Armed => "ARMED",
+                DeviceStatus::Disabled => "DISABLED",
+                DeviceStatus::Error => "ERROR",
+            }
+        }
+    }
+
+    // host -> device, e.g. {"action": "enable"}
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(tag = "action", rename_all = "snake_case")]
+    pub enum HostAction {
+        Enable,
+        Disable,
+        QueryStatus,
+        ConfirmRead,
+    }
+
+    // simulator UI -> device, e.g. {"action": "read", "value": "20"}
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(tag = "action", rename_all = "snake_case")]
+    pub enum UiAction {
+        Read { value: String },
+        // toggles the device in and out of ERROR
+        Error,
+    }
+
+    // device -> host and UI, e.g. {"event": "statusChange", "status": "ARMED"}
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(tag = "event")]
+    pub enum DeviceEvent {
+        #[serde(rename = "statusChange")]
+        StatusChange { status: DeviceStatus },
+        #[serde(rename = "read")]
+        Read { value: String },
+        // acknowledges the host's confirm_read
+        #[serde(rename = "confirm_read")]
+        ConfirmRead,
+        // a read the host did not confirm in time, given back
+        #[serde(rename = "returned")]
+        Returned { value: String },
+        // a message the device could not act on, answered on the port it came from
+        #[serde(rename = "error")]
+        Error { message: String },
+        // answers {"action": "describe"}, see metadata
+        #[serde(rename = "describe")]
+        Describe(Metadata),
+    }
+
+    // Messages that are not valid JSON, or not one of T's variants, give None
+    pub fn parse<T: serde::de::DeserializeOwned>(text: &str) -> Option<T> {
+        serde_json::from_str(text).ok()
+    }
+
+    pub fn to_message<T: Serialize>(message: &T) -> Message {
+        Message::Text(serde_json::to_string(message).expect("protocol messages always serialize"))
+    }
+}
+
+// Runtime side of plugin_macros: #[plugin_actions] generates calls to these and
+// describes the actions it dispatches with Action
+pub mod actions
+{
+    use crate::protocol::{to_message, DeviceEvent};
+    use serde::de::DeserializeOwned;
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+    use tokio_tungstenite::tungstenite::protocol::Message;
+
+    use std::borrow::Cow;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Origin {
+        // the simulator UI
+        Js,
+        // the host
+        External,
+    }
+
+    // Borrowed for the catalogues built at compile time, owned for plugins that only
+    // know their actions at runtime
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Field {
+        pub name: Cow<'static, str>,
+        // the Rust type, as written in the handler
+        #[serde(rename = "type")]
+        pub ty: Cow<'static, str>,
+        pub required: bool,
+    }
+
+    impl Field {
+        pub const fn new(name: &'static str, ty: &'static str, required: bool) -> Self {
+            Field { name: Cow::Borrowed(name), ty: Cow::Borrowed(ty), required }
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Action {
+        pub origin: Origin,
+        pub name: Cow<'static, str>,
+        pub fields: Cow<'static, [Field]>,
+    }
+
+    impl Action {
+        pub const fn new(origin: Origin, name: &'static str, fields: &'static [Field]) -> Self {
+            Action { origin, name: Cow::Borrowed(name), fields: Cow::Borrowed(fields) }
+        }
+    }
+
+    // {"action": "read", ...} gives ("read", the whole message)
+    pub fn parse_action(text: &str) -> Result<(String, Value), String> {
+        let message: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e))?;
+        match message.get("action").and_then(Value::as_str) {
+            Some(action) => Ok((action.to_string(), message)),
+            None => Err("missing action".to_string()),
+        }
+    }
+
+    // A missing field reads as null, so Option fields may be left out
+    pub fn field<T: DeserializeOwned>(message: &Value, name: &str) -> Result<T, String> {
+        let value = message.get(name).cloned().unwrap_or(Value::Null);
+        let missing = value.is_null();
+        serde_json::from_value(value).map_err(|e| {
+            if missing {
+                format!("missing field '{}'", name)
+            } else {
+                format!("invalid field '{}': {}", name, e)
+            }
+        })
+    }
+
+    pub fn error_reply(message: &str) -> Message {
+        to_message(&DeviceEvent::Error { message: message.to_string() })
+    }
+}
+
+// What a device is and what it understands. Hosts and the UI get it by sending
+// {"action": "describe"} on the external port, which the framework answers for every
+// plugin with
+//
+//     {"event": "describe", "device_type": "bna", "model": "BNA simulator", "version": "0.1.0",
+//      "actions": [{"origin": "js", "name": "read", "fields": [...]}, ...],
+//      "events": [{"name": "read", "fields": [{"name": "value", "type": "String", "required": true}]}, ...]}
+pub mod metadata
+{
+    use crate::actions::{Action, Field};
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    use std::borrow::Cow;
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Event {
+        pub name: Cow<'static, str>,
+        pub fields: Cow<'static, [Field]>,
+    }
+
+    impl Event {
+        pub const fn new(name: &'static str, fields: &'static [Field]) -> Self {
+            Event { name: Cow::Borrowed(name), fields: Cow::Borrowed(fields) }
+        }
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct Metadata {
+        pub device_type: String,
+        pub model: String,
+        pub version: String,
+        pub actions: Vec<Action>,
+        // what the device sends, on either port
+        pub events: Vec<Event>,
+    }
+
+    // The events of protocol::DeviceEvent, describe aside
+    pub const DEVICE_EVENTS: &[Event] = &[
+        Event::new("statusChange", STATUS),
+        Event::new("read", VALUE),
+        Event::new("confirm_read", &[]),
+        Event::new("returned", VALUE),
+        Event::new("error", MESSAGE),
+    ];
+
+    const STATUS: &[Field] = &[Field::new("status", "DeviceStatus", true)];
+    const VALUE: &[Field] = &[Field::new("value", "String", true)];
+    const MESSAGE: &[Field] = &[Field::new("message", "String", true)];
+
+    // For devices that only send some of them
+    pub fn device_events(names: &[&str]) -> Vec<Event> {
+        DEVICE_EVENTS.iter().filter(|event| names.contains(&event.name.as_ref())).cloned().collect()
+    }
+
+    pub fn is_describe(text: &str) -> bool {
+        serde_json::from_str::<Value>(text).map(|message| message["action"] == "describe").unwrap_or(false)
+    }
+}
+
+// What the generic simulator UI (assets/ui) draws for a device, top to bottom.
+// Inputs are named after the message field they fill; a button sends its action with
+// the values of the inputs it lists:
+//
+//     UiDescription {
+//         title: "BNA simulator".to_string(),
+//         controls: vec![
+//             Control::status_badge(),
+//             Control::Select { label: "Value".to_string(), field: "value".to_string(), options: ... },
+//             Control::Button { label: "Send".to_string(), action: "read".to_string(),
+//                               fields: vec!["value".to_string()], enabled_in: vec!["ARMED".to_string()] },
+//             Control::EventLog { label: "Events".to_string() },
+//         ],
+//     }
+pub mod ui
+{
+    use crate::actions::Origin;
+    use crate::metadata::Metadata;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct UiDescription {
+        pub title: String,
+        pub controls: Vec<Control>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    pub enum Control {
+        // shows `field` of the last `event` received, e.g. the status of statusChange
+        StatusBadge { label: String, event: String, field: String },
+        TextInput {
+            label: String,
+            field: String,
+            #[serde(default)]
+            placeholder: String,
+            #[serde(default)]
+            max_length: Option<u32>,
+        },
+        Select { label: String, field: String, options: Vec<String> },
+        // with enabled_in, only clickable while the status badge shows one of those states
+        Button {
+            label: String,
+            action: String,
+            #[serde(default)]
+            fields: Vec<String>,
+            #[serde(default)]
+            enabled_in: Vec<String>,
+        },
+        // every message the device sends to the UI
+        EventLog { label: String },
+    }
+
+    impl Control {
+        pub fn status_badge() -> Control {
+            Control::StatusBadge { label: "Status".to_string(), event: "statusChange".to_string(), field: "status".to_string() }
+        }
+
+        pub fn event_log() -> Control {
+            Control::EventLog { label: "Events".to_string() }
+        }
+    }
+
+    impl UiDescription {
+        // For plugins that describe no UI of their own: a text input for every field of
+        // the UI's actions and a button for each of them
+        pub fn from_metadata(metadata: &Metadata) -> UiDescription {
+            let mut controls = Vec::new();
+            if metadata.events.iter().any(|event| event.name == "statusChange") {
+                controls.push(Control::status_badge());
+            }
+
+            let mut buttons = Vec::new();
+            for action in metadata.actions.iter().filter(|action| action.origin == Origin::Js) {
+                for field in action.fields.iter() {
+                    let exists = controls.iter().any(|c| matches!(c, Control::TextInput { field: f, .. } if *f == field.name));
+                    if !exists {
+                        controls.push(Control::TextInput {
+                            label: field.name.to_string(),
+                            field: field.name.to_string(),
+                            placeholder: field.ty.to_string(),
+                            max_length: None,
+                        });
+                    }
+                }
+                buttons.push(Control::Button {
+                    label: action.name.to_string(),
+                    action: action.name.to_string(),
+                    fields: action.fields.iter().map(|field| field.name.to_string()).collect(),
+                    enabled_in: Vec::new(),
+                });
+            }
+            controls.extend(buttons);
+            controls.push(Control::event_log());
+
+            let title = if metadata.model.is_empty() { "Simulator" } else { &metadata.model };
+            UiDescription { title: title.to_string(), controls }
+        }
+    }
+}
+
+// The status every device goes through, described once:
+//
+//     let machine = StateMachine::new(DeviceStatus::Disabled)
+//         .transition("enable", [DeviceStatus::Armed, DeviceStatus::Disabled], DeviceStatus::Armed)
+//         .transition("error", [DeviceStatus::Error], DeviceStatus::Disabled)
+//         .transition_from_any("error", DeviceStatus::Error);
+//
+// fire() takes the first transition for the trigger that is allowed from the current
+// state and whose guard accepts the message, then sends the new status to the UI and
+// to the host. A trigger with no such transition leaves the state alone and is
+// reported back as an IllegalTransition.
+pub mod state_machine
+{
+    use crate::interface_for_server::CommunicationInterface;
+    use crate::protocol::DeviceStatus;
+    use serde_json::Value;
+    use std::fmt;
+    use std::sync::Arc;
+    use tokio_tungstenite::tungstenite::protocol::Message;
+
+    pub trait State: Clone + PartialEq + fmt::Debug {
+        // as sent in statusChange
+        fn name(&self) -> &str;
+    }
+
+    impl State for DeviceStatus {
+        fn name(&self) -> &str {
+            self.as_str()
+        }
+    }
+
+    // for states only known at runtime
+    impl State for String {
+        fn name(&self) -> &str {
+            self
+        }
+    }
+
+    pub type Guard = Arc<dyn Fn(&Value) -> bool + Send + Sync>;
+
+    #[derive(Clone)]
+    struct Transition<S: State> {
+        trigger: String,
+        // None: allowed from any state
+        from: Option<Vec<S>>,
+        to: S,
+        guard: Option<Guard>,
+    }
+
+    impl<S: State> Transition<S> {
+        fn allows(&self, trigger: &str, state: &S, message: &Value) -> bool {
+            self.trigger == trigger
+                && self.from.as_ref().map(|from| from.contains(state)).unwrap_or(true)
+                && self.guard.as_ref().map(|guard| guard(message)).unwrap_or(true)
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct IllegalTransition {
+        pub trigger: String,
+        pub state: String,
+    }
+
+    impl fmt::Display for IllegalTransition {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "'{}' is not allowed in state {}", self.trigger, self.state)
+        }
+    }
+
+    impl std::error::Error for IllegalTransition {}
+
+    #[derive(Clone)]
+    pub struct StateMachine<S: State> {
+        state: S,
+        transitions: Vec<Transition<S>>,
+    }
+
+    impl<S: State> StateMachine<S> {
+        pub fn new(initial: S) -> Self {
+            StateMachine { state: initial, transitions: Vec::new() }
+        }
+
+        pub fn transition(self, trigger: &str, from: impl IntoIterator<Item = S>, to: S) -> Self {
+            self.add(trigger, Some(from.into_iter().collect()), to, None)
+        }
+
+        pub fn transition_from_any(self, trigger: &str, to: S) -> Self {
+            self.add(trigger, None, to, None)
+        }
+
+        // Only taken when the guard accepts the message that triggered it
+        pub fn guarded_transition(
+            self,
+            trigger: &str,
+            from: impl IntoIterator<Item = S>,
+            to: S,
+            guard: impl Fn(&Value) -> bool + Send + Sync + 'static,
+        ) -> Self {
+            self.add(trigger, Some(from.into_iter().collect()), to, Some(Arc::new(guard)))
+        }
+
+        pub fn guarded_transition_from_any(
+            self,
+            trigger: &str,
+            to: S,
+            guard: impl Fn(&Value) -> bool + Send + Sync + 'static,
+        ) -> Self {
+            self.add(trigger, None, to, Some(Arc::new(guard)))
+        }
+
+        fn add(mut self, trigger: &str, from: Option<Vec<S>>, to: S, guard: Option<Guard>) -> Self {
+            self.transitions.push(Transition { trigger: trigger.to_string(), from, to, guard });
+            self
+        }
+
+        pub fn state(&self) -> &S {
+            &self.state
+        }
+
+        // Moves to `state` without sending anything, for plugins restoring a snapshot
+        pub fn restore(&mut self, state: S) {
+            self.state = state;
+        }
+
+        pub fn can_fire(&self, trigger: &str, message: &Value) -> bool {
+            self.transitions.iter().any(|t| t.allows(trigger, &self.state, message))
+        }
+
+        // Moves to the next state and sends statusChange to both sides, even when the
+        // state does not change
+        pub fn fire<I: CommunicationInterface>(
+            &mut self,
+            interface: &I,
+            trigger: &str,
+            message: &Value,
+        ) -> Result<&S, IllegalTransition> {
+            let Some(transition) = self.transitions.iter().find(|t| t.allows(trigger, &self.state, message)) else {
+                return Err(IllegalTransition { trigger: trigger.to_string(), state: self.state.name().to_string() });
+            };
+
+            self.state = transition.to.clone();
+
+            let status_msg = self.status_message();
+            interface.send_to_js_clients(status_msg.clone());
+            interface.send_to_external(status_msg);
+            Ok(&self.state)
+        }
+
+        // {"event": "statusChange", "status": ...} for the current state
+        pub fn status_message(&self) -> Message {
+            let status_msg = serde_json::json!({ "event": "statusChange", "status": self.state.name() });
+            Message::Text(status_msg.to_string())
+        }
+    }
+
+    impl StateMachine<DeviceStatus> {
+        // What every bundled device does: starts DISABLED, the host enables and
+        // disables it except in ERROR, the UI's error action toggles ERROR
+        pub fn device() -> Self {
+            use DeviceStatus::*;
+
+            StateMachine::new(Disabled)
+                .transition("enable", [Armed, Disabled], Armed)
+                .transition("disable", [Armed, Disabled], Disabled)
+                .transition("error", [Error], Disabled)
+                .transition_from_any("error", Error)
+        }
     }
 }
 
 pub mod interface_for_plugin
 {
     use crate::interface_for_server::CommunicationInterface;
+    use crate::metadata::Metadata;
+    use crate::ui::UiDescription;
+    use serde_json::Value;
+    use std::path::{Path, PathBuf};
+    use std::time::SystemTime;
 
     pub trait Plugin {
         fn new() -> Self; // Add new method to the trait
         fn handle_js_message<I: CommunicationInterface>(&mut self, interface: &I, text: String);
         fn handle_external_message<I: CommunicationInterface>(&mut self, interface: &I, text: String);
+
+        // Called when a timer set through the interface expires
+        fn handle_timer<I: CommunicationInterface>(&mut self, _interface: &I, _name: &str) {}
+
+        // The plugin's state as JSON, for tests and tooling that want to look inside
+        fn snapshot(&self) -> Value {
+            Value::Null
+        }
+
+        // Hot reload: when the file returned by artifact changes, the framework loads a
+        // new instance with reloaded and hands it the old one's snapshot to carry on
+        // from. An artifact that does not load keeps the old instance running.
+        fn artifact(&self) -> Option<PathBuf> {
+            None
+        }
+        fn reloaded(&self) -> Result<Self, String>
+        where
+            Self: Sized,
+        {
+            Ok(Self::new())
+        }
+        fn restore(&mut self, _snapshot: &Value) {}
+
+        // What the device is and the messages it understands, see metadata
+        fn metadata(&self) -> Metadata {
+            Metadata::default()
+        }
+
+        // What the generic simulator UI shows, see ui
+        fn ui(&self) -> UiDescription {
+            UiDescription::from_metadata(&self.metadata())
+        }
+    }
+
+    // What hot reload compares to tell a rebuilt artifact, None while the file is missing
+    pub fn modified_time(path: &Path) -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+}
+
+// C boundary for plugins built as a cdylib and loaded at runtime by plugin_loader.
+// A plugin crate exports itself with
+//
+//     plugin_interface::export_plugin!(MyPlugin);
+//
+// which defines the `simulator_plugin` symbol returning the plugin's PluginVTable.
+// Text crosses the boundary as UTF-8 bytes, and whatever is allocated on one side
+// is freed on the same side. A panic inside the plugin is caught on its side of the
+// boundary and reported to the host as a failed call.
+pub mod abi
+{
+    use crate::interface_for_plugin::Plugin;
+    use crate::interface_for_server::{self, CommunicationInterface, Level};
+    use std::ffi::c_void;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::time::Duration;
+    use tokio_tungstenite::tungstenite::protocol::Message;
+
+    // bumped whenever PluginVTable or HostVTable change
+    pub const ABI_VERSION: u32 = 6;
+    // the plugin_interface a plugin was built against, see is_compatible
+    pub const INTERFACE_VERSION: &str = env!("CARGO_PKG_VERSION");
+    pub const ENTRY_POINT: &[u8] = b"simulator_plugin\0";
+
+    pub type EntryPoint = unsafe extern "C" fn() -> *const PluginVTable;
+
+    // Borrowed UTF-8 text, valid for the duration of the call it is passed to
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct Str {
+        pub ptr: *const u8,
+        pub len: usize,
+    }
+
+    impl Str {
+        pub const fn new(text: &str) -> Str {
+            Str { ptr: text.as_ptr(), len: text.len() }
+        }
+
+        /// # Safety
+        /// `ptr` must point to `len` readable bytes.
+        pub unsafe fn to_string(self) -> String {
+            String::from_utf8_lossy(std::slice::from_raw_parts(self.ptr, self.len)).into_owned()
+        }
+    }
+
+    // The CommunicationInterface lent to the plugin for one call
+    #[repr(C)]
+    pub struct HostVTable {
+        pub context: *const c_void,
+        pub send_to_js_clients: unsafe extern "C" fn(*const c_void, Str),
+        pub send_to_external: unsafe extern "C" fn(*const c_void, Str),
+        pub now_nanos: unsafe extern "C" fn(*const c_void) -> u64,
+        pub set_timer: unsafe extern "C" fn(*const c_void, Str, u64),
+        pub cancel_timer: unsafe extern "C" fn(*const c_void, Str),
+        // level as interface_for_server::level_number
+        pub log: unsafe extern "C" fn(*const c_void, u8, Str),
+        // name, labels as interface_for_server::labels_to_json, value
+        pub count: unsafe extern "C" fn(*const c_void, Str, Str, f64),
+        pub gauge: unsafe extern "C" fn(*const c_void, Str, Str, f64),
+    }
+
+    impl HostVTable {
+        // the table points at `interface`, it must not outlive it
+        pub fn new<I: CommunicationInterface>(interface: &I) -> HostVTable {
+            HostVTable {
+                context: interface as *const I as *const c_void,
+                send_to_js_clients: host_send_to_js_clients::<I>,
+                send_to_external: host_send_to_external::<I>,
+                now_nanos: host_now_nanos::<I>,
+                set_timer: host_set_timer::<I>,
+                cancel_timer: host_cancel_timer::<I>,
+                log: host_log::<I>,
+                count: host_count::<I>,
+                gauge: host_gauge::<I>,
+            }
+        }
+    }
+
+    unsafe extern "C" fn host_send_to_js_clients<I: CommunicationInterface>(context: *const c_void, text: Str) {
+        (*(context as *const I)).send_to_js_clients(Message::Text(text.to_string()));
+    }
+
+    unsafe extern "C" fn host_send_to_external<I: CommunicationInterface>(context: *const c_void, text: Str) {
+        (*(context as *const I)).send_to_external(Message::Text(text.to_string()));
+    }
+
+    unsafe extern "C" fn host_now_nanos<I: CommunicationInterface>(context: *const c_void) -> u64 {
+        (*(context as *const I)).now().as_nanos() as u64
+    }
+
+    unsafe extern "C" fn host_set_timer<I: CommunicationInterface>(context: *const c_void, name: Str, delay_nanos: u64) {
+        (*(context as *const I)).set_timer(&name.to_string(), Duration::from_nanos(delay_nanos));
+    }
+
+    unsafe extern "C" fn host_cancel_timer<I: CommunicationInterface>(context: *const c_void, name: Str) {
+        (*(context as *const I)).cancel_timer(&name.to_string());
+    }
+
+    unsafe extern "C" fn host_log<I: CommunicationInterface>(context: *const c_void, level: u8, message: Str) {
+        let level = interface_for_server::level_from_number(level as i64).unwrap_or(Level::INFO);
+        (*(context as *const I)).log(level, &message.to_string());
+    }
+
+    unsafe extern "C" fn host_count<I: CommunicationInterface>(context: *const c_void, name: Str, labels: Str, by: f64) {
+        let interface = &*(context as *const I);
+        with_labels(interface, labels, |labels| interface.count(&name.to_string(), labels, by));
+    }
+
+    unsafe extern "C" fn host_gauge<I: CommunicationInterface>(context: *const c_void, name: Str, labels: Str, value: f64) {
+        let interface = &*(context as *const I);
+        with_labels(interface, labels, |labels| interface.gauge(&name.to_string(), labels, value));
     }
 
+    unsafe fn with_labels<I: CommunicationInterface>(interface: &I, labels: Str, update: impl FnOnce(&[(&str, &str)])) {
+        match interface_for_server::labels_from_json(&labels.to_string()) {
+            Ok(labels) => {
+                let labels: Vec<(&str, &str)> = labels.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect();
+                update(&labels);
+            }
+            Err(e) => interface.log(Level::WARN, &format!("Metric ignored, {}", e)),
+        }
+    }
+
+    // The host's table seen from inside the plugin
+    struct Host<'a>(&'a HostVTable);
+
+    impl CommunicationInterface for Host<'_> {
+        fn send_to_js_clients(&self, message: Message) {
+            let text = message.into_text().unwrap_or_default();
+            unsafe { (self.0.send_to_js_clients)(self.0.context, Str::new(&text)) }
+        }
+
+        fn send_to_external(&self, message: Message) {
+            let text = message.into_text().unwrap_or_default();
+            unsafe { (self.0.send_to_external)(self.0.context, Str::new(&text)) }
+        }
+
+        fn now(&self) -> Duration {
+            Duration::from_nanos(unsafe { (self.0.now_nanos)(self.0.context) })
+        }
+
+        fn set_timer(&self, name: &str, delay: Duration) {
+            unsafe { (self.0.set_timer)(self.0.context, Str::new(name), delay.as_nanos() as u64) }
+        }
+
+        fn cancel_timer(&self, name: &str) {
+            unsafe { (self.0.cancel_timer)(self.0.context, Str::new(name)) }
+        }
+
+        // the plugin library has a tracing of its own, nobody listens to it
+        fn log(&self, level: Level, message: &str) {
+            let level = interface_for_server::level_number(level) as u8;
+            unsafe { (self.0.log)(self.0.context, level, Str::new(message)) }
+        }
+
+        fn count(&self, name: &str, labels: &[(&str, &str)], by: f64) {
+            let labels = interface_for_server::labels_to_json(labels);
+            unsafe { (self.0.count)(self.0.context, Str::new(name), Str::new(&labels), by) }
+        }
+
+        fn gauge(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+            let labels = interface_for_server::labels_to_json(labels);
+            unsafe { (self.0.gauge)(self.0.context, Str::new(name), Str::new(&labels), value) }
+        }
+    }
+
+    // What a plugin library exports. The handle_* functions return false when the
+    // plugin panicked, create returns null.
+    #[repr(C)]
+    pub struct PluginVTable {
+        // checked before anything else is read, keep it first
+        pub abi_version: u32,
+        pub interface_version: Str,
+        pub name: Str,
+        pub create: unsafe extern "C" fn() -> *mut c_void,
+        pub destroy: unsafe extern "C" fn(*mut c_void),
+        pub handle_js_message: unsafe extern "C" fn(*mut c_void, *const HostVTable, Str) -> bool,
+        pub handle_external_message: unsafe extern "C" fn(*mut c_void, *const HostVTable, Str) -> bool,
+        pub handle_timer: unsafe extern "C" fn(*mut c_void, *const HostVTable, Str) -> bool,
+        // hands the snapshot as JSON text to the callback, together with its first argument
+        pub snapshot: unsafe extern "C" fn(*mut c_void, *mut c_void, unsafe extern "C" fn(*mut c_void, Str)),
+        // the snapshot as JSON text
+        pub restore: unsafe extern "C" fn(*mut c_void, Str) -> bool,
+        // hands the metadata as JSON text to the callback, like snapshot
+        pub metadata: unsafe extern "C" fn(*mut c_void, *mut c_void, unsafe extern "C" fn(*mut c_void, Str)),
+        // and the UI description
+        pub ui: unsafe extern "C" fn(*mut c_void, *mut c_void, unsafe extern "C" fn(*mut c_void, Str)),
+    }
+
+    // the pointers inside only refer to statics and functions
+    unsafe impl Sync for PluginVTable {}
+
+    pub const fn vtable<P: Plugin + Send>(name: &'static str) -> PluginVTable {
+        PluginVTable {
+            abi_version: ABI_VERSION,
+            interface_version: Str::new(INTERFACE_VERSION),
+            name: Str::new(name),
+            create: plugin_create::<P>,
+            destroy: plugin_destroy::<P>,
+            handle_js_message: plugin_handle_js_message::<P>,
+            handle_external_message: plugin_handle_external_message::<P>,
+            handle_timer: plugin_handle_timer::<P>,
+            snapshot: plugin_snapshot::<P>,
+            restore: plugin_restore::<P>,
+            metadata: plugin_metadata::<P>,
+            ui: plugin_ui::<P>,
+        }
+    }
+
+    // 0.x versions must agree on the minor version too, later ones on the major
+    pub fn is_compatible(plugin_version: &str, host_version: &str) -> bool {
+        let significant = |version: &str| {
+            let mut parts = version.split('.');
+            match parts.next() {
+                Some("0") => format!("0.{}", parts.next().unwrap_or("")),
+                major => major.unwrap_or("").to_string(),
+            }
+        };
+        significant(plugin_version) == significant(host_version)
+    }
+
+    unsafe extern "C" fn plugin_create<P: Plugin + Send>() -> *mut c_void {
+        catch_unwind(|| Box::into_raw(Box::new(P::new())) as *mut c_void).unwrap_or(std::ptr::null_mut())
+    }
+
+    unsafe extern "C" fn plugin_destroy<P: Plugin + Send>(plugin: *mut c_void) {
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(plugin as *mut P))));
+    }
+
+    unsafe extern "C" fn plugin_handle_js_message<P: Plugin + Send>(plugin: *mut c_void, host: *const HostVTable, text: Str) -> bool {
+        let (plugin, host, text) = (&mut *(plugin as *mut P), Host(&*host), text.to_string());
+        catch_unwind(AssertUnwindSafe(|| plugin.handle_js_message(&host, text))).is_ok()
+    }
+
+    unsafe extern "C" fn plugin_handle_external_message<P: Plugin + Send>(plugin: *mut c_void, host: *const HostVTable, text: Str) -> bool {
+        let (plugin, host, text) = (&mut *(plugin as *mut P), Host(&*host), text.to_string());
+        catch_unwind(AssertUnwindSafe(|| plugin.handle_external_message(&host, text))).is_ok()
+    }
+
+    unsafe extern "C" fn plugin_handle_timer<P: Plugin + Send>(plugin: *mut c_void, host: *const HostVTable, name: Str) -> bool {
+        let (plugin, host, name) = (&mut *(plugin as *mut P), Host(&*host), name.to_string());
+        catch_unwind(AssertUnwindSafe(|| plugin.handle_timer(&host, &name))).is_ok()
+    }
+
+    unsafe extern "C" fn plugin_snapshot<P: Plugin + Send>(plugin: *mut c_void, out: *mut c_void, write: unsafe extern "C" fn(*mut c_void, Str)) {
+        let plugin = &*(plugin as *const P);
+        if let Ok(snapshot) = catch_unwind(AssertUnwindSafe(|| plugin.snapshot().to_string())) {
+            write(out, Str::new(&snapshot));
+        }
+    }
+
+    unsafe extern "C" fn plugin_restore<P: Plugin + Send>(plugin: *mut c_void, snapshot: Str) -> bool {
+        let plugin = &mut *(plugin as *mut P);
+        let snapshot = serde_json::from_str(&snapshot.to_string()).unwrap_or(serde_json::Value::Null);
+        catch_unwind(AssertUnwindSafe(|| plugin.restore(&snapshot))).is_ok()
+    }
+
+    unsafe extern "C" fn plugin_metadata<P: Plugin + Send>(plugin: *mut c_void, out: *mut c_void, write: unsafe extern "C" fn(*mut c_void, Str)) {
+        let plugin = &*(plugin as *const P);
+        if let Ok(Ok(metadata)) = catch_unwind(AssertUnwindSafe(|| serde_json::to_string(&plugin.metadata()))) {
+            write(out, Str::new(&metadata));
+        }
+    }
+
+    unsafe extern "C" fn plugin_ui<P: Plugin + Send>(plugin: *mut c_void, out: *mut c_void, write: unsafe extern "C" fn(*mut c_void, Str)) {
+        let plugin = &*(plugin as *const P);
+        if let Ok(Ok(ui)) = catch_unwind(AssertUnwindSafe(|| serde_json::to_string(&plugin.ui()))) {
+            write(out, Str::new(&ui));
+        }
+    }
+
+    #[macro_export]
+    macro_rules! export_plugin {
+        ($plugin:ty) => {
+            #[no_mangle]
+            pub extern "C" fn simulator_plugin() -> *const $crate::abi::PluginVTable {
+                static VTABLE: $crate::abi::PluginVTable = $crate::abi::vtable::<$plugin>(stringify!($plugin));
+                &VTABLE
+            }
+        };
+    }
 }
\ No newline at end of file
@@ -0,0 +1,87 @@
+use plugin_interface::interface_for_server::CommunicationInterface;
+use plugin_interface::protocol::DeviceStatus::{self, *};
+use plugin_interface::state_machine::{IllegalTransition, StateMachine};
+
+use serde_json::{json, Value};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct Sent {
+    js: Mutex<Vec<Value>>,
+    external: Mutex<Vec<Value>>,
+}
+
+impl CommunicationInterface for Sent {
+    fn send_to_js_clients(&self, message: Message) {
+        self.js.lock().unwrap().push(serde_json::from_str(message.to_text().unwrap()).unwrap());
+    }
+
+    fn send_to_external(&self, message: Message) {
+        self.external.lock().unwrap().push(serde_json::from_str(message.to_text().unwrap()).unwrap());
+    }
+}
+
+fn fire(machine: &mut StateMachine<DeviceStatus>, sent: &Sent, trigger: &str) -> Result<DeviceStatus, IllegalTransition> {
+    machine.fire(sent, trigger, &json!({ "action": trigger })).cloned()
+}
+
+#[test]
+fn transitions_send_the_new_status_to_both_sides() {
+    let sent = Sent::default();
+    let mut machine = StateMachine::device();
+
+    assert_eq!(fire(&mut machine, &sent, "enable"), Ok(Armed));
+
+    let status = json!({ "event": "statusChange", "status": "ARMED" });
+    assert_eq!(*sent.js.lock().unwrap(), vec![status.clone()]);
+    assert_eq!(*sent.external.lock().unwrap(), vec![status]);
+}
+
+#[test]
+fn illegal_transitions_are_reported_and_send_nothing() {
+    let sent = Sent::default();
+    let mut machine = StateMachine::device();
+    fire(&mut machine, &sent, "error").unwrap();
+    sent.js.lock().unwrap().clear();
+    sent.external.lock().unwrap().clear();
+
+    let error = fire(&mut machine, &sent, "enable").unwrap_err();
+
+    assert_eq!(error.to_string(), "'enable' is not allowed in state ERROR");
+    assert_eq!(*machine.state(), Error);
+    assert!(sent.js.lock().unwrap().is_empty());
+    assert!(sent.external.lock().unwrap().is_empty());
+}
+
+#[test]
+fn the_first_allowed_transition_wins() {
+    let sent = Sent::default();
+    let mut machine = StateMachine::device();
+
+    assert_eq!(fire(&mut machine, &sent, "error"), Ok(Error));
+    assert_eq!(fire(&mut machine, &sent, "error"), Ok(Disabled));
+}
+
+#[test]
+fn guards_look_at_the_triggering_message() {
+    let sent = Sent::default();
+    let mut machine = StateMachine::new(Disabled).guarded_transition("enable", [Disabled], Armed, |message| {
+        message.get("key").and_then(|v| v.as_str()) == Some("1234")
+    });
+
+    assert!(!machine.can_fire("enable", &json!({ "key": "0000" })));
+    assert!(machine.fire(&sent, "enable", &json!({ "key": "0000" })).is_err());
+    assert_eq!(machine.fire(&sent, "enable", &json!({ "key": "1234" })), Ok(&Armed));
+}
+
+#[test]
+fn states_can_be_strings() {
+    let sent = Sent::default();
+    let mut machine = StateMachine::new("IDLE".to_string()).transition("insert", ["IDLE".to_string()], "CARD_IN".to_string());
+
+    machine.fire(&sent, "insert", &Value::Null).unwrap();
+
+    assert_eq!(sent.external.lock().unwrap()[0], json!({ "event": "statusChange", "status": "CARD_IN" }));
+}
@@ -0,0 +1,45 @@
+use plugin_interface::actions::{Action, Field, Origin};
+use plugin_interface::metadata::{Metadata, DEVICE_EVENTS};
+use plugin_interface::ui::{Control, UiDescription};
+
+use serde_json::json;
+
+const VALUE: &[Field] = &[Field::new("value", "String", true)];
+const LIMIT: &[Field] = &[Field::new("value", "String", true), Field::new("limit", "u32", false)];
+
+fn metadata() -> Metadata {
+    Metadata {
+        model: "Acceptor".to_string(),
+        actions: vec![
+            Action::new(Origin::Js, "read", VALUE),
+            Action::new(Origin::Js, "reject", LIMIT),
+            Action::new(Origin::External, "enable", &[]),
+        ],
+        events: DEVICE_EVENTS.to_vec(),
+        ..Metadata::default()
+    }
+}
+
+#[test]
+fn every_ui_action_gets_a_button_and_its_fields_an_input() {
+    let ui = UiDescription::from_metadata(&metadata());
+
+    assert_eq!(ui.title, "Acceptor");
+    let types: Vec<_> = ui.controls.iter().map(|c| serde_json::to_value(c).unwrap()["type"].clone()).collect();
+    assert_eq!(types, ["status_badge", "text_input", "text_input", "button", "button", "event_log"]);
+    assert_eq!(serde_json::to_value(&ui.controls[4]).unwrap(), json!({
+        "type": "button",
+        "label": "reject",
+        "action": "reject",
+        "fields": ["value", "limit"],
+        "enabled_in": [],
+    }));
+}
+
+#[test]
+fn devices_without_status_get_no_badge() {
+    let ui = UiDescription::from_metadata(&Metadata::default());
+
+    assert_eq!(ui.title, "Simulator");
+    assert_eq!(ui.controls, [Control::event_log()]);
+}
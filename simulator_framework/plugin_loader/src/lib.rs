@@ -0,0 +1,304 @@
+// src/lib.rs
+//
+// Plugins built as a cdylib (see plugin_interface::abi) and loaded at startup from a
+// plugins directory, instead of being compiled into the simulator behind a feature.
+// The library is a thin crate of its own around the plugin, with
+//
+//     [lib]
+//     crate-type = ["cdylib"]
+//
+// and plugin_interface::export_plugin!(MyPlugin), see plugins/dynamic for the bundled
+// plugins. Libraries built for another ABI or an incompatible plugin_interface are
+// refused.
+//
+// DynamicPlugin is the Plugin the simulator runs: it forwards everything to the
+// library it was made with. Libraries are loaded from a copy, so the original can be
+// rebuilt while the simulator runs; with hot reload on, a rebuilt library replaces the
+// one in use.
+
+use plugin_interface::abi::{self, EntryPoint, HostVTable, PluginVTable, Str};
+use plugin_interface::interface_for_plugin::{modified_time, Plugin};
+use plugin_interface::interface_for_server::CommunicationInterface;
+use plugin_interface::metadata::Metadata;
+use plugin_interface::ui::UiDescription;
+
+use libloading::Library;
+use serde_json::Value;
+
+use std::ffi::c_void;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+pub struct PluginLibrary {
+    name: String,
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    vtable: &'static PluginVTable,
+    // keeps the code behind vtable loaded, None when it is linked into the binary
+    library: Option<Library>,
+    // the copy actually loaded, removed once unloaded
+    shadow: Option<PathBuf>,
+}
+
+impl PluginLibrary {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let error = |e: String| format!("{}: {}", path.display(), e);
+
+        let modified = modified_time(path);
+        let shadow = shadow_copy(path).map_err(|e| error(e.to_string()))?;
+        let loaded = Self::load_shadow(&shadow).and_then(|(library, vtable)| {
+            let mut plugin = Self::from_vtable(vtable)?;
+            plugin.library = Some(library);
+            Ok(plugin)
+        });
+
+        match loaded {
+            Ok(mut plugin) => {
+                plugin.path = Some(path.to_path_buf());
+                plugin.modified = modified;
+                plugin.shadow = Some(shadow);
+                Ok(plugin)
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&shadow);
+                Err(error(e))
+            }
+        }
+    }
+
+    fn load_shadow(shadow: &Path) -> Result<(Library, &'static PluginVTable), String> {
+        let library = unsafe { Library::new(shadow) }.map_err(|e| e.to_string())?;
+        let vtable = unsafe {
+            let entry_point = library
+                .get::<EntryPoint>(abi::ENTRY_POINT)
+                .map_err(|_| "not a simulator plugin".to_string())?;
+            &*entry_point()
+        };
+        Ok((library, vtable))
+    }
+
+    // A plugin whose table is already in the process
+    pub fn from_vtable(vtable: &'static PluginVTable) -> Result<Self, String> {
+        if vtable.abi_version != abi::ABI_VERSION {
+            return Err(format!(
+                "built for plugin ABI {}, the simulator uses {}",
+                vtable.abi_version,
+                abi::ABI_VERSION
+            ));
+        }
+
+        let interface_version = unsafe { vtable.interface_version.to_string() };
+        if !abi::is_compatible(&interface_version, abi::INTERFACE_VERSION) {
+            return Err(format!(
+                "built against plugin_interface {}, the simulator uses {}",
+                interface_version,
+                abi::INTERFACE_VERSION
+            ));
+        }
+
+        Ok(PluginLibrary {
+            name: unsafe { vtable.name.to_string() },
+            path: None,
+            modified: None,
+            vtable,
+            library: None,
+            shadow: None,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    // The file was rebuilt since it was loaded
+    fn is_stale(&self) -> bool {
+        self.path.as_deref().map(|path| modified_time(path) != self.modified).unwrap_or(false)
+    }
+}
+
+impl Drop for PluginLibrary {
+    fn drop(&mut self) {
+        // unloaded before its file goes away
+        drop(self.library.take());
+        if let Some(shadow) = &self.shadow {
+            let _ = fs::remove_file(shadow);
+        }
+    }
+}
+
+// Copies the library to a name of its own in the temp directory: the original stays
+// free to be overwritten (Windows locks loaded libraries), and a rebuilt library is not
+// mistaken for the one already loaded from the same path
+fn shadow_copy(path: &Path) -> std::io::Result<PathBuf> {
+    static COPIES: AtomicUsize = AtomicUsize::new(0);
+
+    let dir = std::env::temp_dir().join("simulator_plugins");
+    fs::create_dir_all(&dir)?;
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let shadow = dir.join(format!("{}-{}-{}", std::process::id(), COPIES.fetch_add(1, Ordering::Relaxed), file_name));
+    fs::copy(path, &shadow)?;
+    Ok(shadow)
+}
+
+// Every shared library in `dir`, loaded or with the reason it was refused
+pub fn discover(dir: impl AsRef<Path>) -> Vec<Result<PluginLibrary, String>> {
+    let dir = dir.as_ref();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return vec![Err(format!("{}: {}", dir.display(), e))],
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().map(|e| e == std::env::consts::DLL_EXTENSION).unwrap_or(false))
+        .collect();
+    paths.sort();
+
+    paths.into_iter().map(PluginLibrary::load).collect()
+}
+
+struct Instance {
+    library: Arc<PluginLibrary>,
+    plugin: *mut c_void,
+}
+
+pub struct DynamicPlugin {
+    // None when no library was given
+    library: Option<Arc<PluginLibrary>>,
+    // None as well when the library failed to create the plugin
+    instance: Option<Instance>,
+}
+
+// export_plugin! only accepts plugins that are Send
+unsafe impl Send for DynamicPlugin {}
+
+impl DynamicPlugin {
+    pub fn with_library(library: Arc<PluginLibrary>) -> Self {
+        let plugin = unsafe { (library.vtable.create)() };
+        if plugin.is_null() {
+            tracing::error!("Plugin {} failed to start", library.name);
+            return DynamicPlugin { library: Some(library), instance: None };
+        }
+        DynamicPlugin { library: Some(library.clone()), instance: Some(Instance { library, plugin }) }
+    }
+
+    pub fn library(&self) -> Option<&PluginLibrary> {
+        self.library.as_deref()
+    }
+
+    // The library again if it was rebuilt. A build that fails to load keeps the
+    // previous one.
+    fn refreshed_library(&self) -> Result<Arc<PluginLibrary>, String> {
+        let library = self.library.as_ref().ok_or("no plugin library selected")?;
+        let Some(path) = library.path().filter(|_| library.is_stale()) else { return Ok(library.clone()) };
+
+        let library = PluginLibrary::load(path)?;
+        tracing::info!("Plugin reloaded: {} ({})", library.name(), path.display());
+        Ok(Arc::new(library))
+    }
+
+    fn call<I: CommunicationInterface>(&mut self, interface: &I, what: &str, text: &str, select: fn(&PluginVTable) -> HandleFn) {
+        let Some(instance) = &self.instance else { return };
+
+        let host = HostVTable::new(interface);
+        let handle = select(instance.library.vtable);
+        if !unsafe { handle(instance.plugin, &host, Str::new(text)) } {
+            tracing::error!("Plugin {} panicked handling {}", instance.library.name, what);
+        }
+    }
+}
+
+type HandleFn = unsafe extern "C" fn(*mut c_void, *const HostVTable, Str) -> bool;
+
+unsafe extern "C" fn write_string(out: *mut c_void, text: Str) {
+    *(out as *mut String) = text.to_string();
+}
+
+impl Drop for DynamicPlugin {
+    fn drop(&mut self) {
+        if let Some(instance) = &self.instance {
+            unsafe { (instance.library.vtable.destroy)(instance.plugin) }
+        }
+    }
+}
+
+impl Plugin for DynamicPlugin {
+
+    // Without a library, see with_library
+    fn new() -> Self {
+        tracing::warn!("No plugin library selected, messages are ignored");
+        DynamicPlugin { library: None, instance: None }
+    }
+
+    fn handle_js_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
+        self.call(interface, "a js message", &text, |v| v.handle_js_message);
+    }
+
+    fn handle_external_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
+        self.call(interface, "an external message", &text, |v| v.handle_external_message);
+    }
+
+    fn handle_timer<I: CommunicationInterface>(&mut self, interface: &I, name: &str) {
+        self.call(interface, "a timer", name, |v| v.handle_timer);
+    }
+
+    fn snapshot(&self) -> Value {
+        let Some(instance) = &self.instance else { return Value::Null };
+
+        let mut snapshot = String::new();
+        unsafe {
+            (instance.library.vtable.snapshot)(instance.plugin, &mut snapshot as *mut String as *mut c_void, write_string)
+        };
+        serde_json::from_str(&snapshot).unwrap_or(Value::Null)
+    }
+
+    fn metadata(&self) -> Metadata {
+        let Some(instance) = &self.instance else { return Metadata::default() };
+
+        let mut metadata = String::new();
+        unsafe {
+            (instance.library.vtable.metadata)(instance.plugin, &mut metadata as *mut String as *mut c_void, write_string)
+        };
+        serde_json::from_str(&metadata).unwrap_or_default()
+    }
+
+    fn ui(&self) -> UiDescription {
+        let Some(instance) = &self.instance else { return UiDescription::from_metadata(&Metadata::default()) };
+
+        let mut ui = String::new();
+        unsafe { (instance.library.vtable.ui)(instance.plugin, &mut ui as *mut String as *mut c_void, write_string) };
+        serde_json::from_str(&ui).unwrap_or_else(|_| UiDescription::from_metadata(&self.metadata()))
+    }
+
+    fn artifact(&self) -> Option<PathBuf> {
+        self.library().and_then(|library| library.path()).map(Path::to_path_buf)
+    }
+
+    fn reloaded(&self) -> Result<Self, String> {
+        let library = self.refreshed_library()?;
+        let name = library.name.clone();
+        let plugin = DynamicPlugin::with_library(library);
+        if plugin.instance.is_none() {
+            return Err(format!("plugin {} failed to start", name));
+        }
+        Ok(plugin)
+    }
+
+    fn restore(&mut self, snapshot: &Value) {
+        let Some(instance) = &self.instance else { return };
+
+        let snapshot = snapshot.to_string();
+        if !unsafe { (instance.library.vtable.restore)(instance.plugin, Str::new(&snapshot)) } {
+            tracing::error!("Plugin {} panicked restoring its state", instance.library.name);
+        }
+    }
+
+}
@@ -0,0 +1,274 @@
+use bna_plugin::BNAPlugin;
+use plugin_interface::abi::{self, PluginVTable, Str};
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_interface::interface_for_server::{CommunicationInterface, Level};
+use plugin_loader::{discover, DynamicPlugin, PluginLibrary};
+use plugin_test_kit::{PluginHarness, RecordingInterface};
+
+use serde_json::{json, Value};
+
+use std::env::consts::{DLL_EXTENSION, DLL_PREFIX};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+static BNA: PluginVTable = abi::vtable::<BNAPlugin>("BNAPlugin");
+
+// The tests loading a library from disk need bna_dynamic, built beforehand with
+//
+//     cargo build --manifest-path plugins/dynamic/Cargo.toml
+//
+// and its path in BNA_PLUGIN_LIBRARY; without it they are skipped
+const LIBRARY_ENV: &str = "BNA_PLUGIN_LIBRARY";
+
+// A plugins directory of its own with a copy of the library named after the test
+fn plugins_dir(test: &str) -> Option<(PathBuf, PathBuf)> {
+    let Some(built) = std::env::var_os(LIBRARY_ENV) else {
+        eprintln!("{} skipped, {} is not set", test, LIBRARY_ENV);
+        return None;
+    };
+    let dir = std::env::temp_dir().join(format!("plugin_loader_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let library = dir.join(format!("{}{}.{}", DLL_PREFIX, test, DLL_EXTENSION));
+    fs::copy(built, &library).unwrap();
+    Some((dir, library))
+}
+
+fn bna() -> PluginHarness<DynamicPlugin> {
+    PluginHarness::with_plugin(DynamicPlugin::with_library(Arc::new(PluginLibrary::from_vtable(&BNA).unwrap())))
+}
+
+#[test]
+fn messages_and_replies_cross_the_boundary() {
+    let mut bna = bna();
+
+    bna.external(json!({ "action": "enable" }));
+    bna.expect_js_event("statusChange").with("status", "ARMED");
+    bna.expect_external_event("statusChange").with("status", "ARMED");
+
+    bna.js(json!({ "action": "read", "value": "20" }));
+    bna.expect_external_event("read").with("value", "20");
+    assert_eq!(bna.state()["value"], json!("20"));
+}
+
+#[test]
+fn timers_set_by_the_plugin_fire_through_the_host() {
+    let mut bna = bna();
+    bna.external(json!({ "action": "enable" }));
+    bna.js(json!({ "action": "read", "value": "50" })).clear();
+
+    bna.advance(Duration::from_millis(29_999));
+    bna.expect_no_more_messages();
+
+    bna.advance(Duration::from_millis(1));
+    bna.expect_external_event("returned").with("value", "50");
+}
+
+#[test]
+fn reload_restores_the_state_through_the_library() {
+    let mut bna = bna();
+    bna.external(json!({ "action": "enable" }));
+    bna.js(json!({ "action": "read", "value": "20" })).clear();
+    let before = bna.state();
+
+    bna.reload().unwrap();
+
+    assert_eq!(bna.state(), before);
+}
+
+#[test]
+fn metadata_crosses_the_boundary() {
+    let mut bna = bna();
+
+    bna.external(json!({ "action": "describe" }));
+
+    let describe = bna.expect_external_event("describe").with("device_type", "bna");
+    assert_eq!(describe.message()["actions"].as_array().unwrap().len(), BNAPlugin::ACTIONS.len());
+}
+
+#[test]
+fn plugin_logs_cross_the_boundary() {
+    let mut bna = bna();
+    bna.js(json!({ "action": "error" }));
+
+    // the host cannot enable a device in ERROR
+    bna.external(json!({ "action": "enable" }));
+
+    let logs = bna.logs();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].0, Level::WARN);
+    assert!(logs[0].1.starts_with("BNA: "), "{}", logs[0].1);
+}
+
+#[test]
+fn the_ui_description_crosses_the_boundary() {
+    let plugin = DynamicPlugin::with_library(Arc::new(PluginLibrary::from_vtable(&BNA).unwrap()));
+
+    assert_eq!(plugin.ui(), BNAPlugin::new().ui());
+}
+
+#[test]
+fn plugins_for_another_abi_are_refused() {
+    static OLD: PluginVTable = PluginVTable { abi_version: abi::ABI_VERSION + 1, ..abi::vtable::<BNAPlugin>("BNAPlugin") };
+
+    let error = PluginLibrary::from_vtable(&OLD).err().unwrap();
+
+    assert!(error.contains("plugin ABI"), "{}", error);
+}
+
+#[test]
+fn plugins_for_an_incompatible_interface_are_refused() {
+    static NEWER: PluginVTable = PluginVTable {
+        interface_version: Str::new("0.99.0"),
+        ..abi::vtable::<BNAPlugin>("BNAPlugin")
+    };
+
+    let error = PluginLibrary::from_vtable(&NEWER).err().unwrap();
+
+    assert!(error.contains("plugin_interface 0.99.0"), "{}", error);
+}
+
+#[test]
+fn interface_versions_follow_semver() {
+    assert!(abi::is_compatible("0.1.3", "0.1.0"));
+    assert!(!abi::is_compatible("0.2.0", "0.1.0"));
+    assert!(abi::is_compatible("1.4.0", "1.0.2"));
+    assert!(!abi::is_compatible("2.0.0", "1.0.0"));
+}
+
+struct Panicky;
+
+impl Plugin for Panicky {
+    fn new() -> Self {
+        Panicky
+    }
+
+    fn handle_js_message<I: CommunicationInterface>(&mut self, _interface: &I, _text: String) {
+        panic!("boom");
+    }
+
+    fn handle_external_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
+        interface.send_to_external(text.into());
+    }
+}
+
+#[test]
+fn a_panicking_plugin_keeps_running() {
+    static PANICKY: PluginVTable = abi::vtable::<Panicky>("Panicky");
+    let interface = RecordingInterface::new();
+    let mut plugin = DynamicPlugin::with_library(Arc::new(PluginLibrary::from_vtable(&PANICKY).unwrap()));
+
+    plugin.handle_js_message(&interface, "{}".to_string());
+    plugin.handle_external_message(&interface, "ping".to_string());
+
+    assert_eq!(interface.take().len(), 1);
+    assert_eq!(plugin.library().unwrap().name(), "Panicky");
+}
+
+#[test]
+fn a_library_built_with_the_dynamic_feature_is_loaded_from_disk() {
+    let Some((dir, path)) = plugins_dir("bna_load") else { return };
+    let interface = RecordingInterface::new();
+
+    let library = PluginLibrary::load(&path).unwrap();
+    assert_eq!(library.name(), "BNAPlugin");
+    assert_eq!(library.path(), Some(path.as_path()));
+
+    let mut plugin = DynamicPlugin::with_library(Arc::new(library));
+    plugin.handle_external_message(&interface, json!({ "action": "enable" }).to_string());
+
+    assert_eq!(plugin.snapshot()["status"], "ARMED");
+    let sent: Vec<Value> = interface.take().iter().map(|(_, text)| serde_json::from_str(text).unwrap()).collect();
+    assert!(sent.contains(&json!({ "event": "statusChange", "status": "ARMED" })), "{:?}", sent);
+    assert_eq!(plugin.metadata().device_type, "bna");
+    drop(plugin);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn libraries_are_loaded_from_a_copy_removed_on_unload() {
+    let Some((dir, path)) = plugins_dir("bna_shadow") else { return };
+    let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+    let copies = || -> Vec<PathBuf> {
+        let shadows = std::env::temp_dir().join("simulator_plugins");
+        let prefix = format!("{}-", std::process::id());
+        fs::read_dir(shadows)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|shadow| {
+                let name = shadow.file_name().unwrap().to_string_lossy();
+                name.starts_with(&prefix) && name.ends_with(&file_name)
+            })
+            .collect()
+    };
+
+    let plugin = DynamicPlugin::with_library(Arc::new(PluginLibrary::load(&path).unwrap()));
+    assert_eq!(copies().len(), 1);
+
+    // the original can go, or be rebuilt, while the plugin runs
+    fs::remove_file(&path).unwrap();
+    assert_eq!(plugin.metadata().device_type, "bna");
+
+    drop(plugin);
+    assert!(copies().is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn plugins_are_discovered_in_a_directory() {
+    let Some((dir, _)) = plugins_dir("bna_discover") else { return };
+    fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+    let found = discover(&dir);
+
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].as_ref().unwrap().name(), "BNAPlugin");
+    drop(found);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_rebuilt_library_replaces_the_one_in_use_on_reload() {
+    let Some((dir, path)) = plugins_dir("bna_rebuilt") else { return };
+    let interface = RecordingInterface::new();
+    let mut plugin = DynamicPlugin::with_library(Arc::new(PluginLibrary::load(&path).unwrap()));
+    plugin.handle_external_message(&interface, json!({ "action": "enable" }).to_string());
+
+    // as if it was built again
+    let rebuilt = SystemTime::now() + Duration::from_secs(10);
+    fs::File::options().write(true).open(&path).unwrap().set_modified(rebuilt).unwrap();
+    let mut reloaded = plugin.reloaded().unwrap();
+    reloaded.restore(&plugin.snapshot());
+
+    assert!(!std::ptr::eq(reloaded.library().unwrap(), plugin.library().unwrap()));
+    assert_eq!(reloaded.snapshot()["status"], "ARMED");
+    drop((plugin, reloaded));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_plugin_without_a_library_ignores_messages() {
+    let interface = RecordingInterface::new();
+    let mut plugin = DynamicPlugin::new();
+
+    plugin.handle_external_message(&interface, json!({ "action": "enable" }).to_string());
+
+    assert!(interface.take().is_empty());
+    assert!(plugin.reloaded().is_err());
+}
+
+#[test]
+fn files_that_are_not_plugins_are_reported() {
+    let dir = std::env::temp_dir().join(format!("plugin_loader_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join(format!("broken.{}", std::env::consts::DLL_EXTENSION)), "not a library").unwrap();
+    std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+    let found = discover(&dir);
+
+    assert_eq!(found.len(), 1);
+    assert!(found[0].as_ref().err().unwrap().contains("broken"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,236 @@
+// src/lib.rs
+//
+// #[plugin_actions] on an impl block turns the methods marked #[js_action("...")] or
+// #[external_action("...")] into the plugin's message handling:
+//
+//     #[plugin_actions]
+//     impl BNAPlugin {
+//         #[js_action("read")]
+//         fn read<I: CommunicationInterface>(&mut self, interface: &I, value: String) { ... }
+//
+//         #[external_action("enable")]
+//         fn enable<I: CommunicationInterface>(&mut self, interface: &I, message: &Value) { ... }
+//     }
+//
+//     impl Plugin for BNAPlugin {
+//         fn handle_js_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
+//             self.dispatch_js_message(interface, text);
+//         }
+//         ...
+//     }
+//
+// The first parameter after self is the interface. The others are taken from the
+// message field of the same name, Option<T> ones may be missing; a reference parameter
+// gets the whole message instead. Messages that are not JSON, name no known action or
+// lack a field are answered with {"event": "error", "message": ...} on the port they
+// came from. ACTIONS lists every action with its fields, see plugin_interface::actions.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, FnArg, ImplItem, ItemImpl, LitStr, Pat, Type};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Origin {
+    Js,
+    External,
+}
+
+enum Parameter {
+    Field { name: syn::Ident, ty: Box<Type>, required: bool },
+    Message,
+}
+
+struct Action {
+    origin: Origin,
+    name: LitStr,
+    method: syn::Ident,
+    parameters: Vec<Parameter>,
+}
+
+#[proc_macro_attribute]
+pub fn plugin_actions(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut block = parse_macro_input!(item as ItemImpl);
+    match collect_actions(&mut block) {
+        Ok(actions) => {
+            let generated = generate(&block, &actions);
+            quote!(#block #generated).into()
+        }
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+// Only meaningful inside a #[plugin_actions] impl, which removes them
+#[proc_macro_attribute]
+pub fn js_action(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    outside_plugin_actions("js_action", item)
+}
+
+#[proc_macro_attribute]
+pub fn external_action(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    outside_plugin_actions("external_action", item)
+}
+
+fn outside_plugin_actions(name: &str, item: TokenStream) -> TokenStream {
+    let item = TokenStream2::from(item);
+    let message = format!("#[{}] only works on methods of a #[plugin_actions] impl block", name);
+    let error = syn::Error::new(Span::call_site(), message).to_compile_error();
+    quote!(#error #item).into()
+}
+
+fn collect_actions(block: &mut ItemImpl) -> syn::Result<Vec<Action>> {
+    let mut actions: Vec<Action> = Vec::new();
+
+    for item in &mut block.items {
+        let ImplItem::Fn(method) = item else { continue };
+
+        let mut marker = None;
+        let mut kept = Vec::new();
+        for attr in method.attrs.drain(..) {
+            let origin = if attr.path().is_ident("js_action") {
+                Origin::Js
+            } else if attr.path().is_ident("external_action") {
+                Origin::External
+            } else {
+                kept.push(attr);
+                continue;
+            };
+            if marker.is_some() {
+                return Err(syn::Error::new(attr.span(), "a method handles a single action"));
+            }
+            marker = Some((origin, attr.parse_args::<LitStr>()?));
+        }
+        method.attrs = kept;
+
+        let Some((origin, name)) = marker else { continue };
+        if actions.iter().any(|a| a.origin == origin && a.name.value() == name.value()) {
+            return Err(syn::Error::new(name.span(), format!("action '{}' is handled twice", name.value())));
+        }
+
+        let mut inputs = method.sig.inputs.iter();
+        match inputs.next() {
+            Some(FnArg::Receiver(receiver)) if receiver.mutability.is_some() && receiver.reference.is_some() => (),
+            _ => return Err(syn::Error::new(method.sig.span(), "action methods take &mut self")),
+        }
+        if inputs.next().is_none() {
+            return Err(syn::Error::new(method.sig.span(), "action methods take the interface after self"));
+        }
+
+        let mut parameters = Vec::new();
+        for input in inputs {
+            let FnArg::Typed(typed) = input else { unreachable!() };
+            if let Type::Reference(_) = &*typed.ty {
+                parameters.push(Parameter::Message);
+                continue;
+            }
+            let Pat::Ident(pattern) = &*typed.pat else {
+                return Err(syn::Error::new(typed.pat.span(), "message fields are bound by name"));
+            };
+            parameters.push(Parameter::Field {
+                name: pattern.ident.clone(),
+                ty: typed.ty.clone(),
+                required: !is_option(&typed.ty),
+            });
+        }
+
+        actions.push(Action { origin, name, method: method.sig.ident.clone(), parameters });
+    }
+
+    Ok(actions)
+}
+
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path.path.segments.last().map(|s| s.ident == "Option").unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn generate(block: &ItemImpl, actions: &[Action]) -> TokenStream2 {
+    let self_ty = &block.self_ty;
+    let (impl_generics, _, where_clause) = block.generics.split_for_impl();
+
+    let catalogue = actions.iter().map(|action| {
+        let origin = match action.origin {
+            Origin::Js => quote!(::plugin_interface::actions::Origin::Js),
+            Origin::External => quote!(::plugin_interface::actions::Origin::External),
+        };
+        let name = &action.name;
+        let fields = action.parameters.iter().filter_map(|parameter| match parameter {
+            Parameter::Field { name, ty, required } => {
+                let name = name.to_string();
+                let ty = quote!(#ty).to_string().replace(' ', "");
+                Some(quote!(::plugin_interface::actions::Field::new(#name, #ty, #required)))
+            }
+            Parameter::Message => None,
+        });
+        // Field has a destructor, so the array needs a const of its own to be borrowed for 'static
+        quote!(::plugin_interface::actions::Action::new(#origin, #name, {
+            const FIELDS: &[::plugin_interface::actions::Field] = &[#(#fields),*];
+            FIELDS
+        }))
+    });
+
+    let js_dispatch = dispatch(actions, Origin::Js);
+    let external_dispatch = dispatch(actions, Origin::External);
+
+    quote! {
+        impl #impl_generics #self_ty #where_clause {
+            pub const ACTIONS: &'static [::plugin_interface::actions::Action] = &[#(#catalogue),*];
+
+            pub fn dispatch_js_message<I: ::plugin_interface::interface_for_server::CommunicationInterface>(&mut self, interface: &I, text: String) {
+                let reply = |error: String| interface.send_to_js_clients(::plugin_interface::actions::error_reply(&error));
+                #js_dispatch
+            }
+
+            pub fn dispatch_external_message<I: ::plugin_interface::interface_for_server::CommunicationInterface>(&mut self, interface: &I, text: String) {
+                let reply = |error: String| interface.send_to_external(::plugin_interface::actions::error_reply(&error));
+                #external_dispatch
+            }
+        }
+    }
+}
+
+fn dispatch(actions: &[Action], origin: Origin) -> TokenStream2 {
+    let arms = actions.iter().filter(|a| a.origin == origin).map(|action| {
+        let name = &action.name;
+        let method = &action.method;
+        let mut bindings = Vec::new();
+        let arguments: Vec<TokenStream2> = action
+            .parameters
+            .iter()
+            .map(|parameter| match parameter {
+                Parameter::Field { name, ty, .. } => {
+                    let field = name.to_string();
+                    bindings.push(quote! {
+                        let #name: #ty = match ::plugin_interface::actions::field(&message, #field) {
+                            Ok(value) => value,
+                            Err(e) => return reply(format!("{}: {}", action, e)),
+                        };
+                    });
+                    quote!(#name)
+                }
+                Parameter::Message => quote!(&message),
+            })
+            .collect();
+
+        quote! {
+            #name => {
+                #(#bindings)*
+                self.#method(interface, #(#arguments),*);
+            }
+        }
+    });
+
+    quote! {
+        let (action, message) = match ::plugin_interface::actions::parse_action(&text) {
+            Ok(parsed) => parsed,
+            Err(e) => return reply(e),
+        };
+        match action.as_str() {
+            #(#arms)*
+            _ => reply(format!("unknown action '{}'", action)),
+        }
+    }
+}
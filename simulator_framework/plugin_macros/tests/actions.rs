@@ -0,0 +1,101 @@
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_interface::interface_for_server::CommunicationInterface;
+use plugin_interface::protocol;
+use plugin_macros::plugin_actions;
+use plugin_test_kit::PluginHarness;
+
+use serde_json::{json, Value};
+
+// answers every action on the port it came from
+struct Echo;
+
+#[plugin_actions]
+impl Echo {
+    #[js_action("add")]
+    fn add<I: CommunicationInterface>(&mut self, interface: &I, a: i64, b: Option<i64>) {
+        let sum = a + b.unwrap_or(0);
+        interface.send_to_js_clients(protocol::to_message(&json!({ "event": "sum", "value": sum })));
+    }
+
+    #[external_action("echo")]
+    fn echo<I: CommunicationInterface>(&mut self, interface: &I, message: &Value) {
+        interface.send_to_external(protocol::to_message(message));
+    }
+}
+
+impl Plugin for Echo {
+    fn new() -> Self {
+        Echo
+    }
+
+    fn handle_js_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
+        self.dispatch_js_message(interface, text);
+    }
+
+    fn handle_external_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
+        self.dispatch_external_message(interface, text);
+    }
+}
+
+#[test]
+fn fields_are_passed_by_name() {
+    let mut echo = PluginHarness::<Echo>::new();
+
+    echo.js(json!({ "action": "add", "a": 2, "b": 3 }));
+    echo.js(json!({ "action": "add", "a": 2 }));
+
+    echo.expect_js_event("sum").with("value", 5);
+    echo.expect_js_event("sum").with("value", 2);
+    echo.expect_no_more_messages();
+}
+
+#[test]
+fn a_reference_parameter_gets_the_whole_message() {
+    let mut echo = PluginHarness::<Echo>::new();
+
+    echo.external(json!({ "action": "echo", "extra": [1, 2] }));
+
+    echo.expect_external(json!({ "action": "echo", "extra": [1, 2] }));
+    echo.expect_no_more_messages();
+}
+
+#[test]
+fn bad_fields_are_answered_with_an_error() {
+    let mut echo = PluginHarness::<Echo>::new();
+
+    echo.js(json!({ "action": "add", "b": 3 }));
+    echo.js(json!({ "action": "add", "a": "two" }));
+
+    echo.expect_js(json!({ "event": "error", "message": "add: missing field 'a'" }));
+    echo.expect_js_event("error");
+    echo.expect_no_more_messages();
+}
+
+#[test]
+fn actions_are_only_dispatched_from_their_port() {
+    let mut echo = PluginHarness::<Echo>::new();
+
+    echo.external(json!({ "action": "add", "a": 1 }));
+    echo.js(json!({ "action": "echo" }));
+
+    echo.expect_external(json!({ "event": "error", "message": "unknown action 'add'" }));
+    echo.expect_js(json!({ "event": "error", "message": "unknown action 'echo'" }));
+    echo.expect_no_more_messages();
+}
+
+#[test]
+fn the_catalogue_lists_fields() {
+    let actions = serde_json::to_value(Echo::ACTIONS).unwrap();
+
+    assert_eq!(actions, json!([
+        {
+            "origin": "js",
+            "name": "add",
+            "fields": [
+                { "name": "a", "type": "i64", "required": true },
+                { "name": "b", "type": "Option<i64>", "required": false },
+            ],
+        },
+        { "origin": "external", "name": "echo", "fields": [] },
+    ]));
+}
@@ -0,0 +1,68 @@
+// src/capture.rs
+//
+// A CommunicationInterface that keeps everything a plugin sends, logs and counts, instead
+// of forwarding it to a socket. Used to drive plugins without a running server.
+
+use plugin_interface::interface_for_server::{CommunicationInterface, Level};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use std::sync::Mutex;
+
+use crate::metrics::Metrics;
+use crate::recorder::Port;
+
+#[derive(Default)]
+pub struct CapturingInterface {
+    messages: Mutex<Vec<(Port, String)>>,
+    logs: Mutex<Vec<(Level, String)>>,
+    metrics: Metrics,
+}
+
+impl CapturingInterface {
+    pub fn new() -> Self {
+        CapturingInterface::default()
+    }
+
+    // Returns the messages captured so far and clears the buffer
+    pub fn take(&self) -> Vec<(Port, String)> {
+        std::mem::take(&mut *self.messages.lock().unwrap())
+    }
+
+    // Same for what the plugin logged
+    pub fn take_logs(&self) -> Vec<(Level, String)> {
+        std::mem::take(&mut *self.logs.lock().unwrap())
+    }
+
+    // The plugin's metrics, kept as the simulator keeps them
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    fn push(&self, port: Port, message: Message) {
+        if let Message::Text(text) = message {
+            self.messages.lock().unwrap().push((port, text));
+        }
+    }
+}
+
+impl CommunicationInterface for CapturingInterface {
+    fn send_to_js_clients(&self, message: Message) {
+        self.push(Port::Js, message);
+    }
+
+    fn send_to_external(&self, message: Message) {
+        self.push(Port::External, message);
+    }
+
+    fn log(&self, level: Level, message: &str) {
+        self.logs.lock().unwrap().push((level, message.to_string()));
+    }
+
+    fn count(&self, name: &str, labels: &[(&str, &str)], by: f64) {
+        self.metrics.increment(name, labels, by);
+    }
+
+    fn gauge(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.metrics.set(name, labels, value);
+    }
+}
@@ -0,0 +1,202 @@
+// src/fault.rs
+//
+// Fault injection: the simulator can behave like a slow, lossy or misbehaving device.
+// A FaultProfile describes what can go wrong, the FaultInjector decides, message by
+// message, what actually goes wrong, and a link applies it to a stream of messages:
+//
+//     let to_socket = fault::link(faults.clone(), socket_sender);
+//     to_socket.send(message)?; // comes out of socket_sender late, twice, or never
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// a message held back to be reordered goes out anyway after this long, if no other
+// message comes along to overtake it
+pub const REORDER_TIMEOUT: Duration = Duration::from_millis(200);
+
+// All rates are probabilities between 0.0 and 1.0, the default profile injects nothing
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FaultProfile {
+    // delay added to every message, plus a random 0..jitter_ms; each message waits on
+    // its own, so with jitter a message can overtake the ones sent before it even
+    // with no reorder_rate
+    pub latency_ms: u64,
+    pub jitter_ms: u64,
+    pub drop_rate: f64,
+    pub duplicate_rate: f64,
+    // a reordered message is held back and delivered after the next one, see REORDER_TIMEOUT
+    pub reorder_rate: f64,
+    pub corrupt_rate: f64,
+    // close the connection once this many messages went through it, in either direction
+    pub disconnect_after: Option<u64>,
+    // chance of closing the connection at each message
+    pub disconnect_rate: f64,
+    // makes the random decisions reproducible
+    pub seed: Option<u64>,
+}
+
+struct FaultState {
+    name: String,
+    profile: FaultProfile,
+    rng: StdRng,
+}
+
+// What becomes of one message
+pub struct Plan {
+    pub delay: Duration,
+    // empty when the message was dropped
+    pub messages: Vec<Message>,
+    // the last of the messages is to be held back and delivered after the next one
+    pub reorder: bool,
+}
+
+pub struct FaultInjector {
+    state: Mutex<FaultState>,
+}
+
+impl Default for FaultInjector {
+    fn default() -> Self {
+        FaultInjector::new("none", FaultProfile::default())
+    }
+}
+
+impl FaultInjector {
+    pub fn new(name: &str, profile: FaultProfile) -> Self {
+        let rng = Self::rng_for(&profile);
+        FaultInjector {
+            state: Mutex::new(FaultState { name: name.to_string(), profile, rng }),
+        }
+    }
+
+    fn rng_for(profile: &FaultProfile) -> StdRng {
+        match profile.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        }
+    }
+
+    pub fn set_profile(&self, name: &str, profile: FaultProfile) {
+        let mut state = self.state.lock().unwrap();
+        state.rng = Self::rng_for(&profile);
+        state.name = name.to_string();
+        state.profile = profile;
+    }
+
+    pub fn profile(&self) -> (String, FaultProfile) {
+        let state = self.state.lock().unwrap();
+        (state.name.clone(), state.profile.clone())
+    }
+
+    // What to deliver in place of `message`, in order, after the delay
+    pub fn plan(&self, message: Message) -> Plan {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let profile = &state.profile;
+        let rng = &mut state.rng;
+
+        let delay = Duration::from_millis(profile.latency_ms + rng.random_range(0..=profile.jitter_ms));
+        if rng.random::<f64>() < profile.drop_rate {
+            return Plan { delay, messages: Vec::new(), reorder: false };
+        }
+
+        let message = if rng.random::<f64>() < profile.corrupt_rate { corrupt(message, rng) } else { message };
+
+        let mut messages = vec![message.clone()];
+        if rng.random::<f64>() < profile.duplicate_rate {
+            messages.push(message);
+        }
+
+        let reorder = rng.random::<f64>() < profile.reorder_rate;
+        Plan { delay, messages, reorder }
+    }
+
+    pub fn should_disconnect(&self, messages_on_connection: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let reached_limit = state.profile.disconnect_after.is_some_and(|n| messages_on_connection >= n);
+        let rate = state.profile.disconnect_rate;
+        reached_limit || state.rng.random::<f64>() < rate
+    }
+}
+
+// One direction of one connection: what is sent into the returned sender comes out of
+// `output` with the faults applied, each message after a delay of its own. The link
+// ends when the returned sender is dropped.
+pub fn link(faults: Arc<FaultInjector>, output: UnboundedSender<Message>) -> UnboundedSender<Message> {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut held: Option<(Duration, Message)> = None;
+        loop {
+            let message = match held {
+                Some(_) => match tokio::time::timeout(REORDER_TIMEOUT, receiver.recv()).await {
+                    Ok(message) => message,
+                    Err(_) => {
+                        // nothing came along to overtake it
+                        let (delay, message) = held.take().unwrap();
+                        deliver(&output, delay, vec![message]);
+                        continue;
+                    }
+                },
+                None => receiver.recv().await,
+            };
+            let Some(message) = message else {
+                // closed, whatever was held back still goes out
+                if let Some((delay, message)) = held.take() {
+                    deliver(&output, delay, vec![message]);
+                }
+                break;
+            };
+
+            let mut plan = faults.plan(message);
+            if plan.messages.is_empty() {
+                continue;
+            }
+            if let Some((_, message)) = held.take() {
+                plan.messages.push(message);
+            } else if plan.reorder {
+                held = plan.messages.pop().map(|message| (plan.delay, message));
+            }
+            deliver(&output, plan.delay, plan.messages);
+        }
+    });
+    sender
+}
+
+// in order, right away or from a task of their own, so that later messages with a
+// shorter delay can overtake them
+fn deliver(output: &UnboundedSender<Message>, delay: Duration, messages: Vec<Message>) {
+    if delay.is_zero() {
+        for message in messages {
+            let _ = output.send(message);
+        }
+        return;
+    }
+
+    let output = output.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        for message in messages {
+            let _ = output.send(message);
+        }
+    });
+}
+
+// Cuts the JSON text short and appends garbage, so it no longer parses
+fn corrupt(message: Message, rng: &mut StdRng) -> Message {
+    match message {
+        Message::Text(text) => {
+            let chars: Vec<char> = text.chars().collect();
+            let keep = if chars.len() > 1 { rng.random_range(1..chars.len()) } else { 0 };
+            let mut corrupted: String = chars[..keep].iter().collect();
+            corrupted.push_str("\u{0}#!");
+            Message::Text(corrupted)
+        }
+        other => other,
+    }
+}
@@ -1,36 +1,307 @@
 // src/plugin_manager.rs
-use plugin_interface::interface_for_server::CommunicationInterface;
+use plugin_interface::interface_for_server::{CommunicationInterface, Level};
 use plugin_interface::interface_for_plugin::Plugin;
+use plugin_interface::clock::{Clock, RealClock, VirtualClock};
+use plugin_interface::actions;
+use plugin_interface::metadata::{self, Metadata};
+use plugin_interface::protocol::{self, DeviceEvent};
+use plugin_interface::ui::UiDescription;
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use serde_json::Value;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub mod capture;
+pub mod fault;
+pub mod metrics;
+pub mod recorder;
+pub mod replay;
+pub mod schema;
+
+use recorder::Port;
+use schema::InboundValidator;
+
+// Timers set by the plugin, sorted by deadline
+#[derive(Default)]
+struct Timers {
+    pending: Vec<(Duration, String)>,
+}
+
+impl Timers {
+    fn set(&mut self, name: &str, deadline: Duration) {
+        self.cancel(name);
+        let position = self.pending.partition_point(|(d, _)| *d <= deadline);
+        self.pending.insert(position, (deadline, name.to_string()));
+    }
+
+    fn cancel(&mut self, name: &str) {
+        self.pending.retain(|(_, n)| n != name);
+    }
+
+    fn next_deadline(&self) -> Option<Duration> {
+        self.pending.first().map(|(d, _)| *d)
+    }
+
+    fn pop_due(&mut self, now: Duration) -> Option<String> {
+        match self.pending.first() {
+            Some((deadline, _)) if *deadline <= now => Some(self.pending.remove(0).1),
+            _ => None,
+        }
+    }
+}
+
+// The host's trace, carried by the optional W3C traceparent of its messages, e.g.
+// {"action": "enable", "traceparent": "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"}
+fn host_trace(message: &str) -> Option<opentelemetry::Context> {
+    let message: Value = serde_json::from_str(message).ok()?;
+    let traceparent = message.get("traceparent")?.as_str()?;
+
+    let carrier = HashMap::from([("traceparent".to_string(), traceparent.to_string())]);
+    let context = TraceContextPropagator::new().extract(&carrier);
+    if !context.span().span_context().is_valid() {
+        tracing::debug!(%traceparent, "Invalid traceparent ignored");
+        return None;
+    }
+    Some(context)
+}
+
+// The interface handed to the plugin: messages go to the server's interface,
+// time and timers come from the plugin manager
+struct PluginContext<'a, I: CommunicationInterface> {
+    interface: &'a I,
+    clock: &'a dyn Clock,
+    timers: &'a Mutex<Timers>,
+}
+
+impl<I: CommunicationInterface> CommunicationInterface for PluginContext<'_, I> {
+    fn send_to_js_clients(&self, message: Message) {
+        self.interface.send_to_js_clients(message);
+    }
+
+    fn send_to_external(&self, message: Message) {
+        self.interface.send_to_external(message);
+    }
+
+    fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    fn set_timer(&self, name: &str, delay: Duration) {
+        self.timers.lock().unwrap().set(name, self.clock.now() + delay);
+    }
+
+    fn cancel_timer(&self, name: &str) {
+        self.timers.lock().unwrap().cancel(name);
+    }
+
+    fn log(&self, level: Level, message: &str) {
+        self.interface.log(level, message);
+    }
+
+    fn count(&self, name: &str, labels: &[(&str, &str)], by: f64) {
+        self.interface.count(name, labels, by);
+    }
+
+    fn gauge(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.interface.gauge(name, labels, value);
+    }
+}
 
 pub struct PluginManager<I: CommunicationInterface, P: Plugin> {
     plugin: Mutex<P>,
     communication_interface: Arc<I>,
+    clock: Arc<dyn Clock>,
+    // set when the manager runs on a virtual clock, which only advance() moves
+    virtual_clock: Option<Arc<VirtualClock>>,
+    timers: Mutex<Timers>,
+    // checks inbound messages against the plugin's metadata, see schema.rs; built when
+    // validation is turned on and again when the plugin is reloaded
+    inbound_validation: bool,
+    validator: Mutex<Option<InboundValidator>>,
 }
 
 impl<I: CommunicationInterface, P: Plugin> PluginManager<I, P> {
     pub fn new(communication_interface: Arc<I>) -> Self {
-        let plugin = P::new();
+        Self::from_plugin(communication_interface, P::new())
+    }
 
+    // Runs a plugin built by the caller instead of P::new(), on the real clock
+    pub fn from_plugin(communication_interface: Arc<I>, plugin: P) -> Self {
+        Self::with_clock(communication_interface, plugin, Arc::new(RealClock::new()), None)
+    }
+
+    pub fn with_virtual_clock(communication_interface: Arc<I>, clock: Arc<VirtualClock>) -> Self {
+        Self::with_clock(communication_interface, P::new(), clock.clone(), Some(clock))
+    }
+
+    // Runs a plugin built by the caller instead of P::new(), on a virtual clock
+    pub fn with_plugin(communication_interface: Arc<I>, plugin: P, clock: Arc<VirtualClock>) -> Self {
+        Self::with_clock(communication_interface, plugin, clock.clone(), Some(clock))
+    }
+
+    fn with_clock(communication_interface: Arc<I>, plugin: P, clock: Arc<dyn Clock>, virtual_clock: Option<Arc<VirtualClock>>) -> Self {
         PluginManager {
             plugin: Mutex::new(plugin),
             communication_interface,
+            clock,
+            virtual_clock,
+            timers: Mutex::new(Timers::default()),
+            inbound_validation: false,
+            validator: Mutex::new(None),
+        }
+    }
+
+    // Messages that do not match the plugin's metadata are answered with an error
+    // instead of reaching the plugin
+    pub fn set_inbound_validation(&mut self, enabled: bool) {
+        self.inbound_validation = enabled;
+        let metadata = self.plugin.get_mut().unwrap().metadata();
+        self.build_validator(&metadata);
+    }
+
+    fn build_validator(&self, metadata: &Metadata) {
+        let validator = if self.inbound_validation {
+            InboundValidator::new(metadata)
+                .map_err(|e| tracing::warn!("Inbound messages not validated, the metadata has no valid schema: {}", e))
+                .ok()
+        } else {
+            None
+        };
+        *self.validator.lock().unwrap() = validator;
+    }
+
+    fn is_valid(&self, port: Port, message: &str) -> bool {
+        let validator = self.validator.lock().unwrap();
+        let Some(validator) = validator.as_ref() else { return true };
+
+        let Err(e) = validator.validate(port, message) else { return true };
+        tracing::info!("Rejected {:?} message {}: {}", port, message, e);
+        match port {
+            Port::Js => self.communication_interface.send_to_js_clients(actions::error_reply(&e)),
+            Port::External => self.communication_interface.send_to_external(actions::error_reply(&e)),
+        }
+        false
+    }
+
+    fn context(&self) -> PluginContext<'_, I> {
+        PluginContext {
+            interface: &*self.communication_interface,
+            clock: &*self.clock,
+            timers: &self.timers,
         }
     }
 
     pub fn handle_js_message(&self, message: String) 
     {
         // Your synchronous code here
+        let _span = tracing::info_span!("plugin_message", port = "js").entered();
+        tracing::debug!(%message, "handling");
         let mut plugin = self.plugin.lock().unwrap();
-        plugin.handle_js_message(&*self.communication_interface, message);
+        if !self.is_valid(Port::Js, &message) {
+            return;
+        }
+        plugin.handle_js_message(&self.context(), message);
     }
 
     pub fn handle_external_message(&self, message: String) 
     {
         // Your synchronous code here
+        let span = tracing::info_span!("plugin_message", port = "external", otel.kind = "server");
+        // when spans are exported the handling shows up in the host's trace
+        if let Some(context) = host_trace(&message) {
+            let _ = span.set_parent(context);
+        }
+        let _span = span.entered();
+        tracing::debug!(%message, "handling");
+        let mut plugin = self.plugin.lock().unwrap();
+        // answered here for every plugin, see Plugin::metadata
+        if metadata::is_describe(&message) {
+            let describe = DeviceEvent::Describe(plugin.metadata());
+            self.communication_interface.send_to_external(protocol::to_message(&describe));
+            return;
+        }
+        if !self.is_valid(Port::External, &message) {
+            return;
+        }
+        plugin.handle_external_message(&self.context(), message);
+    }
+
+    pub fn snapshot(&self) -> Value {
+        self.plugin.lock().unwrap().snapshot()
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        self.plugin.lock().unwrap().metadata()
+    }
+
+    pub fn ui(&self) -> UiDescription {
+        self.plugin.lock().unwrap().ui()
+    }
+
+    // The file to watch for hot reload, see Plugin::artifact
+    pub fn artifact(&self) -> Option<PathBuf> {
+        self.plugin.lock().unwrap().artifact()
+    }
+
+    // Replaces the plugin with a new instance restored from the old one's snapshot.
+    // Timers set by the old instance stay pending and fire on the new one. When the
+    // new instance does not load the old one stays, see Plugin::reloaded.
+    pub fn reload(&self) -> Result<(), String> {
         let mut plugin = self.plugin.lock().unwrap();
-        plugin.handle_external_message(&*self.communication_interface, message);
+        let mut reloaded = plugin.reloaded()?;
+        reloaded.restore(&plugin.snapshot());
+        *plugin = reloaded;
+        // the new instance may understand other messages
+        self.build_validator(&plugin.metadata());
+        Ok(())
+    }
+
+    pub fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    pub fn next_timer_deadline(&self) -> Option<Duration> {
+        self.timers.lock().unwrap().next_deadline()
+    }
+
+    // Calls handle_timer for every timer whose deadline has passed, earliest first
+    pub fn fire_due_timers(&self) {
+        loop {
+            let due = self.timers.lock().unwrap().pop_due(self.clock.now());
+            match due {
+                Some(name) => {
+                    let _span = tracing::info_span!("plugin_timer", timer = %name).entered();
+                    let mut plugin = self.plugin.lock().unwrap();
+                    plugin.handle_timer(&self.context(), &name);
+                }
+                None => break,
+            }
+        }
+    }
+
+    // Moves a virtual clock forward, stopping at every timer deadline on the way so
+    // that each timer fires at its own time. With a real clock it only fires the
+    // timers that are already due.
+    pub fn advance(&self, by: Duration) {
+        let Some(clock) = &self.virtual_clock else {
+            self.fire_due_timers();
+            return;
+        };
+
+        let target = clock.now() + by;
+        while let Some(deadline) = self.next_timer_deadline().filter(|d| *d <= target) {
+            clock.advance_to(deadline);
+            self.fire_due_timers();
+        }
+        clock.advance_to(target);
+        self.fire_due_timers();
     }
     
 }
@@ -0,0 +1,222 @@
+// src/metrics.rs
+//
+// A registry of counters, gauges and histograms, rendered in the Prometheus text
+// format. Metrics are registered the first time they are used; describe() gives them
+// a help text. Labels tell the series of a metric apart:
+//
+//     metrics.increment("bna_reads_total", &[("denomination", "20")], 1.0);
+//
+//     # TYPE bna_reads_total counter
+//     bna_reads_total{denomination="20"} 1
+//
+// Invalid names, negative increments and a name used with two kinds are logged and
+// ignored.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+// upper bounds, in seconds, of the histogram buckets: message handlers take from
+// microseconds to a few milliseconds
+pub const BUCKETS: &[f64] = &[0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl Kind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+            Kind::Histogram => "histogram",
+        }
+    }
+}
+
+// label names and values, sorted by name
+type Labels = Vec<(String, String)>;
+
+enum Series {
+    Value(f64),
+    // counts per bucket, not cumulative
+    Histogram { buckets: Vec<u64>, sum: f64, count: u64 },
+}
+
+struct Family {
+    kind: Kind,
+    help: Option<String>,
+    series: BTreeMap<Labels, Series>,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    families: Mutex<BTreeMap<String, Family>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    pub fn describe(&self, name: &str, kind: Kind, help: &str) {
+        let mut families = self.families.lock().unwrap();
+        if let Some(family) = family(&mut families, name, kind) {
+            family.help = Some(help.to_string());
+        }
+    }
+
+    // Counters only go up
+    pub fn increment(&self, name: &str, labels: &[(&str, &str)], by: f64) {
+        if by < 0.0 || by.is_nan() {
+            tracing::warn!("Metric {} ignored, counters cannot go down ({})", name, by);
+            return;
+        }
+        self.update(name, Kind::Counter, labels, |series| {
+            if let Series::Value(value) = series {
+                *value += by;
+            }
+        });
+    }
+
+    pub fn set(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.update(name, Kind::Gauge, labels, |series| {
+            if let Series::Value(current) = series {
+                *current = value;
+            }
+        });
+    }
+
+    // Counts the value in the first of BUCKETS it fits in
+    pub fn observe(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.update(name, Kind::Histogram, labels, |series| {
+            if let Series::Histogram { buckets, sum, count } = series {
+                if let Some(bucket) = BUCKETS.iter().position(|bound| value <= *bound) {
+                    buckets[bucket] += 1;
+                }
+                *sum += value;
+                *count += 1;
+            }
+        });
+    }
+
+    // The current value of a counter or gauge series
+    pub fn value(&self, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        let families = self.families.lock().unwrap();
+        match families.get(name)?.series.get(&sorted(labels))? {
+            Series::Value(value) => Some(*value),
+            Series::Histogram { .. } => None,
+        }
+    }
+
+    fn update(&self, name: &str, kind: Kind, labels: &[(&str, &str)], change: impl FnOnce(&mut Series)) {
+        if let Some((label, _)) = labels.iter().find(|(label, _)| !is_valid_name(label, false) || label.starts_with("__")) {
+            tracing::warn!("Metric {} ignored, invalid label name '{}'", name, label);
+            return;
+        }
+
+        let mut families = self.families.lock().unwrap();
+        let Some(family) = family(&mut families, name, kind) else { return };
+        let series = family.series.entry(sorted(labels)).or_insert_with(|| match kind {
+            Kind::Histogram => Series::Histogram { buckets: vec![0; BUCKETS.len()], sum: 0.0, count: 0 },
+            _ => Series::Value(0.0),
+        });
+        change(series);
+    }
+
+    // The text format, see https://prometheus.io/docs/instrumenting/exposition_formats/
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut text = String::new();
+        for (name, family) in families.iter() {
+            if let Some(help) = &family.help {
+                let _ = writeln!(text, "# HELP {} {}", name, help.replace('\\', "\\\\").replace('\n', "\\n"));
+            }
+            let _ = writeln!(text, "# TYPE {} {}", name, family.kind.as_str());
+
+            for (labels, series) in &family.series {
+                match series {
+                    Series::Value(value) => {
+                        let _ = writeln!(text, "{}{} {}", name, render_labels(labels, None), number(*value));
+                    }
+                    Series::Histogram { buckets, sum, count } => {
+                        let mut cumulative = 0;
+                        for (bound, in_bucket) in BUCKETS.iter().zip(buckets) {
+                            cumulative += in_bucket;
+                            let le = number(*bound);
+                            let _ = writeln!(text, "{}_bucket{} {}", name, render_labels(labels, Some(&le)), cumulative);
+                        }
+                        let _ = writeln!(text, "{}_bucket{} {}", name, render_labels(labels, Some("+Inf")), count);
+                        let _ = writeln!(text, "{}_sum{} {}", name, render_labels(labels, None), number(*sum));
+                        let _ = writeln!(text, "{}_count{} {}", name, render_labels(labels, None), count);
+                    }
+                }
+            }
+        }
+        text
+    }
+}
+
+// The family called `name`, registered as `kind` if it is new; None if the name is
+// not valid or is already used by another kind
+fn family<'a>(families: &'a mut BTreeMap<String, Family>, name: &str, kind: Kind) -> Option<&'a mut Family> {
+    if !is_valid_name(name, true) {
+        tracing::warn!("Metric ignored, invalid name '{}'", name);
+        return None;
+    }
+
+    let family = families
+        .entry(name.to_string())
+        .or_insert_with(|| Family { kind, help: None, series: BTreeMap::new() });
+    if family.kind != kind {
+        tracing::warn!("Metric {} ignored, it is a {} and not a {}", name, family.kind.as_str(), kind.as_str());
+        return None;
+    }
+    Some(family)
+}
+
+// [a-zA-Z_:][a-zA-Z0-9_:]* for metrics, the same without colons for labels
+fn is_valid_name(name: &str, metric: bool) -> bool {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || c == '_' || (metric && c == ':');
+    match name.chars().next() {
+        Some(first) => !first.is_ascii_digit() && name.chars().all(allowed),
+        None => false,
+    }
+}
+
+fn sorted(labels: &[(&str, &str)]) -> Labels {
+    let mut labels: Labels = labels.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+    labels.sort();
+    labels
+}
+
+fn render_labels(labels: &Labels, le: Option<&str>) -> String {
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .chain(le.map(|le| ("le", le)))
+        .map(|(name, value)| {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+fn number(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
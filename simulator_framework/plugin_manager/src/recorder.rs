@@ -0,0 +1,154 @@
+// src/recorder.rs
+//
+// Session recording: every message that goes in or out of the js and external
+// ports is appended to a JSON Lines trace file, one record per line.
+
+use serde::{Deserialize, Serialize};
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Inbound => "inbound",
+            Direction::Outbound => "outbound",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Port {
+    Js,
+    External,
+}
+
+impl Port {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Port::Js => "js",
+            Port::External => "external",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TraceRecord {
+    // milliseconds since the unix epoch
+    pub timestamp_ms: u64,
+    pub direction: Direction,
+    pub port: Port,
+    pub client_id: u64,
+    pub message: String,
+}
+
+impl TraceRecord {
+    pub fn now(direction: Direction, port: Port, client_id: u64, message: String) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        TraceRecord { timestamp_ms, direction, port, client_id, message }
+    }
+}
+
+pub struct SessionRecorder {
+    path: PathBuf,
+    enabled: AtomicBool,
+    writer: Mutex<Option<BufWriter<File>>>,
+}
+
+impl SessionRecorder {
+    pub fn new(path: impl Into<PathBuf>, enabled: bool) -> Self {
+        let recorder = SessionRecorder {
+            path: path.into(),
+            enabled: AtomicBool::new(false),
+            writer: Mutex::new(None),
+        };
+        recorder.set_enabled(enabled);
+        recorder
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    // Recording can be switched on and off at runtime; the trace file is opened in
+    // append mode, so a session toggled several times ends up in a single file.
+    pub fn set_enabled(&self, enabled: bool) {
+        let mut writer = self.writer.lock().unwrap();
+
+        if enabled && writer.is_none() {
+            match OpenOptions::new().create(true).append(true).open(&self.path) {
+                Ok(file) => *writer = Some(BufWriter::new(file)),
+                Err(e) => {
+                    tracing::error!("Unable to open trace file {}: {}", self.path.display(), e);
+                    return;
+                }
+            }
+        } else if !enabled {
+            if let Some(mut w) = writer.take() {
+                let _ = w.flush();
+            }
+        }
+
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    pub fn record(&self, direction: Direction, port: Port, client_id: u64, message: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let record = TraceRecord::now(direction, port, client_id, message.to_string());
+        self.write(&record);
+    }
+
+    pub fn write(&self, record: &TraceRecord) {
+        let mut writer = self.writer.lock().unwrap();
+
+        if let Some(w) = writer.as_mut() {
+            let line = serde_json::to_string(record).expect("Trace record is always serializable");
+            // flush every line, a trace is mostly needed after something went wrong
+            if writeln!(w, "{}", line).and_then(|_| w.flush()).is_err() {
+                tracing::error!("Unable to write to trace file {}", self.path.display());
+            }
+        }
+    }
+}
+
+pub fn read_trace(path: impl AsRef<Path>) -> io::Result<Vec<TraceRecord>> {
+    let file = File::open(path)?;
+    let mut records = Vec::new();
+
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", number + 1, e))
+        })?;
+        records.push(record);
+    }
+
+    Ok(records)
+}
@@ -0,0 +1,280 @@
+// src/replay.rs
+//
+// Replay of traces written by the session recorder, in two directions:
+// - replay_against_plugin feeds the recorded inbound messages into a plugin and
+//   compares what the plugin sends back with what was recorded;
+// - replay_to_host plays the device side of the external port to a live host
+//   application and checks that the host sends what it sent in the recording.
+
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_interface::clock::VirtualClock;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use tokio::net::TcpListener;
+use tokio_tungstenite::accept_async;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::capture::CapturingInterface;
+use crate::recorder::{Direction, Port, TraceRecord};
+use crate::PluginManager;
+
+pub struct ReplayOptions {
+    // 1.0 keeps the recorded timing, 2.0 plays twice as fast, 0.0 disables waiting
+    pub speed: f64,
+    // how long replay_to_host waits for each message the host is expected to send
+    pub response_timeout: Duration,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        ReplayOptions {
+            speed: 1.0,
+            response_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl ReplayOptions {
+    fn scaled(&self, from: &TraceRecord, to: &TraceRecord) -> Duration {
+        if self.speed <= 0.0 || self.speed.is_nan() {
+            return Duration::ZERO;
+        }
+        let gap_ms = to.timestamp_ms.saturating_sub(from.timestamp_ms) as f64;
+        Duration::from_secs_f64(gap_ms / self.speed / 1000.0)
+    }
+}
+
+#[derive(Debug)]
+pub struct Mismatch {
+    // position of the offending record in the trace
+    pub index: usize,
+    pub port: Port,
+    pub message: String,
+    pub expected: Vec<String>,
+    pub actual: Vec<String>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "record #{} on {:?} port: {}", self.index, self.port, self.message)?;
+        writeln!(f, "  expected: {:?}", self.expected)?;
+        write!(f, "  actual:   {:?}", self.actual)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    pub replayed: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl ReplayReport {
+    pub fn is_success(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for mismatch in &self.mismatches {
+            writeln!(f, "{}", mismatch)?;
+        }
+        write!(f, "{} messages replayed, {} mismatches", self.replayed, self.mismatches.len())
+    }
+}
+
+// Messages are compared as JSON when possible, so key order does not matter
+fn same_message(a: &str, b: &str) -> bool {
+    let (port_a, a) = split_tag(a);
+    let (port_b, b) = split_tag(b);
+
+    port_a == port_b
+        && match (serde_json::from_str::<Value>(a), serde_json::from_str::<Value>(b)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => a == b,
+        }
+}
+
+fn same_messages(expected: &[String], actual: &[String]) -> bool {
+    expected.len() == actual.len() && expected.iter().zip(actual).all(|(e, a)| same_message(e, a))
+}
+
+fn split_tag(text: &str) -> (&str, &str) {
+    for prefix in ["js: ", "external: "] {
+        if let Some(rest) = text.strip_prefix(prefix) {
+            return (prefix, rest);
+        }
+    }
+    ("", text)
+}
+
+fn tag(port: Port, text: &str) -> String {
+    match port {
+        Port::Js => format!("js: {}", text),
+        Port::External => format!("external: {}", text),
+    }
+}
+
+// The plugin runs on a virtual clock that follows the trace timestamps, so timers
+// expire exactly where they did in the recording. The recorder only writes what is
+// sent to a connected client, so the plugin's output is only compared on the ports
+// the trace has outbound messages for (a session without a UI has none on js).
+pub async fn replay_against_plugin<P: Plugin>(records: &[TraceRecord], options: &ReplayOptions) -> ReplayReport {
+    replay_against(P::new(), records, options).await
+}
+
+// The same, for a plugin built by the caller
+pub async fn replay_against<P: Plugin>(plugin: P, records: &[TraceRecord], options: &ReplayOptions) -> ReplayReport {
+    let interface = Arc::new(CapturingInterface::new());
+    let clock = Arc::new(VirtualClock::new());
+    let plugin_manager = PluginManager::with_plugin(interface.clone(), plugin, clock);
+    let mut report = ReplayReport::default();
+
+    let compared: Vec<Port> = records.iter().filter(|r| r.direction == Direction::Outbound).map(|r| r.port).collect();
+
+    let start = records.first().map(|r| r.timestamp_ms).unwrap_or(0);
+    let elapsed = |record: &TraceRecord| Duration::from_millis(record.timestamp_ms.saturating_sub(start));
+
+    // the inbound message being answered, with what was recorded as its answer
+    let mut current: Option<(usize, &TraceRecord, Vec<String>)> = None;
+
+    let close = |current: Option<(usize, &TraceRecord, Vec<String>)>, report: &mut ReplayReport| {
+        let actual: Vec<String> = interface
+            .take()
+            .into_iter()
+            .filter(|(port, _)| compared.contains(port))
+            .map(|(port, text)| tag(port, &text))
+            .collect();
+
+        if let Some((index, record, expected)) = current {
+            report.replayed += 1;
+            if !same_messages(&expected, &actual) {
+                report.mismatches.push(Mismatch {
+                    index,
+                    port: record.port,
+                    message: record.message.clone(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+    };
+
+    for (index, record) in records.iter().enumerate() {
+        if record.direction != Direction::Inbound {
+            continue;
+        }
+
+        if let Some((_, previous, _)) = &current {
+            tokio::time::sleep(options.scaled(previous, record)).await;
+        }
+
+        // timers expiring before this message still belong to the answer of the previous one
+        plugin_manager.advance(elapsed(record).saturating_sub(plugin_manager.now()));
+        close(current.take(), &mut report);
+
+        // everything recorded after this message, up to the next inbound one, is the expected answer
+        let expected: Vec<String> = records[index + 1..]
+            .iter()
+            .take_while(|r| r.direction == Direction::Outbound)
+            .map(|r| tag(r.port, &r.message))
+            .collect();
+
+        match record.port {
+            Port::Js => plugin_manager.handle_js_message(record.message.clone()),
+            Port::External => plugin_manager.handle_external_message(record.message.clone()),
+        }
+
+        current = Some((index, record, expected));
+    }
+
+    if let Some(last) = records.last() {
+        plugin_manager.advance(elapsed(last).saturating_sub(plugin_manager.now()));
+    }
+    close(current.take(), &mut report);
+
+    report
+}
+
+// Listens on `address` as the simulator's external port would
+pub async fn replay_to_host(records: &[TraceRecord], address: SocketAddr, options: &ReplayOptions) -> io::Result<ReplayReport> {
+    let listener = TcpListener::bind(address).await?;
+    tracing::info!("Replay waiting for the host on ws://{}", listener.local_addr()?);
+
+    let (stream, _) = listener.accept().await?;
+    let ws_stream = accept_async(stream)
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::ConnectionAborted, e))?;
+    let (mut write_to_socket, mut read_from_socket) = ws_stream.split();
+
+    let mut report = ReplayReport::default();
+    let mut previous: Option<&TraceRecord> = None;
+
+    for (index, record) in records.iter().enumerate() {
+        if record.port != Port::External {
+            continue;
+        }
+
+        match record.direction {
+            Direction::Outbound => {
+                if let Some(previous) = previous {
+                    tokio::time::sleep(options.scaled(previous, record)).await;
+                }
+
+                if write_to_socket.send(Message::Text(record.message.clone())).await.is_err() {
+                    report.mismatches.push(Mismatch {
+                        index,
+                        port: record.port,
+                        message: record.message.clone(),
+                        expected: vec![record.message.clone()],
+                        actual: vec!["<connection closed>".to_string()],
+                    });
+                    break;
+                }
+            }
+            Direction::Inbound => {
+                let received = tokio::time::timeout(options.response_timeout, async {
+                    while let Some(Ok(msg)) = read_from_socket.next().await {
+                        if let Message::Text(text) = msg {
+                            return Some(text);
+                        }
+                    }
+                    None
+                })
+                .await;
+
+                let closed = matches!(received, Ok(None));
+                let actual = match received {
+                    Ok(Some(text)) => vec![text],
+                    Ok(None) => vec!["<connection closed>".to_string()],
+                    Err(_) => vec!["<timeout>".to_string()],
+                };
+
+                if !same_messages(std::slice::from_ref(&record.message), &actual) {
+                    report.mismatches.push(Mismatch {
+                        index,
+                        port: record.port,
+                        message: record.message.clone(),
+                        expected: vec![record.message.clone()],
+                        actual,
+                    });
+                }
+                if closed {
+                    break;
+                }
+            }
+        }
+
+        report.replayed += 1;
+        previous = Some(record);
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,187 @@
+// src/schema.rs
+//
+// The protocol a plugin reports in its metadata (see plugin_interface::metadata) as
+// JSON Schema and AsyncAPI documents for host developers, and the check of inbound
+// messages against it:
+//
+//     let metadata = plugin.metadata();
+//     let schema = schema::json_schema(&metadata);
+//     let document = schema::asyncapi(&metadata, Port::External, "localhost:9001");
+//
+// Every action and event gets a schema of its own under $defs, named after the port
+// it arrives on ("js.read", "external.enable") or "event.<name>". Field types are
+// mapped from the Rust types the handlers take, unknown ones accept any value.
+
+use crate::recorder::Port;
+
+use plugin_interface::actions::{Action, Field, Origin};
+use plugin_interface::metadata::Metadata;
+
+use jsonschema::Validator;
+use serde_json::{json, Map, Value};
+
+use std::collections::HashMap;
+
+pub const SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+pub const ASYNCAPI_VERSION: &str = "2.6.0";
+// W3C Trace Context, version-trace_id-parent_id-flags
+pub const TRACEPARENT_PATTERN: &str = "^[0-9a-f]{2}-[0-9a-f]{32}-[0-9a-f]{16}-[0-9a-f]{2}$";
+
+pub fn field_type(ty: &str) -> Value {
+    let ty = ty.replace(' ', "");
+    if let Some(inner) = ty.strip_prefix("Option<").and_then(|t| t.strip_suffix('>')) {
+        return json!({ "anyOf": [field_type(inner), { "type": "null" }] });
+    }
+    if let Some(inner) = ty.strip_prefix("Vec<").and_then(|t| t.strip_suffix('>')) {
+        return json!({ "type": "array", "items": field_type(inner) });
+    }
+
+    match ty.as_str() {
+        "String" | "&str" | "char" => json!({ "type": "string" }),
+        "bool" => json!({ "type": "boolean" }),
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize" => json!({ "type": "integer" }),
+        "u8" | "u16" | "u32" | "u64" | "u128" | "usize" => json!({ "type": "integer", "minimum": 0 }),
+        "f32" | "f64" => json!({ "type": "number" }),
+        "DeviceStatus" => json!({ "type": "string", "enum": ["ARMED", "DISABLED", "ERROR"] }),
+        _ => json!({}),
+    }
+}
+
+// {"<tag>": "<name>", ...fields}, e.g. {"action": "read", "value": "20"}
+fn message_schema(tag: &str, name: &str, fields: &[Field]) -> Value {
+    let mut properties = Map::new();
+    properties.insert(tag.to_string(), json!({ "const": name }));
+    let mut required = vec![tag.to_string()];
+    for field in fields {
+        properties.insert(field.name.to_string(), field_type(&field.ty));
+        if field.required {
+            required.push(field.name.to_string());
+        }
+    }
+    json!({ "title": name, "type": "object", "properties": properties, "required": required })
+}
+
+fn action_schema(action: &Action) -> Value {
+    let mut schema = message_schema("action", &action.name, &action.fields);
+    // the host may carry its trace along, see PluginManager::handle_external_message
+    if action.origin == Origin::External {
+        schema["properties"]["traceparent"] = json!({ "type": "string", "pattern": TRACEPARENT_PATTERN });
+    }
+    schema
+}
+
+fn port_name(origin: Origin) -> &'static str {
+    match origin {
+        Origin::Js => "js",
+        Origin::External => "external",
+    }
+}
+
+// (name under $defs, schema) of every action, then of every event
+fn definitions(metadata: &Metadata) -> Vec<(String, Value)> {
+    let actions = metadata.actions.iter().map(|action| {
+        let name = format!("{}.{}", port_name(action.origin), action.name);
+        (name, action_schema(action))
+    });
+    let events = metadata
+        .events
+        .iter()
+        .map(|event| (format!("event.{}", event.name), message_schema("event", &event.name, &event.fields)));
+    actions.chain(events).collect()
+}
+
+pub fn json_schema(metadata: &Metadata) -> Value {
+    let defs: Map<String, Value> = definitions(metadata).into_iter().collect();
+    json!({
+        "$schema": SCHEMA_DIALECT,
+        "title": format!("{} protocol", metadata.model),
+        "$defs": defs,
+    })
+}
+
+// In AsyncAPI 2 terms the host publishes the actions and subscribes to the events
+pub fn asyncapi(metadata: &Metadata, port: Port, url: &str) -> Value {
+    let origin = match port {
+        Port::Js => Origin::Js,
+        Port::External => Origin::External,
+    };
+    let reference = |name: &str| json!({ "$ref": format!("#/components/messages/{}", name) });
+
+    let mut messages = Map::new();
+    let mut published = Vec::new();
+    let mut subscribed = Vec::new();
+    for (name, schema) in definitions(metadata) {
+        let is_event = name.starts_with("event.");
+        if !is_event && !name.starts_with(&format!("{}.", port_name(origin))) {
+            continue;
+        }
+        if is_event {
+            subscribed.push(reference(&name));
+        } else {
+            published.push(reference(&name));
+        }
+        messages.insert(name.clone(), json!({ "name": schema["title"], "payload": schema }));
+    }
+
+    let mut channel = Map::new();
+    if !published.is_empty() {
+        channel.insert("publish".to_string(), json!({ "summary": "What the simulator accepts", "message": { "oneOf": published } }));
+    }
+    if !subscribed.is_empty() {
+        channel.insert("subscribe".to_string(), json!({ "summary": "What the simulator sends", "message": { "oneOf": subscribed } }));
+    }
+
+    json!({
+        "asyncapi": ASYNCAPI_VERSION,
+        "info": {
+            "title": format!("{} ({} port)", metadata.model, port_name(origin)),
+            "version": if metadata.version.is_empty() { "0.0.0" } else { &metadata.version },
+        },
+        "defaultContentType": "application/json",
+        "servers": { "simulator": { "url": url, "protocol": "ws" } },
+        "channels": { "/": channel },
+        "components": { "messages": messages },
+    })
+}
+
+// Checks inbound messages against the actions of the metadata it was built from. A port
+// the metadata lists no action for is not checked, there is nothing to check it against.
+pub struct InboundValidator {
+    validators: HashMap<(Port, String), Validator>,
+    checked: Vec<Port>,
+}
+
+impl InboundValidator {
+    pub fn new(metadata: &Metadata) -> Result<Self, String> {
+        let mut validators = HashMap::new();
+        let mut checked = Vec::new();
+        for action in &metadata.actions {
+            let port = match action.origin {
+                Origin::Js => Port::Js,
+                Origin::External => Port::External,
+            };
+            let schema = action_schema(action);
+            let validator = jsonschema::validator_for(&schema).map_err(|e| format!("{}: {}", action.name, e))?;
+            validators.insert((port, action.name.to_string()), validator);
+            if !checked.contains(&port) {
+                checked.push(port);
+            }
+        }
+        Ok(InboundValidator { validators, checked })
+    }
+
+    pub fn validate(&self, port: Port, text: &str) -> Result<(), String> {
+        if !self.checked.contains(&port) {
+            return Ok(());
+        }
+
+        let message: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e))?;
+        let Some(action) = message.get("action").and_then(Value::as_str) else {
+            return Err("missing action".to_string());
+        };
+        let Some(validator) = self.validators.get(&(port, action.to_string())) else {
+            return Err(format!("unknown action '{}'", action));
+        };
+        validator.validate(&message).map_err(|e| format!("{}: {}", action, e))
+    }
+}
@@ -0,0 +1,112 @@
+// The tests run on paused time: tokio moves the clock forward whenever every task is
+// waiting, so the delays are exact and the tests do not sleep for real.
+
+use plugin_manager::fault::{self, FaultInjector, FaultProfile, REORDER_TIMEOUT};
+
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::time::{sleep, Duration};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use std::sync::Arc;
+
+fn link(profile: FaultProfile) -> (UnboundedSender<Message>, UnboundedReceiver<Message>) {
+    let (output, received) = mpsc::unbounded_channel();
+    let sender = fault::link(Arc::new(FaultInjector::new("test", profile)), output);
+    (sender, received)
+}
+
+fn send(sender: &UnboundedSender<Message>, messages: impl IntoIterator<Item = u32>) {
+    for n in messages {
+        sender.send(Message::Text(n.to_string())).unwrap();
+    }
+}
+
+// what came out of the link so far
+fn received(receiver: &mut UnboundedReceiver<Message>) -> Vec<String> {
+    let mut messages = Vec::new();
+    while let Ok(message) = receiver.try_recv() {
+        messages.push(message.into_text().unwrap());
+    }
+    messages
+}
+
+async fn dropped_with_seed(seed: u64) -> Vec<String> {
+    let (sender, mut receiver) = link(FaultProfile { drop_rate: 0.5, seed: Some(seed), ..FaultProfile::default() });
+    send(&sender, 0..20);
+    sleep(Duration::from_millis(10)).await;
+    received(&mut receiver)
+}
+
+#[tokio::test(start_paused = true)]
+async fn without_faults_messages_go_through_unchanged() {
+    let (sender, mut receiver) = link(FaultProfile::default());
+    send(&sender, 0..5);
+    sleep(Duration::from_millis(1)).await;
+    assert_eq!(received(&mut receiver), ["0", "1", "2", "3", "4"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_same_seed_drops_the_same_messages() {
+    let first = dropped_with_seed(7).await;
+    assert!(!first.is_empty() && first.len() < 20, "{:?}", first);
+    assert_eq!(dropped_with_seed(7).await, first);
+
+    let (sender, mut receiver) = link(FaultProfile { drop_rate: 1.0, ..FaultProfile::default() });
+    send(&sender, 0..5);
+    sleep(Duration::from_secs(1)).await;
+    assert!(received(&mut receiver).is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn latency_delays_each_message_on_its_own() {
+    let (sender, mut receiver) = link(FaultProfile { latency_ms: 100, seed: Some(1), ..FaultProfile::default() });
+    send(&sender, 0..5);
+
+    sleep(Duration::from_millis(99)).await;
+    assert!(received(&mut receiver).is_empty());
+    // all of them after the latency, not one latency after the other
+    sleep(Duration::from_millis(2)).await;
+    assert_eq!(received(&mut receiver), ["0", "1", "2", "3", "4"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn jitter_stays_within_its_bound() {
+    let (sender, mut receiver) = link(FaultProfile { latency_ms: 50, jitter_ms: 50, seed: Some(3), ..FaultProfile::default() });
+    send(&sender, 0..20);
+
+    sleep(Duration::from_millis(49)).await;
+    assert!(received(&mut receiver).is_empty());
+    sleep(Duration::from_millis(52)).await;
+    let mut messages = received(&mut receiver);
+    messages.sort_by_key(|n| n.parse::<u32>().unwrap());
+    assert_eq!(messages, (0..20).map(|n| n.to_string()).collect::<Vec<_>>());
+}
+
+#[tokio::test(start_paused = true)]
+async fn reordered_messages_go_out_after_the_next_one() {
+    let (sender, mut receiver) = link(FaultProfile { reorder_rate: 1.0, seed: Some(5), ..FaultProfile::default() });
+    send(&sender, 0..4);
+    sleep(Duration::from_millis(1)).await;
+    assert_eq!(received(&mut receiver), ["1", "0", "3", "2"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_reordered_message_goes_out_when_the_link_closes() {
+    let (sender, mut receiver) = link(FaultProfile { reorder_rate: 1.0, seed: Some(5), ..FaultProfile::default() });
+    send(&sender, [0]);
+    drop(sender);
+
+    sleep(Duration::from_millis(1)).await;
+    assert_eq!(received(&mut receiver), ["0"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_reordered_message_is_not_held_back_forever() {
+    let (sender, mut receiver) = link(FaultProfile { reorder_rate: 1.0, seed: Some(5), ..FaultProfile::default() });
+    send(&sender, [0]);
+
+    sleep(REORDER_TIMEOUT - Duration::from_millis(1)).await;
+    assert!(received(&mut receiver).is_empty());
+    sleep(Duration::from_millis(2)).await;
+    assert_eq!(received(&mut receiver), ["0"]);
+}
@@ -0,0 +1,47 @@
+use plugin_manager::metrics::{Kind, Metrics};
+
+#[test]
+fn metrics_are_rendered_in_the_prometheus_text_format() {
+    let metrics = Metrics::new();
+    metrics.describe("reads_total", Kind::Counter, "Notes read");
+    metrics.increment("reads_total", &[("value", "20"), ("port", "js")], 1.0);
+    metrics.increment("reads_total", &[("port", "js"), ("value", "20")], 2.0);
+    metrics.set("temperature", &[("sensor", "a \"b\"")], 21.5);
+    metrics.observe("latency_seconds", &[], 0.003);
+    metrics.observe("latency_seconds", &[], 2.0);
+
+    let text = metrics.render();
+
+    let expected = [
+        "# TYPE latency_seconds histogram",
+        r#"latency_seconds_bucket{le="0.0025"} 0"#,
+        r#"latency_seconds_bucket{le="0.005"} 1"#,
+        r#"latency_seconds_bucket{le="1"} 1"#,
+        r#"latency_seconds_bucket{le="+Inf"} 2"#,
+        "latency_seconds_sum 2.003",
+        "latency_seconds_count 2",
+        "# HELP reads_total Notes read",
+        "# TYPE reads_total counter",
+        r#"reads_total{port="js",value="20"} 3"#,
+        "# TYPE temperature gauge",
+        r#"temperature{sensor="a \"b\""} 21.5"#,
+    ];
+    for line in expected {
+        assert!(text.lines().any(|l| l == line), "{} not in\n{}", line, text);
+    }
+}
+
+#[test]
+fn invalid_updates_are_ignored() {
+    let metrics = Metrics::new();
+    metrics.increment("reads_total", &[], 1.0);
+
+    metrics.increment("reads_total", &[], -1.0);
+    metrics.set("reads_total", &[], 10.0);
+    metrics.increment("2reads", &[], 1.0);
+    metrics.increment("reads_total", &[("le-vel", "x")], 1.0);
+
+    assert_eq!(metrics.value("reads_total", &[]), Some(1.0));
+    assert_eq!(metrics.value("2reads", &[]), None);
+    assert_eq!(metrics.render(), "# TYPE reads_total counter\nreads_total 1\n");
+}
@@ -0,0 +1,51 @@
+use plugin_manager::recorder::{read_trace, Direction, Port, SessionRecorder};
+
+use std::path::PathBuf;
+
+fn trace_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("recorder-{}-{}.jsonl", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn recorded_messages_read_back_in_order() {
+    let path = trace_file("round-trip");
+    let recorder = SessionRecorder::new(&path, true);
+
+    recorder.record(Direction::Inbound, Port::External, 1, r#"{"action": "enable"}"#);
+    recorder.record(Direction::Outbound, Port::External, 1, r#"{"event": "statusChange", "status": "ARMED"}"#);
+    recorder.record(Direction::Inbound, Port::Js, 2, r#"{"action": "read", "value": "20"}"#);
+    recorder.record(Direction::Outbound, Port::Js, 2, r#"{"event": "read", "value": "20"}"#);
+
+    let records = read_trace(&path).unwrap();
+    let summary: Vec<_> = records.iter().map(|r| (r.direction, r.port, r.client_id, r.message.as_str())).collect();
+    assert_eq!(
+        summary,
+        [
+            (Direction::Inbound, Port::External, 1, r#"{"action": "enable"}"#),
+            (Direction::Outbound, Port::External, 1, r#"{"event": "statusChange", "status": "ARMED"}"#),
+            (Direction::Inbound, Port::Js, 2, r#"{"action": "read", "value": "20"}"#),
+            (Direction::Outbound, Port::Js, 2, r#"{"event": "read", "value": "20"}"#),
+        ]
+    );
+    assert!(records.windows(2).all(|pair| pair[0].timestamp_ms <= pair[1].timestamp_ms));
+}
+
+#[test]
+fn only_messages_sent_while_recording_are_kept() {
+    let path = trace_file("toggle");
+    let recorder = SessionRecorder::new(&path, false);
+
+    recorder.record(Direction::Inbound, Port::External, 1, "before");
+    recorder.set_enabled(true);
+    recorder.record(Direction::Inbound, Port::External, 1, "first");
+    recorder.set_enabled(false);
+    recorder.record(Direction::Inbound, Port::External, 1, "paused");
+    recorder.set_enabled(true);
+    recorder.record(Direction::Outbound, Port::Js, 1, "second");
+
+    let messages: Vec<String> = read_trace(&path).unwrap().into_iter().map(|r| r.message).collect();
+    assert_eq!(messages, ["first", "second"]);
+    assert!(recorder.is_enabled());
+}
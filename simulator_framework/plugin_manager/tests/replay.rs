@@ -0,0 +1,62 @@
+use bna_plugin::BNAPlugin;
+use plugin_manager::recorder::{Direction, Port, TraceRecord};
+use plugin_manager::replay::{replay_against_plugin, ReplayOptions};
+
+fn record(timestamp_ms: u64, direction: Direction, port: Port, message: &str) -> TraceRecord {
+    TraceRecord { timestamp_ms, direction, port, client_id: 1, message: message.to_string() }
+}
+
+fn instant() -> ReplayOptions {
+    ReplayOptions { speed: 0.0, ..ReplayOptions::default() }
+}
+
+#[tokio::test]
+async fn a_recorded_session_replays_cleanly() {
+    let trace = [
+        record(0, Direction::Inbound, Port::External, r#"{"action": "enable"}"#),
+        record(1, Direction::Outbound, Port::External, r#"{"event": "statusChange", "status": "ARMED"}"#),
+        record(10, Direction::Inbound, Port::External, r#"{"action": "query_status"}"#),
+        record(11, Direction::Outbound, Port::External, r#"{"event": "statusChange", "status": "ARMED"}"#),
+    ];
+
+    let report = replay_against_plugin::<BNAPlugin>(&trace, &instant()).await;
+
+    // no UI was connected, what the plugin sent on the js port is not compared
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(report.replayed, 2);
+}
+
+#[tokio::test]
+async fn key_order_does_not_matter() {
+    let trace = [
+        record(0, Direction::Inbound, Port::External, r#"{"action": "enable"}"#),
+        record(1, Direction::Outbound, Port::External, r#"{"status": "ARMED", "event": "statusChange"}"#),
+    ];
+
+    let report = replay_against_plugin::<BNAPlugin>(&trace, &instant()).await;
+
+    assert!(report.is_success(), "{}", report);
+}
+
+#[tokio::test]
+async fn different_answers_are_reported() {
+    let trace = [
+        record(0, Direction::Inbound, Port::External, r#"{"action": "enable"}"#),
+        record(1, Direction::Outbound, Port::External, r#"{"event": "statusChange", "status": "DISABLED"}"#),
+        record(2, Direction::Outbound, Port::Js, r#"{"event": "statusChange", "status": "ARMED"}"#),
+    ];
+
+    let report = replay_against_plugin::<BNAPlugin>(&trace, &instant()).await;
+
+    assert_eq!(report.mismatches.len(), 1);
+    let mismatch = &report.mismatches[0];
+    assert_eq!(mismatch.index, 0);
+    // with js traffic in the trace the js port is compared too
+    assert_eq!(
+        mismatch.actual,
+        [
+            r#"js: {"event":"statusChange","status":"ARMED"}"#,
+            r#"external: {"event":"statusChange","status":"ARMED"}"#,
+        ]
+    );
+}
@@ -0,0 +1,134 @@
+use plugin_interface::actions::{Action, Field, Origin};
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_interface::interface_for_server::CommunicationInterface;
+use plugin_interface::metadata::{Event, Metadata};
+use plugin_manager::capture::CapturingInterface;
+use plugin_manager::recorder::Port;
+use plugin_manager::schema::{self, InboundValidator};
+use plugin_manager::PluginManager;
+
+use serde_json::json;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+const VALUE: &[Field] = &[Field::new("value", "String", true)];
+const LIMIT: &[Field] = &[Field::new("limit", "Option<u32>", false)];
+
+fn acceptor() -> Metadata {
+    Metadata {
+        device_type: "bna".to_string(),
+        model: "Acceptor".to_string(),
+        version: "1.2.0".to_string(),
+        actions: vec![
+            Action::new(Origin::Js, "read", VALUE),
+            Action::new(Origin::External, "enable", LIMIT),
+        ],
+        events: vec![Event::new("read", VALUE)],
+    }
+}
+
+#[test]
+fn every_action_and_event_gets_a_schema() {
+    let schema = schema::json_schema(&acceptor());
+
+    assert!(jsonschema::meta::is_valid(&schema));
+    assert_eq!(schema["$defs"]["js.read"], json!({
+        "title": "read",
+        "type": "object",
+        "properties": { "action": { "const": "read" }, "value": { "type": "string" } },
+        "required": ["action", "value"],
+    }));
+    assert_eq!(schema["$defs"]["external.enable"]["properties"]["limit"], json!({
+        "anyOf": [{ "type": "integer", "minimum": 0 }, { "type": "null" }],
+    }));
+    assert_eq!(schema["$defs"]["event.read"]["required"], json!(["event", "value"]));
+}
+
+#[test]
+fn asyncapi_documents_only_hold_the_actions_of_their_port() {
+    let document = schema::asyncapi(&acceptor(), Port::External, "localhost:9001");
+
+    assert_eq!(document["info"], json!({ "title": "Acceptor (external port)", "version": "1.2.0" }));
+    assert_eq!(document["servers"]["simulator"], json!({ "url": "localhost:9001", "protocol": "ws" }));
+    let channel = &document["channels"]["/"];
+    assert_eq!(channel["publish"]["message"]["oneOf"], json!([{ "$ref": "#/components/messages/external.enable" }]));
+    assert_eq!(channel["subscribe"]["message"]["oneOf"], json!([{ "$ref": "#/components/messages/event.read" }]));
+    assert!(document["components"]["messages"].get("js.read").is_none());
+}
+
+#[test]
+fn inbound_messages_are_checked_against_their_action() {
+    let validator = InboundValidator::new(&acceptor()).unwrap();
+
+    assert_eq!(validator.validate(Port::Js, r#"{"action": "read", "value": "20"}"#), Ok(()));
+    assert_eq!(validator.validate(Port::External, r#"{"action": "enable"}"#), Ok(()));
+    assert!(validator.validate(Port::Js, r#"{"action": "read"}"#).unwrap_err().starts_with("read: "));
+    assert!(validator.validate(Port::External, r#"{"action": "enable", "limit": -1}"#).is_err());
+    assert_eq!(validator.validate(Port::External, r#"{"action": "read", "value": "20"}"#), Err("unknown action 'read'".to_string()));
+    assert_eq!(validator.validate(Port::Js, r#"{"value": "20"}"#), Err("missing action".to_string()));
+    assert!(validator.validate(Port::Js, "not json").unwrap_err().starts_with("invalid JSON"));
+}
+
+#[test]
+fn host_actions_may_carry_a_traceparent() {
+    let validator = InboundValidator::new(&acceptor()).unwrap();
+
+    let traced = r#"{"action": "enable", "traceparent": "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"}"#;
+    assert_eq!(validator.validate(Port::External, traced), Ok(()));
+    assert!(validator.validate(Port::External, r#"{"action": "enable", "traceparent": "trace me"}"#).is_err());
+}
+
+#[test]
+fn ports_without_actions_are_not_checked() {
+    let validator = InboundValidator::new(&Metadata { actions: vec![Action::new(Origin::Js, "read", VALUE)], ..Metadata::default() }).unwrap();
+
+    assert_eq!(validator.validate(Port::External, "anything"), Ok(()));
+}
+
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+static DESCRIBED: AtomicUsize = AtomicUsize::new(0);
+
+// Understands "first" until it is reloaded, then "second"; answers what it accepts
+struct Evolving {
+    generation: usize,
+}
+
+impl Plugin for Evolving {
+    fn new() -> Self {
+        Evolving { generation: CREATED.fetch_add(1, Ordering::SeqCst) }
+    }
+
+    fn handle_js_message<I: CommunicationInterface>(&mut self, _interface: &I, _text: String) {}
+
+    fn handle_external_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
+        interface.send_to_external(Message::Text(text));
+    }
+
+    fn metadata(&self) -> Metadata {
+        DESCRIBED.fetch_add(1, Ordering::SeqCst);
+        let action = if self.generation == 0 { "first" } else { "second" };
+        Metadata { actions: vec![Action::new(Origin::External, action, &[])], ..Metadata::default() }
+    }
+}
+
+#[test]
+fn the_validator_is_built_once_and_again_on_reload() {
+    let interface = Arc::new(CapturingInterface::new());
+    let mut plugin_manager = PluginManager::<_, Evolving>::new(interface.clone());
+    plugin_manager.set_inbound_validation(true);
+    let accepted = |action: &str| {
+        plugin_manager.handle_external_message(json!({ "action": action }).to_string());
+        !interface.take()[0].1.contains("\"error\"")
+    };
+
+    assert!(accepted("first"));
+    assert!(!accepted("second"));
+    assert_eq!(DESCRIBED.load(Ordering::SeqCst), 1);
+
+    plugin_manager.reload().unwrap();
+    assert!(accepted("second"));
+    assert!(!accepted("first"));
+    assert_eq!(DESCRIBED.load(Ordering::SeqCst), 2);
+}
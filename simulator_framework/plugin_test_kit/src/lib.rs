@@ -0,0 +1,239 @@
+// src/lib.rs
+//
+// Drives a plugin in-process, the way the simulator server would, and records
+// everything it sends so tests can assert on it:
+//
+//     let mut bna = PluginHarness::<BNAPlugin>::new();
+//     bna.external(json!({ "action": "enable" }));
+//     bna.expect_external_event("statusChange").with("status", "ARMED");
+//     bna.expect_js_event("statusChange").with("status", "ARMED");
+//     bna.expect_no_more_messages();
+//
+// Each expect_* call checks the next message sent on that port, in order. The
+// plugin runs on a virtual clock, only advance() moves it.
+//
+// reader.rs has the checks shared by the reader devices.
+
+use plugin_interface::clock::VirtualClock;
+use plugin_interface::interface_for_server::Level;
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_manager::PluginManager;
+
+use serde_json::Value;
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod reader;
+
+pub use plugin_manager::capture::CapturingInterface as RecordingInterface;
+pub use plugin_manager::recorder::Port;
+
+pub struct PluginHarness<P: Plugin> {
+    interface: Arc<RecordingInterface>,
+    plugin_manager: PluginManager<RecordingInterface, P>,
+    js_messages: VecDeque<Value>,
+    external_messages: VecDeque<Value>,
+}
+
+impl<P: Plugin> Default for PluginHarness<P> {
+    fn default() -> Self {
+        PluginHarness::new()
+    }
+}
+
+impl<P: Plugin> PluginHarness<P> {
+    pub fn new() -> Self {
+        PluginHarness::with_plugin(P::new())
+    }
+
+    // For plugins that take their configuration, a file for example, as arguments
+    pub fn with_plugin(plugin: P) -> Self {
+        let interface = Arc::new(RecordingInterface::new());
+        let plugin_manager = PluginManager::with_plugin(interface.clone(), plugin, Arc::new(VirtualClock::new()));
+
+        PluginHarness {
+            interface,
+            plugin_manager,
+            js_messages: VecDeque::new(),
+            external_messages: VecDeque::new(),
+        }
+    }
+
+    // Sends a message as the simulator UI would, on the js port
+    pub fn js(&mut self, message: Value) -> &mut Self {
+        self.plugin_manager.handle_js_message(message.to_string());
+        self.collect();
+        self
+    }
+
+    // Sends a message as the host application would, on the external port
+    pub fn external(&mut self, message: Value) -> &mut Self {
+        self.plugin_manager.handle_external_message(message.to_string());
+        self.collect();
+        self
+    }
+
+    pub fn advance(&mut self, by: Duration) -> &mut Self {
+        self.plugin_manager.advance(by);
+        self.collect();
+        self
+    }
+
+    // Swaps in a new instance restored from the current one, as hot reload does; on
+    // an error the current one stays
+    pub fn reload(&mut self) -> Result<&mut Self, String> {
+        self.plugin_manager.reload()?;
+        self.collect();
+        Ok(self)
+    }
+
+    fn collect(&mut self) {
+        for (port, text) in self.interface.take() {
+            let message = serde_json::from_str(&text)
+                .unwrap_or_else(|e| panic!("plugin sent invalid JSON on the {:?} port: {} ({})", port, text, e));
+            match port {
+                Port::Js => self.js_messages.push_back(message),
+                Port::External => self.external_messages.push_back(message),
+            }
+        }
+    }
+
+    // What the plugin logged since the last call, see CommunicationInterface::log
+    pub fn logs(&self) -> Vec<(Level, String)> {
+        self.interface.take_logs()
+    }
+
+    // The value of one of the plugin's counters or gauges, see CommunicationInterface::count
+    pub fn metric(&self, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        self.interface.metrics().value(name, labels)
+    }
+
+    // The plugin's own view of its state, see Plugin::snapshot
+    pub fn state(&self) -> Value {
+        self.plugin_manager.snapshot()
+    }
+
+    // Messages sent so far and not yet checked by an expect_* call
+    pub fn pending(&self, port: Port) -> &VecDeque<Value> {
+        match port {
+            Port::Js => &self.js_messages,
+            Port::External => &self.external_messages,
+        }
+    }
+
+    pub fn clear(&mut self) -> &mut Self {
+        self.js_messages.clear();
+        self.external_messages.clear();
+        self
+    }
+
+    #[track_caller]
+    fn next(&mut self, port: Port) -> Value {
+        let queue = match port {
+            Port::Js => &mut self.js_messages,
+            Port::External => &mut self.external_messages,
+        };
+        queue
+            .pop_front()
+            .unwrap_or_else(|| panic!("expected a message on the {:?} port, none was sent", port))
+    }
+
+    #[track_caller]
+    pub fn expect_js_event(&mut self, event: &str) -> EventAssertion {
+        EventAssertion::new(Port::Js, self.next(Port::Js), event)
+    }
+
+    #[track_caller]
+    pub fn expect_external_event(&mut self, event: &str) -> EventAssertion {
+        EventAssertion::new(Port::External, self.next(Port::External), event)
+    }
+
+    // The next message on the port must be exactly `message`
+    #[track_caller]
+    pub fn expect_js(&mut self, message: Value) -> &mut Self {
+        let actual = self.next(Port::Js);
+        assert_eq!(actual, message, "unexpected message on the js port");
+        self
+    }
+
+    #[track_caller]
+    pub fn expect_external(&mut self, message: Value) -> &mut Self {
+        let actual = self.next(Port::External);
+        assert_eq!(actual, message, "unexpected message on the external port");
+        self
+    }
+
+    #[track_caller]
+    pub fn expect_no_js_messages(&mut self) -> &mut Self {
+        assert!(self.js_messages.is_empty(), "unexpected messages on the js port: {:?}", self.js_messages);
+        self
+    }
+
+    #[track_caller]
+    pub fn expect_no_external_messages(&mut self) -> &mut Self {
+        assert!(
+            self.external_messages.is_empty(),
+            "unexpected messages on the external port: {:?}",
+            self.external_messages
+        );
+        self
+    }
+
+    #[track_caller]
+    pub fn expect_no_more_messages(&mut self) -> &mut Self {
+        self.expect_no_js_messages().expect_no_external_messages()
+    }
+}
+
+pub struct EventAssertion {
+    port: Port,
+    message: Value,
+}
+
+impl EventAssertion {
+    #[track_caller]
+    fn new(port: Port, message: Value, event: &str) -> Self {
+        assert_eq!(
+            message.get("event").and_then(|v| v.as_str()),
+            Some(event),
+            "expected event '{}' on the {:?} port, got {}",
+            event,
+            port,
+            message
+        );
+        EventAssertion { port, message }
+    }
+
+    #[track_caller]
+    pub fn with(self, field: &str, value: impl Into<Value>) -> Self {
+        let value = value.into();
+        assert_eq!(
+            self.message.get(field),
+            Some(&value),
+            "expected {} = {} on the {:?} port, got {}",
+            field,
+            value,
+            self.port,
+            self.message
+        );
+        self
+    }
+
+    #[track_caller]
+    pub fn without(self, field: &str) -> Self {
+        assert!(
+            self.message.get(field).is_none(),
+            "expected no {} on the {:?} port, got {}",
+            field,
+            self.port,
+            self.message
+        );
+        self
+    }
+
+    pub fn message(&self) -> &Value {
+        &self.message
+    }
+}
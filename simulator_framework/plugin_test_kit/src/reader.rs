@@ -0,0 +1,107 @@
+// src/reader.rs
+//
+// What every reader device (barcode, card) does the same way: enable and disable arm
+// and disarm it on both ports, a read goes to the host, error moves it in and out of
+// ERROR. A reader's own tests call these and add what is particular to the device:
+//
+//     #[test]
+//     fn enable_arms_the_device_on_both_ports() {
+//         reader::enable_arms_the_device_on_both_ports::<CardPlugin>();
+//     }
+
+use crate::PluginHarness;
+
+use plugin_interface::interface_for_plugin::Plugin;
+
+use serde_json::json;
+
+// enabled by the host, with the status changes already cleared
+pub fn armed<P: Plugin>() -> PluginHarness<P> {
+    let mut reader = PluginHarness::<P>::new();
+    reader.external(json!({ "action": "enable" })).clear();
+    reader
+}
+
+pub fn enable_arms_the_device_on_both_ports<P: Plugin>() {
+    let mut reader = PluginHarness::<P>::new();
+
+    reader.external(json!({ "action": "enable" }));
+
+    reader.expect_js_event("statusChange").with("status", "ARMED");
+    reader.expect_external_event("statusChange").with("status", "ARMED");
+    reader.expect_no_more_messages();
+}
+
+pub fn disable_disarms_the_device_on_both_ports<P: Plugin>() {
+    let mut reader = armed::<P>();
+
+    reader.external(json!({ "action": "disable" }));
+
+    reader.expect_js_event("statusChange").with("status", "DISABLED");
+    reader.expect_external_event("statusChange").with("status", "DISABLED");
+    reader.expect_no_more_messages();
+}
+
+pub fn read_sends_the_value_to_the_host_only<P: Plugin>(value: &str) {
+    let mut reader = armed::<P>();
+
+    reader.js(json!({ "action": "read", "value": value }));
+
+    reader.expect_external(json!({ "event": "read", "value": value }));
+    reader.expect_no_more_messages();
+}
+
+pub fn read_does_not_change_the_status<P: Plugin>() {
+    let mut reader = armed::<P>();
+    reader.js(json!({ "action": "read", "value": "42" })).clear();
+
+    reader.external(json!({ "action": "disable" }));
+
+    reader.expect_external_event("statusChange").with("status", "DISABLED");
+}
+
+pub fn read_without_value_is_answered_with_an_error<P: Plugin>() {
+    let mut reader = armed::<P>();
+
+    reader.js(json!({ "action": "read" }));
+
+    reader.expect_js(json!({ "event": "error", "message": "read: missing field 'value'" }));
+    reader.expect_no_more_messages();
+}
+
+pub fn error_toggles_between_error_and_disabled<P: Plugin>() {
+    let mut reader = armed::<P>();
+
+    reader.js(json!({ "action": "error" }));
+    reader.expect_js_event("statusChange").with("status", "ERROR");
+    reader.expect_external_event("statusChange").with("status", "ERROR");
+
+    reader.js(json!({ "action": "error" }));
+    reader.expect_js_event("statusChange").with("status", "DISABLED");
+    reader.expect_external_event("statusChange").with("status", "DISABLED");
+    reader.expect_no_more_messages();
+}
+
+pub fn enable_and_disable_are_rejected_in_error<P: Plugin>() {
+    let mut reader = armed::<P>();
+    reader.js(json!({ "action": "error" })).clear();
+
+    reader.external(json!({ "action": "enable" }));
+    reader.external(json!({ "action": "disable" }));
+
+    reader.expect_no_more_messages();
+}
+
+// no status query and no escrow on a reader
+pub fn unknown_actions_are_answered_on_their_port<P: Plugin>() {
+    let mut reader = armed::<P>();
+
+    reader.external(json!({ "action": "query_status" }));
+    reader.external(json!({ "action": "confirm_read" }));
+    reader.js(json!({ "action": "beep" }));
+
+    reader.expect_external(json!({ "event": "error", "message": "unknown action 'query_status'" }));
+    reader.expect_external(json!({ "event": "error", "message": "unknown action 'confirm_read'" }));
+    reader.expect_js(json!({ "event": "error", "message": "unknown action 'beep'" }));
+    reader.expect_no_more_messages();
+}
@@ -0,0 +1,44 @@
+use barcode_plugin::BarcodePlugin;
+use plugin_test_kit::reader;
+
+// the behaviour shared with the other readers is in plugin_test_kit::reader
+
+#[test]
+fn enable_arms_the_device_on_both_ports() {
+    reader::enable_arms_the_device_on_both_ports::<BarcodePlugin>();
+}
+
+#[test]
+fn disable_disarms_the_device_on_both_ports() {
+    reader::disable_disarms_the_device_on_both_ports::<BarcodePlugin>();
+}
+
+#[test]
+fn read_sends_the_code_to_the_host_only() {
+    reader::read_sends_the_value_to_the_host_only::<BarcodePlugin>("8001234567890");
+}
+
+#[test]
+fn read_does_not_change_the_status() {
+    reader::read_does_not_change_the_status::<BarcodePlugin>();
+}
+
+#[test]
+fn read_without_value_is_answered_with_an_error() {
+    reader::read_without_value_is_answered_with_an_error::<BarcodePlugin>();
+}
+
+#[test]
+fn error_toggles_between_error_and_disabled() {
+    reader::error_toggles_between_error_and_disabled::<BarcodePlugin>();
+}
+
+#[test]
+fn enable_and_disable_are_rejected_in_error() {
+    reader::enable_and_disable_are_rejected_in_error::<BarcodePlugin>();
+}
+
+#[test]
+fn unknown_actions_are_answered_on_their_port() {
+    reader::unknown_actions_are_answered_on_their_port::<BarcodePlugin>();
+}
@@ -1,36 +1,91 @@
 // src/plugin.rs
 
 use plugin_interface::interface_for_plugin::Plugin;
-use plugin_interface::interface_for_server::CommunicationInterface;
+use plugin_interface::interface_for_server::{CommunicationInterface, Level};
+use plugin_interface::metadata::{Metadata, DEVICE_EVENTS};
+use plugin_interface::ui::{Control, UiDescription};
+use plugin_interface::protocol::{self, DeviceEvent, DeviceStatus};
+use plugin_interface::state_machine::StateMachine;
+use plugin_macros::plugin_actions;
 
 use serde_json::Value;
-use tokio_tungstenite::tungstenite::protocol::Message;
 
-#[derive(Clone, Debug, PartialEq)]
-enum DeviceStatus {
-    Armed,
-    Disabled,
-    Error,
-}
+use std::time::Duration;
+
+// a note left in escrow without confirm_read is given back to the customer
+const ESCROW_TIMEOUT: Duration = Duration::from_secs(30);
+// the notes the UI offers and counted by denomination, whatever else it sends is counted
+// as "other"
+const DENOMINATIONS: [&str; 5] = ["5", "10", "20", "50", "100"];
 
 
 #[derive(Clone)]
 pub struct BNAPlugin {
-    status: DeviceStatus,
+    machine: StateMachine<DeviceStatus>,
     numeric_value: String,
+    // a note has been read and is waiting in escrow for confirm_read
     read_state: bool,
 }
 
+#[plugin_actions]
 impl BNAPlugin
 {
-    fn status_to_str(&self, status: &DeviceStatus) -> &'static str 
+    #[js_action("read")]
+    fn read<I: CommunicationInterface>(&mut self, interface: &I, value: String, message: &Value)
     {
-        match status 
-        {
-            DeviceStatus::Armed => "ARMED",
-            DeviceStatus::Disabled => "DISABLED",
-            DeviceStatus::Error => "ERROR",
+        self.read_state = true;
+        interface.set_timer("escrow", ESCROW_TIMEOUT);
+
+        self.numeric_value = value;
+        let denomination = DENOMINATIONS.iter().find(|d| **d == self.numeric_value).unwrap_or(&"other");
+        interface.count("bna_reads_total", &[("denomination", denomination)], 1.0);
+
+        let read_msg = DeviceEvent::Read { value: self.numeric_value.clone() };
+        interface.send_to_external(protocol::to_message(&read_msg));
+
+        self.fire(interface, "read", message);
+    }
+
+    #[js_action("error")]
+    fn error<I: CommunicationInterface>(&mut self, interface: &I, message: &Value)
+    {
+        self.fire(interface, "error", message);
+    }
+
+    #[external_action("enable")]
+    fn enable<I: CommunicationInterface>(&mut self, interface: &I, message: &Value)
+    {
+        self.fire(interface, "enable", message);
+    }
+
+    #[external_action("disable")]
+    fn disable<I: CommunicationInterface>(&mut self, interface: &I, message: &Value)
+    {
+        self.fire(interface, "disable", message);
+    }
+
+    #[external_action("query_status")]
+    fn query_status<I: CommunicationInterface>(&mut self, interface: &I)
+    {
+        interface.send_to_external(self.machine.status_message());
+    }
+
+    #[external_action("confirm_read")]
+    fn confirm_read<I: CommunicationInterface>(&mut self, interface: &I)
+    {
+        self.read_state = false;
+        interface.cancel_timer("escrow");
+
+        interface.send_to_external(protocol::to_message(&DeviceEvent::ConfirmRead));
+    }
+
+    fn fire<I: CommunicationInterface>(&mut self, interface: &I, trigger: &str, message: &Value)
+    {
+        if let Err(e) = self.machine.fire(interface, trigger, message) {
+            interface.log(Level::WARN, &format!("BNA: {}", e));
         }
+        let in_error = *self.machine.state() == DeviceStatus::Error;
+        interface.gauge("bna_error", &[], if in_error { 1.0 } else { 0.0 });
     }
 }
 
@@ -39,7 +94,8 @@ impl Plugin for BNAPlugin {
 
     fn new() -> Self {
         BNAPlugin {
-            status: DeviceStatus::Disabled,
+            // the acceptor disables itself after every note
+            machine: StateMachine::device().transition_from_any("read", DeviceStatus::Disabled),
             numeric_value: String::new(),
             read_state: false
         }
@@ -47,82 +103,74 @@ impl Plugin for BNAPlugin {
 
     fn handle_js_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) 
     {
-        let json: Value = serde_json::from_str(&text).expect("Invalid JSON");
+        self.dispatch_js_message(interface, text);
+    }
 
-        if let Some(action) = json.get("action").and_then(|v| v.as_str()) 
+    fn handle_external_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
+        self.dispatch_external_message(interface, text);
+    }
+
+    fn handle_timer<I: CommunicationInterface>(&mut self, interface: &I, name: &str)
+    {
+        if name == "escrow" && self.read_state
         {
-            match action {
-                "read" => {
-
-                    if let Some(value) = json.get("value").and_then(|v| v.as_str()) 
-                    {
-                        self.status = DeviceStatus::Disabled;
-                        
-                        // Lock the read_state mutex and update its value
-                        self.read_state = true;
-
-                        self.numeric_value = value.to_string();
-
-                        let read_msg = serde_json::json!({ "event": "read", "value": self.numeric_value });
-
-                        interface.send_to_external(Message::Text(read_msg.to_string()));
-
-                        let status_msg = serde_json::json!({ "event": "statusChange", "status": self.status_to_str(&self.status) });
-                        interface.send_to_js_clients(Message::Text(status_msg.to_string()));
-                        interface.send_to_external(Message::Text(status_msg.to_string()));
-                    }
-                }
-                "error" => {
-                    if self.status == DeviceStatus::Error {
-                        self.status = DeviceStatus::Disabled;
-                    } else {
-                        self.status = DeviceStatus::Error;
-                    }
-                    
-                    let status_msg = serde_json::json!({ "event": "statusChange", "status": self.status_to_str(&self.status) });
-
-                    interface.send_to_js_clients(Message::Text(status_msg.to_string()));
-                    interface.send_to_external(Message::Text(status_msg.to_string()));
-                }
-                _ => (),
-            }
+            self.read_state = false;
+
+            let returned_msg = protocol::to_message(&DeviceEvent::Returned { value: self.numeric_value.clone() });
+            interface.send_to_js_clients(returned_msg.clone());
+            interface.send_to_external(returned_msg);
         }
     }
 
-    fn handle_external_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
-        let json: Value = serde_json::from_str(&text).expect("Invalid JSON");
-
-        if let Some(action) = json.get("action").and_then(|v| v.as_str()) {
-
-            if self.status == DeviceStatus::Error && (action == "enable" || action == "disable") {
-                return;
-            }
-
-            match action {
-                "enable" => {
-                    self.status = DeviceStatus::Armed;
-                    let status_msg = serde_json::json!({ "event": "statusChange", "status": "ARMED" });
-
-                    interface.send_to_js_clients(Message::Text(status_msg.to_string()));
-                    interface.send_to_external(Message::Text(status_msg.to_string()));
-                }
-                "disable" => {
-                    self.status = DeviceStatus::Disabled;
-                    let status_msg = serde_json::json!({ "event": "statusChange", "status": "DISABLED" });
-
-                    interface.send_to_js_clients(Message::Text(status_msg.to_string()));
-                    interface.send_to_external(Message::Text(status_msg.to_string()));
-                }
-                "query_status" =>{
-                    let status_msg = serde_json::json!({ "event": "statusChange", "status": self.status_to_str(&self.status) });
-                    interface.send_to_external(Message::Text(status_msg.to_string()));
-                }
-                "confirm_read" =>{
-                    let status_msg = serde_json::json!({ "event": "confirm_read" });
-                    interface.send_to_external(Message::Text(status_msg.to_string()));
-                }
-                _ => (),
-            }
+    fn snapshot(&self) -> Value
+    {
+        serde_json::json!({
+            "status": self.machine.state(),
+            "value": self.numeric_value,
+            "escrow": self.read_state,
+        })
+    }
+
+    fn restore(&mut self, snapshot: &Value)
+    {
+        if let Ok(status) = serde_json::from_value(snapshot["status"].clone()) {
+            self.machine.restore(status);
+        }
+        self.numeric_value = snapshot["value"].as_str().unwrap_or_default().to_string();
+        self.read_state = snapshot["escrow"].as_bool().unwrap_or(false);
+    }
+
+    fn metadata(&self) -> Metadata
+    {
+        Metadata {
+            device_type: "bna".to_string(),
+            model: "BNA simulator".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            actions: Self::ACTIONS.to_vec(),
+            events: DEVICE_EVENTS.to_vec(),
+        }
+    }
+
+    fn ui(&self) -> UiDescription
+    {
+        UiDescription {
+            title: "BNA Simulator".to_string(),
+            controls: vec![
+                Control::status_badge(),
+                Control::Select {
+                    label: "Select Value".to_string(),
+                    field: "value".to_string(),
+                    options: DENOMINATIONS.map(String::from).to_vec(),
+                },
+                Control::Button {
+                    label: "Send".to_string(),
+                    action: "read".to_string(),
+                    fields: vec!["value".to_string()],
+                    enabled_in: vec!["ARMED".to_string()],
+                },
+                Control::Button { label: "Toggle Error".to_string(), action: "error".to_string(), fields: Vec::new(), enabled_in: Vec::new() },
+                Control::event_log(),
+            ],
         }
     }
 
@@ -0,0 +1,235 @@
+use bna_plugin::BNAPlugin;
+use plugin_test_kit::PluginHarness;
+
+use serde_json::json;
+
+use std::time::Duration;
+
+fn armed() -> PluginHarness<BNAPlugin> {
+    let mut bna = PluginHarness::<BNAPlugin>::new();
+    bna.external(json!({ "action": "enable" })).clear();
+    bna
+}
+
+fn in_error() -> PluginHarness<BNAPlugin> {
+    let mut bna = PluginHarness::<BNAPlugin>::new();
+    bna.js(json!({ "action": "error" })).clear();
+    bna
+}
+
+#[test]
+fn starts_disabled() {
+    let mut bna = PluginHarness::<BNAPlugin>::new();
+
+    bna.external(json!({ "action": "query_status" }));
+
+    bna.expect_external_event("statusChange").with("status", "DISABLED");
+    bna.expect_no_more_messages();
+}
+
+#[test]
+fn enable_arms_the_device_on_both_ports() {
+    let mut bna = PluginHarness::<BNAPlugin>::new();
+
+    bna.external(json!({ "action": "enable" }));
+
+    bna.expect_external_event("statusChange").with("status", "ARMED");
+    bna.expect_js_event("statusChange").with("status", "ARMED");
+    bna.expect_no_more_messages();
+}
+
+#[test]
+fn disable_disarms_the_device_on_both_ports() {
+    let mut bna = armed();
+
+    bna.external(json!({ "action": "disable" }));
+
+    bna.expect_external_event("statusChange").with("status", "DISABLED");
+    bna.expect_js_event("statusChange").with("status", "DISABLED");
+    bna.expect_no_more_messages();
+}
+
+#[test]
+fn query_status_answers_only_the_host() {
+    let mut bna = armed();
+
+    bna.external(json!({ "action": "query_status" }));
+
+    bna.expect_external_event("statusChange").with("status", "ARMED");
+    bna.expect_no_more_messages();
+}
+
+#[test]
+fn read_sends_the_note_to_the_host_and_disables() {
+    let mut bna = armed();
+
+    bna.js(json!({ "action": "read", "value": "20" }));
+
+    bna.expect_external_event("read").with("value", "20");
+    bna.expect_external_event("statusChange").with("status", "DISABLED");
+    bna.expect_js_event("statusChange").with("status", "DISABLED");
+    bna.expect_no_more_messages();
+}
+
+#[test]
+fn read_without_value_is_answered_with_an_error() {
+    let mut bna = armed();
+
+    bna.js(json!({ "action": "read" }));
+
+    bna.expect_js(json!({ "event": "error", "message": "read: missing field 'value'" }));
+    bna.expect_no_more_messages();
+}
+
+#[test]
+fn confirm_read_is_acknowledged() {
+    let mut bna = armed();
+    bna.js(json!({ "action": "read", "value": "50" })).clear();
+
+    bna.external(json!({ "action": "confirm_read" }));
+
+    bna.expect_external(json!({ "event": "confirm_read" }));
+    bna.expect_no_more_messages();
+}
+
+#[test]
+fn unconfirmed_note_is_returned_after_the_escrow_timeout() {
+    let mut bna = armed();
+    bna.js(json!({ "action": "read", "value": "50" })).clear();
+
+    bna.advance(Duration::from_millis(29_999));
+    bna.expect_no_more_messages();
+
+    bna.advance(Duration::from_millis(1));
+    bna.expect_js_event("returned").with("value", "50");
+    bna.expect_external_event("returned").with("value", "50");
+    bna.expect_no_more_messages();
+}
+
+#[test]
+fn confirm_read_cancels_the_escrow_timeout() {
+    let mut bna = armed();
+    bna.js(json!({ "action": "read", "value": "50" }));
+    bna.external(json!({ "action": "confirm_read" })).clear();
+
+    bna.advance(Duration::from_secs(60));
+
+    bna.expect_no_more_messages();
+}
+
+#[test]
+fn a_new_note_restarts_the_escrow_timeout() {
+    let mut bna = armed();
+    bna.js(json!({ "action": "read", "value": "5" }));
+    bna.advance(Duration::from_secs(20));
+    bna.js(json!({ "action": "read", "value": "10" })).clear();
+
+    bna.advance(Duration::from_secs(20));
+    bna.expect_no_more_messages();
+
+    bna.advance(Duration::from_secs(10));
+    bna.expect_external_event("returned").with("value", "10");
+}
+
+#[test]
+fn error_toggles_between_error_and_disabled() {
+    let mut bna = armed();
+
+    bna.js(json!({ "action": "error" }));
+    bna.expect_js_event("statusChange").with("status", "ERROR");
+    bna.expect_external_event("statusChange").with("status", "ERROR");
+
+    bna.js(json!({ "action": "error" }));
+    bna.expect_js_event("statusChange").with("status", "DISABLED");
+    bna.expect_external_event("statusChange").with("status", "DISABLED");
+    bna.expect_no_more_messages();
+}
+
+#[test]
+fn enable_and_disable_are_rejected_in_error() {
+    let mut bna = in_error();
+
+    bna.external(json!({ "action": "enable" }));
+    bna.external(json!({ "action": "disable" }));
+    bna.expect_no_more_messages();
+
+    bna.external(json!({ "action": "query_status" }));
+    bna.expect_external_event("statusChange").with("status", "ERROR");
+}
+
+#[test]
+fn unknown_actions_are_answered_on_their_port() {
+    let mut bna = armed();
+
+    bna.external(json!({ "action": "self_destruct" }));
+    bna.js(json!({ "action": "self_destruct" }));
+    bna.external(json!({ "event": "enable" }));
+
+    bna.expect_external(json!({ "event": "error", "message": "unknown action 'self_destruct'" }));
+    bna.expect_external(json!({ "event": "error", "message": "missing action" }));
+    bna.expect_js(json!({ "event": "error", "message": "unknown action 'self_destruct'" }));
+    bna.expect_no_more_messages();
+}
+
+#[test]
+fn actions_are_listed() {
+    let actions = serde_json::to_value(BNAPlugin::ACTIONS).unwrap();
+
+    assert_eq!(actions[0], json!({
+        "origin": "js",
+        "name": "read",
+        "fields": [{ "name": "value", "type": "String", "required": true }],
+    }));
+    let names: Vec<_> = BNAPlugin::ACTIONS.iter().map(|a| a.name.as_ref()).collect();
+    assert_eq!(names, ["read", "error", "enable", "disable", "query_status", "confirm_read"]);
+}
+
+#[test]
+fn describe_is_answered_with_the_metadata() {
+    let mut bna = armed();
+
+    bna.external(json!({ "action": "describe" }));
+
+    let describe = bna.expect_external_event("describe").with("device_type", "bna").with("model", "BNA simulator");
+    let message = describe.message();
+    assert_eq!(message["actions"], serde_json::to_value(BNAPlugin::ACTIONS).unwrap());
+    assert_eq!(message["events"][0], json!({
+        "name": "statusChange",
+        "fields": [{ "name": "status", "type": "DeviceStatus", "required": true }],
+    }));
+    bna.expect_no_more_messages();
+}
+
+#[test]
+fn reload_keeps_the_note_in_escrow() {
+    let mut bna = armed();
+    bna.js(json!({ "action": "read", "value": "50" })).clear();
+    let before = bna.state();
+
+    bna.reload().unwrap();
+
+    assert_eq!(bna.state(), before);
+    bna.advance(Duration::from_secs(30));
+    bna.expect_external_event("returned").with("value", "50");
+}
+
+#[test]
+fn reads_are_counted_per_denomination() {
+    let mut bna = armed();
+
+    for value in ["20", "50", "20", "1000000"] {
+        bna.js(json!({ "action": "read", "value": value }));
+        bna.external(json!({ "action": "enable" }));
+    }
+
+    assert_eq!(bna.metric("bna_reads_total", &[("denomination", "20")]), Some(2.0));
+    assert_eq!(bna.metric("bna_reads_total", &[("denomination", "50")]), Some(1.0));
+    assert_eq!(bna.metric("bna_reads_total", &[("denomination", "100")]), None);
+    assert_eq!(bna.metric("bna_reads_total", &[("denomination", "other")]), Some(1.0));
+    assert_eq!(bna.metric("bna_reads_total", &[("denomination", "1000000")]), None);
+
+    bna.js(json!({ "action": "error" }));
+    assert_eq!(bna.metric("bna_error", &[]), Some(1.0));
+    bna.js(json!({ "action": "error" }));
+    assert_eq!(bna.metric("bna_error", &[]), Some(0.0));
+}
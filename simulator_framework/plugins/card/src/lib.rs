@@ -1,109 +1,117 @@
 // src/plugin.rs
 
 use plugin_interface::interface_for_plugin::Plugin;
-use plugin_interface::interface_for_server::CommunicationInterface;
+use plugin_interface::interface_for_server::{CommunicationInterface, Level};
+use plugin_interface::metadata::{self, Metadata};
+use plugin_interface::ui::{Control, UiDescription};
+use plugin_interface::protocol::{self, DeviceEvent, DeviceStatus};
+use plugin_interface::state_machine::StateMachine;
+use plugin_macros::plugin_actions;
 
 use serde_json::Value;
-use tokio_tungstenite::tungstenite::protocol::Message;
 
 
-#[derive(Clone, Debug, PartialEq)]
-enum DeviceStatus {
-    Armed,
-    Disabled,
-    Error,
-}
-
 #[derive(Clone)]
-pub struct BarcodePlugin {
-    status: DeviceStatus,
+pub struct CardPlugin {
+    machine: StateMachine<DeviceStatus>,
     numeric_value: String,
 }
 
-impl BarcodePlugin{
-    fn status_to_str(&self, status: &DeviceStatus) -> &'static str {
-        match status {
-            DeviceStatus::Armed => "ARMED",
-            DeviceStatus::Disabled => "DISABLED",
-            DeviceStatus::Error => "ERROR",
+// no status query and no escrow on this device
+#[plugin_actions]
+impl CardPlugin{
+    #[js_action("read")]
+    fn read<I: CommunicationInterface>(&mut self, interface: &I, value: String) {
+        self.numeric_value = value;
+        let read_msg = DeviceEvent::Read { value: self.numeric_value.clone() };
+
+        interface.send_to_external(protocol::to_message(&read_msg));
+    }
+
+    #[js_action("error")]
+    fn error<I: CommunicationInterface>(&mut self, interface: &I, message: &Value) {
+        self.fire(interface, "error", message);
+    }
+
+    #[external_action("enable")]
+    fn enable<I: CommunicationInterface>(&mut self, interface: &I, message: &Value) {
+        self.fire(interface, "enable", message);
+    }
+
+    #[external_action("disable")]
+    fn disable<I: CommunicationInterface>(&mut self, interface: &I, message: &Value) {
+        self.fire(interface, "disable", message);
+    }
+
+    fn fire<I: CommunicationInterface>(&mut self, interface: &I, trigger: &str, message: &Value) {
+        if let Err(e) = self.machine.fire(interface, trigger, message) {
+            interface.log(Level::WARN, &format!("Card: {}", e));
         }
     }
 }
 
-impl Plugin for BarcodePlugin {
+impl Plugin for CardPlugin {
 
     fn new() -> Self {
-        BarcodePlugin {
-            status:DeviceStatus::Disabled,
+        CardPlugin {
+            machine: StateMachine::device(),
             numeric_value: String::new(),
         }
     }
 
     fn handle_js_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
-        let json: Value = serde_json::from_str(&text).expect("Invalid JSON");
-
-        if let Some(action) = json.get("action").and_then(|v| v.as_str()) {
-
-            match action {
-                "read" => {
-                    if let Some(value) = json.get("value").and_then(|v| v.as_str()) 
-                    {
-                        self.numeric_value = value.to_string();
-                        let read_msg = serde_json::json!({ "event": "read", "value": value });
-
-                        interface.send_to_external(Message::Text(read_msg.to_string()));
-                    }
-                }
-                "error" => {
-
-                    if self.status == DeviceStatus::Error 
-                    {
-                        self.status = DeviceStatus::Disabled;
-                    } 
-                    else 
-                    {
-                        self.status = DeviceStatus::Error;
-                    }
-
-                    let status_msg = serde_json::json!({ "event": "statusChange", "status": self.status_to_str(&self.status) });
-
-                    interface.send_to_js_clients(Message::Text(status_msg.to_string()));
-                    interface.send_to_external(Message::Text(status_msg.to_string()));
-                }
-                _ => (),
-            }
-        }
+        self.dispatch_js_message(interface, text);
     }
 
     fn handle_external_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
-        let json: Value = serde_json::from_str(&text).expect("Invalid JSON");
-
-        if let Some(action) = json.get("action").and_then(|v| v.as_str()) 
-        {
-
-            if self.status == DeviceStatus::Error && (action == "enable" || action == "disable") {
-                return;
-            }
-
-            match action {
-                "enable" => {
-                    self.status = DeviceStatus::Armed;
-                    let status_msg = serde_json::json!({ "event": "statusChange", "status": "ARMED" });
-
-                    interface.send_to_js_clients(Message::Text(status_msg.to_string()));
-                    interface.send_to_external(Message::Text(status_msg.to_string()));
-                }
-                "disable" => {
-                    self.status = DeviceStatus::Disabled;
-                    let status_msg = serde_json::json!({ "event": "statusChange", "status": "DISABLED" });
-
-                    interface.send_to_js_clients(Message::Text(status_msg.to_string()));
-                    interface.send_to_external(Message::Text(status_msg.to_string()));
-                }
-                _ => (),
-            }
+        self.dispatch_external_message(interface, text);
+    }
+
+    fn snapshot(&self) -> Value {
+        serde_json::json!({
+            "status": self.machine.state(),
+            "value": self.numeric_value,
+        })
+    }
+
+    fn restore(&mut self, snapshot: &Value) {
+        if let Ok(status) = serde_json::from_value(snapshot["status"].clone()) {
+            self.machine.restore(status);
+        }
+        self.numeric_value = snapshot["value"].as_str().unwrap_or_default().to_string();
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            device_type: "card".to_string(),
+            model: "Card reader simulator".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            actions: Self::ACTIONS.to_vec(),
+            events: metadata::device_events(&["statusChange", "read", "error"]),
+        }
+    }
+
+    fn ui(&self) -> UiDescription {
+        UiDescription {
+            title: "Card Simulator".to_string(),
+            controls: vec![
+                Control::status_badge(),
+                Control::TextInput {
+                    label: "Value".to_string(),
+                    field: "value".to_string(),
+                    placeholder: "Enter numeric value".to_string(),
+                    max_length: Some(15),
+                },
+                Control::Button {
+                    label: "Send".to_string(),
+                    action: "read".to_string(),
+                    fields: vec!["value".to_string()],
+                    enabled_in: vec!["ARMED".to_string()],
+                },
+                Control::Button { label: "Toggle Error".to_string(), action: "error".to_string(), fields: Vec::new(), enabled_in: Vec::new() },
+                Control::event_log(),
+            ],
         }
-        
     }
 
 }
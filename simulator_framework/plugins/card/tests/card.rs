@@ -0,0 +1,93 @@
+use card_plugin::CardPlugin;
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_interface::interface_for_server::Level;
+use plugin_interface::ui::Control;
+use plugin_test_kit::{reader, PluginHarness};
+
+use serde_json::json;
+
+// the behaviour shared with the other readers is in plugin_test_kit::reader
+
+#[test]
+fn enable_arms_the_device_on_both_ports() {
+    reader::enable_arms_the_device_on_both_ports::<CardPlugin>();
+}
+
+#[test]
+fn disable_disarms_the_device_on_both_ports() {
+    reader::disable_disarms_the_device_on_both_ports::<CardPlugin>();
+}
+
+#[test]
+fn read_sends_the_card_to_the_host_only() {
+    reader::read_sends_the_value_to_the_host_only::<CardPlugin>("4111111111111111");
+}
+
+#[test]
+fn read_does_not_change_the_status() {
+    reader::read_does_not_change_the_status::<CardPlugin>();
+}
+
+#[test]
+fn read_without_value_is_answered_with_an_error() {
+    reader::read_without_value_is_answered_with_an_error::<CardPlugin>();
+}
+
+#[test]
+fn error_toggles_between_error_and_disabled() {
+    reader::error_toggles_between_error_and_disabled::<CardPlugin>();
+}
+
+#[test]
+fn enable_and_disable_are_rejected_in_error() {
+    reader::enable_and_disable_are_rejected_in_error::<CardPlugin>();
+}
+
+#[test]
+fn unknown_actions_are_answered_on_their_port() {
+    reader::unknown_actions_are_answered_on_their_port::<CardPlugin>();
+}
+
+#[test]
+fn describes_itself_as_a_card_reader() {
+    let mut card = PluginHarness::<CardPlugin>::new();
+
+    card.external(json!({ "action": "describe" }));
+
+    card.expect_external_event("describe").with("device_type", "card").with("model", "Card reader simulator");
+}
+
+#[test]
+fn the_ui_takes_card_numbers_of_up_to_15_digits() {
+    let ui = CardPlugin::new().ui();
+
+    assert_eq!(ui.title, "Card Simulator");
+    let input = ui.controls.iter().find_map(|control| match control {
+        Control::TextInput { field, max_length, .. } => Some((field.as_str(), *max_length)),
+        _ => None,
+    });
+    assert_eq!(input, Some(("value", Some(15))));
+}
+
+#[test]
+fn rejected_transitions_are_logged_as_the_card_reader() {
+    let mut card = reader::armed::<CardPlugin>();
+    card.js(json!({ "action": "error" }));
+
+    card.external(json!({ "action": "enable" }));
+
+    let logs = card.logs();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].0, Level::WARN);
+    assert!(logs[0].1.starts_with("Card: "), "{}", logs[0].1);
+}
+
+#[test]
+fn the_last_card_read_survives_a_reload() {
+    let mut card = reader::armed::<CardPlugin>();
+    card.js(json!({ "action": "read", "value": "4111111111111111" })).clear();
+
+    card.reload().unwrap();
+
+    assert_eq!(card.state(), json!({ "status": "ARMED", "value": "4111111111111111" }));
+}
@@ -0,0 +1,225 @@
+// src/definition.rs
+//
+// Device definition files are TOML documents, e.g. a barcode reader:
+//
+//     name = "Barcode"
+//     initial = "DISABLED"
+//     states = ["DISABLED", "ARMED", "ERROR"]
+//
+//     [[actions]]
+//     port = "external"
+//     action = "enable"
+//     from = ["DISABLED", "ARMED"]
+//     to = "ARMED"
+//
+//     [[actions]]
+//     port = "js"
+//     action = "read"
+//     requires = ["value"]
+//     emit = [{ port = "external", message = { event = "read", value = "{{value}}" } }]
+//
+// An action is taken when a message {"action": ...} arrives on its port while the
+// device is in one of the `from` states (any state when missing) and carries every
+// field in `requires`. When several actions match, the first one listed wins.
+// Taking an action sends its `emit` messages, then moves to `to` if given, which
+// sends statusChange to both sides.
+//
+// In emitted messages "{{field}}" is replaced by that field of the incoming message,
+// and "{{state}}" by the state the device is in once the action is taken.
+//
+// describe reports the actions and the events they emit, the device as `device_type`
+// ("declarative" when missing), `name` and `version`.
+
+use plugin_interface::actions::{Action, Field, Origin};
+use plugin_interface::metadata::{Event, Metadata};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Port {
+    Js,
+    External,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Destination {
+    Js,
+    External,
+    Both,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Emit {
+    pub port: Destination,
+    pub message: Value,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ActionDefinition {
+    pub port: Port,
+    pub action: String,
+    #[serde(default)]
+    pub from: Option<Vec<String>>,
+    #[serde(default)]
+    pub to: Option<String>,
+    #[serde(default)]
+    pub requires: Vec<String>,
+    #[serde(default)]
+    pub emit: Vec<Emit>,
+}
+
+impl ActionDefinition {
+    pub fn matches(&self, port: Port, action: &str) -> bool {
+        self.port == port && self.action == action
+    }
+
+    pub fn allows(&self, state: &str, message: &Value) -> bool {
+        self.allowed_in(state) && self.missing_field(message).is_none()
+    }
+
+    pub fn allowed_in(&self, state: &str) -> bool {
+        self.from.as_ref().map(|from| from.iter().any(|s| s == state)).unwrap_or(true)
+    }
+
+    // the first of `requires` the message does not have
+    pub fn missing_field(&self, message: &Value) -> Option<&str> {
+        self.requires.iter().find(|field| message.get(field.as_str()).is_none()).map(String::as_str)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Definition {
+    pub name: String,
+    #[serde(default)]
+    pub device_type: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    pub initial: String,
+    // when given, every state used by the actions must be listed
+    #[serde(default)]
+    pub states: Vec<String>,
+    #[serde(default)]
+    pub actions: Vec<ActionDefinition>,
+}
+
+impl Definition {
+    pub fn knows_state(&self, state: &str) -> bool {
+        self.states.is_empty() || self.states.iter().any(|s| s == state)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.knows_state(&self.initial) {
+            return Err(format!("initial state {} is not in states", self.initial));
+        }
+
+        for action in &self.actions {
+            let used = action.from.iter().flatten().chain(action.to.iter());
+            if let Some(state) = used.into_iter().find(|s| !self.knows_state(s)) {
+                return Err(format!("action '{}' uses state {} which is not in states", action.action, state));
+            }
+        }
+        Ok(())
+    }
+
+    // Fields are untyped: they are whatever JSON the message carries
+    pub fn metadata(&self) -> Metadata {
+        let field = |name: &str| Field { name: name.to_string().into(), ty: "Value".into(), required: true };
+
+        let mut actions: Vec<Action> = Vec::new();
+        let mut events: Vec<Event> = Vec::new();
+        for action in &self.actions {
+            let origin = match action.port {
+                Port::Js => Origin::Js,
+                Port::External => Origin::External,
+            };
+            // actions listed more than once, for different states, are described once
+            if !actions.iter().any(|a| a.origin == origin && a.name == action.action) {
+                let fields: Vec<Field> = action.requires.iter().map(|name| field(name)).collect();
+                actions.push(Action { origin, name: action.action.clone().into(), fields: fields.into() });
+            }
+
+            let mut sent: Vec<(&str, Vec<Field>)> = Vec::new();
+            for emit in &action.emit {
+                let Some(name) = emit.message.get("event").and_then(Value::as_str) else { continue };
+                let keys = emit.message.as_object().into_iter().flatten().map(|(key, _)| key);
+                sent.push((name, keys.filter(|key| key.as_str() != "event").map(|key| field(key)).collect()));
+            }
+            if action.to.is_some() {
+                sent.push(("statusChange", vec![field("status")]));
+            }
+
+            for (name, fields) in sent {
+                if !events.iter().any(|e| e.name == name) {
+                    events.push(Event { name: name.to_string().into(), fields: fields.into() });
+                }
+            }
+        }
+
+        Metadata {
+            device_type: self.device_type.clone().unwrap_or_else(|| "declarative".to_string()),
+            model: self.name.clone(),
+            version: self.version.clone().unwrap_or_default(),
+            actions,
+            events,
+        }
+    }
+}
+
+pub fn parse_definition(text: &str) -> Result<Definition, String> {
+    let definition: Definition = toml::from_str(text).map_err(|e| e.to_string())?;
+    definition.validate()?;
+    Ok(definition)
+}
+
+pub fn load_definition(path: impl AsRef<Path>) -> Result<Definition, String> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_definition(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Fills the "{{...}}" placeholders of an emitted message
+pub fn render(template: &Value, message: &Value, state: &str) -> Value {
+    let lookup = |name: &str| -> Value {
+        match name {
+            "state" => Value::String(state.to_string()),
+            _ => message.get(name).cloned().unwrap_or(Value::Null),
+        }
+    };
+
+    match template {
+        Value::String(text) => {
+            // a placeholder on its own keeps the type of the field
+            if let Some(name) = text.strip_prefix("{{").and_then(|t| t.strip_suffix("}}")) {
+                if !name.contains("{{") {
+                    return lookup(name.trim());
+                }
+            }
+
+            let mut rendered = String::new();
+            let mut rest = text.as_str();
+            while let Some(start) = rest.find("{{") {
+                let Some(end) = rest[start..].find("}}") else { break };
+                rendered.push_str(&rest[..start]);
+                match lookup(rest[start + 2..start + end].trim()) {
+                    Value::String(s) => rendered.push_str(&s),
+                    Value::Null => (),
+                    other => rendered.push_str(&other.to_string()),
+                }
+                rest = &rest[start + end + 2..];
+            }
+            rendered.push_str(rest);
+            Value::String(rendered)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(|item| render(item, message, state)).collect()),
+        Value::Object(fields) => Value::Object(
+            fields.iter().map(|(key, value)| (key.clone(), render(value, message, state))).collect(),
+        ),
+        other => other.clone(),
+    }
+}
@@ -0,0 +1,203 @@
+// src/plugin.rs
+//
+// A device described by a definition file instead of Rust code, see definition.rs.
+// new() takes the file from SIMULATOR_DEVICE_DEFINITION, or device.toml next to the
+// executable; the simulator hands over the one in its config with open(). With hot
+// reload on it is read again whenever it changes on disk, the device keeps its state
+// if the new definition still has it.
+
+use plugin_interface::actions;
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_interface::interface_for_server::{CommunicationInterface, Level};
+use plugin_interface::metadata::Metadata;
+use plugin_interface::state_machine::StateMachine;
+
+use serde_json::Value;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use std::path::{Path, PathBuf};
+
+pub mod definition;
+
+use definition::{load_definition, render, Definition, Destination, Port};
+
+pub const DEFINITION_ENV: &str = "SIMULATOR_DEVICE_DEFINITION";
+
+pub struct DeclarativePlugin {
+    // None when the definition was handed over directly
+    path: Option<PathBuf>,
+    definition: Definition,
+    machine: StateMachine<String>,
+}
+
+impl DeclarativePlugin {
+    pub fn from_definition(definition: Definition) -> Self {
+        let machine = build_machine(&definition, definition.initial.clone());
+        DeclarativePlugin { path: None, definition, machine }
+    }
+
+    // The definition is read again from `path` on hot reload
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let mut plugin = DeclarativePlugin::from_definition(load_definition(path)?);
+        plugin.path = Some(path.to_path_buf());
+        Ok(plugin)
+    }
+
+    // Like from_path, but a file that does not load leaves the device empty, until hot
+    // reload finds a definition that does
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        match DeclarativePlugin::from_path(&path) {
+            Ok(plugin) => {
+                tracing::info!("Device definition loaded: {} ({})", plugin.definition.name, path.display());
+                plugin
+            }
+            // without a definition the device does nothing until the file shows up
+            Err(e) => {
+                tracing::warn!("Device definition not loaded: {}", e);
+                let mut plugin = DeclarativePlugin::from_definition(Definition {
+                    name: "empty".to_string(),
+                    device_type: None,
+                    version: None,
+                    initial: "DISABLED".to_string(),
+                    states: Vec::new(),
+                    actions: Vec::new(),
+                });
+                plugin.path = Some(path);
+                plugin
+            }
+        }
+    }
+
+    pub fn definition(&self) -> &Definition {
+        &self.definition
+    }
+
+    fn handle_message<I: CommunicationInterface>(&mut self, interface: &I, port: Port, text: String) {
+        let (action, json) = match actions::parse_action(&text) {
+            Ok(parsed) => parsed,
+            Err(e) => return reply_error(interface, port, &e),
+        };
+
+        let state = self.machine.state().clone();
+        let Some(taken) = self
+            .definition
+            .actions
+            .iter()
+            .find(|a| a.matches(port, &action) && a.allows(&state, &json))
+            .cloned()
+        else {
+            // answered like the plugins written in Rust, see plugin_macros
+            let candidates: Vec<_> = self.definition.actions.iter().filter(|a| a.matches(port, &action)).collect();
+            let error = if candidates.is_empty() {
+                format!("unknown action '{}'", action)
+            } else if let Some(candidate) = candidates.iter().find(|a| a.allowed_in(&state)) {
+                format!("{}: missing field '{}'", action, candidate.missing_field(&json).unwrap_or_default())
+            } else {
+                interface.log(Level::INFO, &format!("{}: '{}' is not allowed in state {}", self.definition.name, action, state));
+                format!("{}: not allowed in state {}", action, state)
+            };
+            return reply_error(interface, port, &error);
+        };
+
+        let next_state = taken.to.clone().unwrap_or(state);
+        for emit in &taken.emit {
+            let message = Message::Text(render(&emit.message, &json, &next_state).to_string());
+            match emit.port {
+                Destination::Js => interface.send_to_js_clients(message),
+                Destination::External => interface.send_to_external(message),
+                Destination::Both => {
+                    interface.send_to_js_clients(message.clone());
+                    interface.send_to_external(message);
+                }
+            }
+        }
+
+        if taken.to.is_some() {
+            if let Err(e) = self.machine.fire(interface, &trigger(port, &action), &json) {
+                interface.log(Level::WARN, &format!("{}: {}", self.definition.name, e));
+            }
+        }
+    }
+}
+
+fn reply_error<I: CommunicationInterface>(interface: &I, port: Port, error: &str) {
+    let reply = actions::error_reply(error);
+    match port {
+        Port::Js => interface.send_to_js_clients(reply),
+        Port::External => interface.send_to_external(reply),
+    }
+}
+
+fn default_definition_path() -> PathBuf {
+    if let Ok(path) = std::env::var(DEFINITION_ENV) {
+        return PathBuf::from(path);
+    }
+    let exe_path = std::env::current_exe().expect("Failed to get current executable path");
+    exe_path.parent().unwrap().join("device.toml")
+}
+
+fn trigger(port: Port, action: &str) -> String {
+    format!("{:?}:{}", port, action)
+}
+
+// Only the actions that change state go through the state machine
+fn build_machine(definition: &Definition, state: String) -> StateMachine<String> {
+    let mut machine = StateMachine::new(state);
+    for action in &definition.actions {
+        let Some(to) = &action.to else { continue };
+
+        let requires = action.requires.clone();
+        let guard = move |message: &Value| requires.iter().all(|field| message.get(field).is_some());
+        let trigger = trigger(action.port, &action.action);
+        machine = match &action.from {
+            Some(from) => machine.guarded_transition(&trigger, from.clone(), to.clone(), guard),
+            None => machine.guarded_transition_from_any(&trigger, to.clone(), guard),
+        };
+    }
+    machine
+}
+
+impl Plugin for DeclarativePlugin {
+
+    fn new() -> Self {
+        DeclarativePlugin::open(default_definition_path())
+    }
+
+    fn handle_js_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
+        self.handle_message(interface, Port::Js, text);
+    }
+
+    fn handle_external_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
+        self.handle_message(interface, Port::External, text);
+    }
+
+    fn snapshot(&self) -> Value {
+        serde_json::json!({
+            "device": self.definition.name,
+            "status": self.machine.state(),
+        })
+    }
+
+    fn metadata(&self) -> Metadata {
+        self.definition.metadata()
+    }
+
+    fn artifact(&self) -> Option<PathBuf> {
+        self.path.clone()
+    }
+
+    fn reloaded(&self) -> Result<Self, String> {
+        let path = self.path.as_ref().ok_or("the definition was not loaded from a file")?;
+        DeclarativePlugin::from_path(path)
+    }
+
+    // a state the definition no longer has starts over from the initial one
+    fn restore(&mut self, snapshot: &Value) {
+        if let Some(state) = snapshot["status"].as_str().filter(|state| self.definition.knows_state(state)) {
+            self.machine = build_machine(&self.definition, state.to_string());
+        }
+    }
+
+}
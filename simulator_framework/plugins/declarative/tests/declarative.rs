@@ -0,0 +1,178 @@
+use declarative_plugin::definition::{parse_definition, render};
+use declarative_plugin::DeclarativePlugin;
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_test_kit::{PluginHarness, RecordingInterface};
+
+use serde_json::json;
+
+fn barcode() -> PluginHarness<DeclarativePlugin> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/barcode.toml");
+    PluginHarness::with_plugin(DeclarativePlugin::from_path(path).unwrap())
+}
+
+fn armed() -> PluginHarness<DeclarativePlugin> {
+    let mut device = barcode();
+    device.external(json!({ "action": "enable" })).clear();
+    device
+}
+
+#[test]
+fn loads_the_definition_from_its_file() {
+    let device = barcode();
+
+    assert_eq!(device.state(), json!({ "device": "Barcode", "status": "DISABLED" }));
+}
+
+#[test]
+fn transitions_send_status_changes_to_both_ports() {
+    let mut device = barcode();
+
+    device.external(json!({ "action": "enable" }));
+
+    device.expect_js_event("statusChange").with("status", "ARMED");
+    device.expect_external_event("statusChange").with("status", "ARMED");
+    device.expect_no_more_messages();
+}
+
+#[test]
+fn emitted_messages_are_filled_from_the_incoming_message() {
+    let mut device = armed();
+
+    device.js(json!({ "action": "read", "value": "8001234567890" }));
+
+    device.expect_external(json!({ "event": "read", "value": "8001234567890" }));
+    device.expect_no_more_messages();
+}
+
+#[test]
+fn actions_missing_a_required_field_are_answered_with_an_error() {
+    let mut device = armed();
+
+    device.js(json!({ "action": "read" }));
+
+    device.expect_js(json!({ "event": "error", "message": "read: missing field 'value'" }));
+    device.expect_no_more_messages();
+}
+
+#[test]
+fn the_first_matching_action_wins() {
+    let mut device = armed();
+
+    device.js(json!({ "action": "error" }));
+    device.expect_external_event("statusChange").with("status", "ERROR");
+
+    device.js(json!({ "action": "error" }));
+    device.expect_external_event("statusChange").with("status", "DISABLED");
+}
+
+#[test]
+fn actions_not_allowed_in_the_current_state_are_answered_with_an_error() {
+    let mut device = armed();
+    device.js(json!({ "action": "error" })).clear();
+
+    device.external(json!({ "action": "enable" }));
+    device.external(json!({ "action": "query_status" }));
+
+    device.expect_external(json!({ "event": "error", "message": "enable: not allowed in state ERROR" }));
+    device.expect_external(json!({ "event": "statusChange", "status": "ERROR" }));
+    device.expect_no_more_messages();
+}
+
+#[test]
+fn unknown_actions_are_answered_on_their_port() {
+    let mut device = armed();
+
+    device.external(json!({ "action": "confirm_read" }));
+    device.js(json!({ "action": "enable" }));
+
+    device.expect_external(json!({ "event": "error", "message": "unknown action 'confirm_read'" }));
+    device.expect_js(json!({ "event": "error", "message": "unknown action 'enable'" }));
+    device.expect_no_more_messages();
+}
+
+#[test]
+fn messages_that_are_not_actions_are_answered_with_an_error() {
+    let mut device = armed();
+    device.js(json!({ "value": "8001234567890" }));
+    device.expect_js_event("error").with("message", "missing action");
+    device.expect_no_more_messages();
+
+    let mut plugin = DeclarativePlugin::from_definition(parse_definition("name = \"Door\"\ninitial = \"CLOSED\"").unwrap());
+    let interface = RecordingInterface::new();
+    plugin.handle_external_message(&interface, "{ not json".to_string());
+    let sent = interface.take();
+    assert_eq!(sent.len(), 1);
+    assert!(sent[0].1.contains("invalid JSON"), "{:?}", sent);
+}
+
+#[test]
+fn definitions_can_be_handed_over_directly() {
+    let definition = parse_definition(
+        r#"
+        name = "Door"
+        initial = "CLOSED"
+
+        [[actions]]
+        port = "js"
+        action = "open"
+        to = "OPEN"
+        emit = [{ port = "both", message = { event = "opened", by = "{{user}}" } }]
+        "#,
+    )
+    .unwrap();
+    let door = DeclarativePlugin::from_definition(definition);
+
+    assert_eq!(door.snapshot()["status"], "CLOSED");
+    assert_eq!(door.definition().actions.len(), 1);
+}
+
+#[test]
+fn states_must_be_listed() {
+    let error = parse_definition(
+        r#"
+        name = "Door"
+        initial = "CLOSED"
+        states = ["CLOSED"]
+
+        [[actions]]
+        port = "js"
+        action = "open"
+        to = "OPEN"
+        "#,
+    )
+    .unwrap_err();
+
+    assert_eq!(error, "action 'open' uses state OPEN which is not in states");
+}
+
+#[test]
+fn placeholders_keep_the_field_type_unless_inside_text() {
+    let message = json!({ "amount": 20, "currency": "EUR" });
+    let template = json!({ "amount": "{{amount}}", "label": "{{amount}} {{currency}}", "state": "{{state}}", "missing": "{{nope}}" });
+
+    assert_eq!(
+        render(&template, &message, "ARMED"),
+        json!({ "amount": 20, "label": "20 EUR", "state": "ARMED", "missing": null })
+    );
+}
+
+#[test]
+fn describe_lists_the_actions_and_what_they_emit() {
+    let mut device = barcode();
+
+    device.external(json!({ "action": "describe" }));
+
+    let describe = device.expect_external_event("describe").with("device_type", "declarative").with("model", "Barcode");
+    let message = describe.message();
+    let actions: Vec<_> = message["actions"].as_array().unwrap().iter().map(|a| (a["origin"].clone(), a["name"].clone())).collect();
+    assert_eq!(actions, [
+        (json!("external"), json!("enable")),
+        (json!("external"), json!("disable")),
+        (json!("external"), json!("query_status")),
+        (json!("js"), json!("read")),
+        (json!("js"), json!("error")),
+    ]);
+    assert_eq!(message["actions"][3]["fields"], json!([{ "name": "value", "type": "Value", "required": true }]));
+    let events: Vec<_> = message["events"].as_array().unwrap().iter().map(|e| e["name"].clone()).collect();
+    assert_eq!(events, [json!("statusChange"), json!("read")]);
+}
@@ -0,0 +1,53 @@
+use declarative_plugin::DeclarativePlugin;
+use plugin_test_kit::PluginHarness;
+
+use serde_json::json;
+
+use std::fs;
+
+const FIRST: &str = r#"
+name = "Lamp"
+initial = "OFF"
+states = ["OFF", "ON"]
+
+[[actions]]
+port = "external"
+action = "switch_on"
+to = "ON"
+"#;
+
+const SECOND: &str = r#"
+name = "Lamp"
+initial = "OFF"
+states = ["OFF", "ON", "BROKEN"]
+
+[[actions]]
+port = "external"
+action = "switch_on"
+to = "BROKEN"
+"#;
+
+#[test]
+fn the_definition_is_reloaded_from_its_file() {
+    let path = std::env::temp_dir().join(format!("declarative_reload_{}.toml", std::process::id()));
+    fs::write(&path, FIRST).unwrap();
+    let mut lamp = PluginHarness::with_plugin(DeclarativePlugin::from_path(&path).unwrap());
+
+    lamp.external(json!({ "action": "switch_on" }));
+    lamp.expect_external_event("statusChange").with("status", "ON");
+
+    fs::write(&path, SECOND).unwrap();
+    lamp.reload().unwrap();
+    assert_eq!(lamp.state(), json!({ "device": "Lamp", "status": "ON" }));
+    lamp.external(json!({ "action": "switch_on" }));
+    lamp.expect_external_event("statusChange").with("status", "BROKEN");
+
+    // a broken file keeps the previous definition and state
+    fs::write(&path, "name = ").unwrap();
+    assert!(lamp.reload().is_err());
+    assert_eq!(lamp.state(), json!({ "device": "Lamp", "status": "BROKEN" }));
+    lamp.external(json!({ "action": "switch_on" }));
+    lamp.expect_external_event("statusChange").with("status", "BROKEN");
+
+    fs::remove_file(&path).unwrap();
+}
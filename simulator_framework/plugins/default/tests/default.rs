@@ -0,0 +1,16 @@
+use default_plugin::DefaultPlugin;
+use plugin_test_kit::PluginHarness;
+
+use serde_json::json;
+
+#[test]
+fn ignores_every_message() {
+    let mut plugin = PluginHarness::<DefaultPlugin>::new();
+
+    plugin.external(json!({ "action": "enable" }));
+    plugin.external(json!({ "action": "query_status" }));
+    plugin.js(json!({ "action": "read", "value": "20" }));
+    plugin.js(json!({ "action": "error" }));
+
+    plugin.expect_no_more_messages();
+}
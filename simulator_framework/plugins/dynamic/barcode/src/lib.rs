@@ -0,0 +1,5 @@
+// src/lib.rs
+
+use barcode_plugin::BarcodePlugin;
+
+plugin_interface::export_plugin!(BarcodePlugin);
@@ -0,0 +1,5 @@
+// src/lib.rs
+
+use bna_plugin::BNAPlugin;
+
+plugin_interface::export_plugin!(BNAPlugin);
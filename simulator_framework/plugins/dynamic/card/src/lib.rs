@@ -0,0 +1,5 @@
+// src/lib.rs
+
+use card_plugin::CardPlugin;
+
+plugin_interface::export_plugin!(CardPlugin);
@@ -0,0 +1,5 @@
+// src/lib.rs
+
+use default_plugin::DefaultPlugin;
+
+plugin_interface::export_plugin!(DefaultPlugin);
@@ -0,0 +1,335 @@
+// src/plugin.rs
+//
+// A device whose behaviour is a Rhai script, taken from SIMULATOR_SCRIPT or
+// device.rhai next to the executable by new(), or handed over with open(). The
+// script defines any of
+//
+//     fn on_js_message(message) { ... }        // message as a map
+//     fn on_external_message(message) { ... }
+//     fn on_timer(name) { ... }
+//
+// and can call send_to_js_clients(message), send_to_external(message),
+// set_timer(name, ms), cancel_timer(name), now_ms(), log(level, message), level
+// being "error", "warn", "info", "debug" or "trace", and count(name, labels, by) and
+// gauge(name, labels, value) for metrics of its own, labels being a map (or left out).
+// `this` is a map kept between
+// calls, and across reloads: with hot reload on the script is compiled again whenever
+// its file changes. fn init() is called once, before the first message, to fill it. fn describe() may
+// return the device's metadata as a map, see plugin_interface::metadata.
+//
+//     fn init() {
+//         this.notes = 0;
+//     }
+//
+//     fn on_js_message(message) {
+//         this.notes += 1;
+//         if this.notes % 3 == 0 {
+//             send_to_js_clients(#{ event: "rejected", value: message.value });
+//         } else {
+//             send_to_external(#{ event: "read", value: message.value });
+//         }
+//     }
+
+use plugin_interface::actions;
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_interface::interface_for_server::{CommunicationInterface, Level};
+use plugin_interface::metadata::Metadata;
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use serde_json::Value;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const SCRIPT_ENV: &str = "SIMULATOR_SCRIPT";
+
+// a script stuck in a loop is stopped after this many operations
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+// What the script asked for during a call, carried out once the call returns
+enum Command {
+    ToJs(String),
+    ToExternal(String),
+    SetTimer(String, Duration),
+    CancelTimer(String),
+    Log(Level, String),
+    Count(String, Vec<(String, String)>, f64),
+    Gauge(String, Vec<(String, String)>, f64),
+}
+
+#[derive(Default)]
+struct CallContext {
+    commands: Vec<Command>,
+    now: Duration,
+}
+
+pub struct ScriptPlugin {
+    engine: Engine,
+    context: Arc<Mutex<CallContext>>,
+    // None until a script compiles
+    ast: Option<AST>,
+    state: Dynamic,
+    initialized: bool,
+    // None when the script was handed over directly
+    path: Option<PathBuf>,
+}
+
+impl ScriptPlugin {
+    pub fn from_source(source: &str) -> Result<Self, String> {
+        let mut plugin = ScriptPlugin::empty(None);
+        plugin.ast = Some(plugin.engine.compile(source).map_err(|e| e.to_string())?);
+        Ok(plugin)
+    }
+
+    // The script is compiled again from `path` on hot reload
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let mut plugin = ScriptPlugin::empty(Some(path.to_path_buf()));
+        plugin.ast = Some(load_script(&plugin.engine, path)?);
+        Ok(plugin)
+    }
+
+    // Like from_path, but a script that does not compile leaves the device idle, until
+    // hot reload finds one that does
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        match ScriptPlugin::from_path(&path) {
+            Ok(plugin) => {
+                tracing::info!("Script loaded: {}", path.display());
+                plugin
+            }
+            // without a script the device does nothing until the file shows up
+            Err(e) => {
+                tracing::warn!("Script not loaded: {}", e);
+                ScriptPlugin::empty(Some(path))
+            }
+        }
+    }
+
+    fn empty(path: Option<PathBuf>) -> Self {
+        let context = Arc::new(Mutex::new(CallContext::default()));
+        ScriptPlugin {
+            engine: build_engine(&context),
+            context,
+            ast: None,
+            state: Dynamic::from_map(Map::new()),
+            initialized: false,
+            path,
+        }
+    }
+
+    fn call<I: CommunicationInterface>(&mut self, interface: &I, function: &str, argument: Dynamic) {
+        if self.ast.is_none() {
+            return;
+        }
+
+        self.context.lock().unwrap().now = interface.now();
+
+        if !self.initialized {
+            self.initialized = true;
+            self.invoke("init", Vec::new());
+        }
+        self.invoke(function, vec![argument]);
+
+        // what was sent before an error still goes out
+        let commands = std::mem::take(&mut self.context.lock().unwrap().commands);
+        for command in commands {
+            match command {
+                Command::ToJs(text) => interface.send_to_js_clients(Message::Text(text)),
+                Command::ToExternal(text) => interface.send_to_external(Message::Text(text)),
+                Command::SetTimer(name, delay) => interface.set_timer(&name, delay),
+                Command::CancelTimer(name) => interface.cancel_timer(&name),
+                Command::Log(level, message) => interface.log(level, &message),
+                Command::Count(name, labels, by) => interface.count(&name, &borrowed(&labels), by),
+                Command::Gauge(name, labels, value) => interface.gauge(&name, &borrowed(&labels), value),
+            }
+        }
+    }
+
+    // Functions the script does not define are skipped
+    fn invoke(&mut self, function: &str, arguments: Vec<Dynamic>) {
+        let Some(ast) = &self.ast else { return };
+        if !ast.iter_functions().any(|f| f.name == function && f.params.len() == arguments.len()) {
+            return;
+        }
+
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.state);
+        let result = self.engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), ast, function, arguments);
+        if let Err(e) = result {
+            tracing::warn!("Script error in {}: {}", function, e);
+        }
+    }
+
+}
+
+// The message as a map for the script; any JSON will do, it need not be an action
+fn to_message(text: &str) -> Result<Dynamic, String> {
+    let json: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e))?;
+    rhai::serde::to_dynamic(json).map_err(|e| e.to_string())
+}
+
+fn build_engine(context: &Arc<Mutex<CallContext>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    // the defaults of debug builds are too tight for messages built inside functions
+    engine.set_max_expr_depths(64, 64);
+
+    // maps and arrays are sent as JSON, anything else as its text
+    fn to_text(message: Dynamic) -> String {
+        if message.is_map() || message.is_array() {
+            rhai::serde::from_dynamic::<Value>(&message).map(|v| v.to_string()).unwrap_or_default()
+        } else {
+            message.to_string()
+        }
+    }
+
+    let ctx = context.clone();
+    engine.register_fn("send_to_js_clients", move |message: Dynamic| {
+        ctx.lock().unwrap().commands.push(Command::ToJs(to_text(message)));
+    });
+    let ctx = context.clone();
+    engine.register_fn("send_to_external", move |message: Dynamic| {
+        ctx.lock().unwrap().commands.push(Command::ToExternal(to_text(message)));
+    });
+    let ctx = context.clone();
+    engine.register_fn("set_timer", move |name: &str, ms: i64| {
+        let delay = Duration::from_millis(ms.max(0) as u64);
+        ctx.lock().unwrap().commands.push(Command::SetTimer(name.to_string(), delay));
+    });
+    let ctx = context.clone();
+    engine.register_fn("cancel_timer", move |name: &str| {
+        ctx.lock().unwrap().commands.push(Command::CancelTimer(name.to_string()));
+    });
+    let ctx = context.clone();
+    engine.register_fn("now_ms", move || ctx.lock().unwrap().now.as_millis() as i64);
+    let ctx = context.clone();
+    engine.register_fn("log", move |level: &str, message: Dynamic| {
+        let level = level.parse().unwrap_or(Level::INFO);
+        ctx.lock().unwrap().commands.push(Command::Log(level, to_text(message)));
+    });
+
+    // label values may be any type, they are taken as their text
+    fn labels(labels: Map) -> Vec<(String, String)> {
+        labels.into_iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+    // integers and floats alike, anything else stops the script
+    fn number(value: Dynamic) -> Result<f64, Box<EvalAltResult>> {
+        match value.as_float() {
+            Ok(value) => Ok(value),
+            Err(type_name) => value.as_int().map(|n| n as f64).map_err(|_| format!("{} is not a number", type_name).into()),
+        }
+    }
+
+    let ctx = context.clone();
+    engine.register_fn("count", move |name: &str, labels_map: Map, by: Dynamic| {
+        let command = Command::Count(name.to_string(), labels(labels_map), number(by)?);
+        ctx.lock().unwrap().commands.push(command);
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    let ctx = context.clone();
+    engine.register_fn("count", move |name: &str, by: Dynamic| {
+        let command = Command::Count(name.to_string(), Vec::new(), number(by)?);
+        ctx.lock().unwrap().commands.push(command);
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    let ctx = context.clone();
+    engine.register_fn("gauge", move |name: &str, labels_map: Map, value: Dynamic| {
+        let command = Command::Gauge(name.to_string(), labels(labels_map), number(value)?);
+        ctx.lock().unwrap().commands.push(command);
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    let ctx = context.clone();
+    engine.register_fn("gauge", move |name: &str, value: Dynamic| {
+        let command = Command::Gauge(name.to_string(), Vec::new(), number(value)?);
+        ctx.lock().unwrap().commands.push(command);
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+
+    engine
+}
+
+fn borrowed(labels: &[(String, String)]) -> Vec<(&str, &str)> {
+    labels.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect()
+}
+
+fn load_script(engine: &Engine, path: &Path) -> Result<AST, String> {
+    let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    engine.compile(source).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn default_script_path() -> PathBuf {
+    if let Ok(path) = std::env::var(SCRIPT_ENV) {
+        return PathBuf::from(path);
+    }
+    let exe_path = std::env::current_exe().expect("Failed to get current executable path");
+    exe_path.parent().unwrap().join("device.rhai")
+}
+
+impl Plugin for ScriptPlugin {
+
+    fn new() -> Self {
+        ScriptPlugin::open(default_script_path())
+    }
+
+    fn handle_js_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
+        match to_message(&text) {
+            Ok(message) => self.call(interface, "on_js_message", message),
+            Err(e) => interface.send_to_js_clients(actions::error_reply(&e)),
+        }
+    }
+
+    fn handle_external_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
+        match to_message(&text) {
+            Ok(message) => self.call(interface, "on_external_message", message),
+            Err(e) => interface.send_to_external(actions::error_reply(&e)),
+        }
+    }
+
+    fn handle_timer<I: CommunicationInterface>(&mut self, interface: &I, name: &str) {
+        self.call(interface, "on_timer", Dynamic::from(name.to_string()));
+    }
+
+    // the script's `this`
+    fn snapshot(&self) -> Value {
+        rhai::serde::from_dynamic(&self.state).unwrap_or(Value::Null)
+    }
+
+    fn artifact(&self) -> Option<PathBuf> {
+        self.path.clone()
+    }
+
+    fn reloaded(&self) -> Result<Self, String> {
+        let path = self.path.as_ref().ok_or("the script was not loaded from a file")?;
+        ScriptPlugin::from_path(path)
+    }
+
+    // init() is not called again, unless `this` is still empty
+    fn restore(&mut self, snapshot: &Value) {
+        if snapshot.as_object().is_some_and(|this| !this.is_empty()) {
+            if let Ok(state) = rhai::serde::to_dynamic(snapshot) {
+                self.state = state;
+                self.initialized = true;
+            }
+        }
+    }
+
+    fn metadata(&self) -> Metadata {
+        let Some(ast) = &self.ast else { return Metadata::default() };
+        if !ast.iter_functions().any(|f| f.name == "describe" && f.params.is_empty()) {
+            return Metadata::default();
+        }
+
+        let options = CallFnOptions::new().eval_ast(false);
+        let described = self.engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), ast, "describe", ());
+        match described.map_err(|e| e.to_string()).and_then(|d| rhai::serde::from_dynamic(&d).map_err(|e| e.to_string())) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                tracing::warn!("Script error in describe: {}", e);
+                Metadata::default()
+            }
+        }
+    }
+
+}
@@ -0,0 +1,52 @@
+use plugin_test_kit::PluginHarness;
+use script_plugin::ScriptPlugin;
+
+use serde_json::json;
+
+use std::fs;
+
+const FIRST: &str = r#"
+fn init() { this.count = 0; }
+fn on_external_message(message) {
+    this.count += 1;
+    send_to_external(#{ event: "count", value: this.count });
+}
+"#;
+
+const SECOND: &str = r#"
+fn on_external_message(message) {
+    this.count += 10;
+    send_to_external(#{ event: "count", value: this.count });
+    if message.action == "crash" {
+        throw "crashed";
+    }
+}
+"#;
+
+#[test]
+fn the_script_is_reloaded_and_keeps_its_state() {
+    let path = std::env::temp_dir().join(format!("script_reload_{}.rhai", std::process::id()));
+    fs::write(&path, FIRST).unwrap();
+    let mut device = PluginHarness::with_plugin(ScriptPlugin::from_path(&path).unwrap());
+
+    device.external(json!({ "action": "tick" }));
+    device.expect_external(json!({ "event": "count", "value": 1 }));
+
+    fs::write(&path, SECOND).unwrap();
+    device.reload().unwrap();
+    device.external(json!({ "action": "tick" }));
+    device.expect_external(json!({ "event": "count", "value": 11 }));
+
+    // a script error is reported, what was sent before it still goes out
+    device.external(json!({ "action": "crash" }));
+    device.expect_external(json!({ "event": "count", "value": 21 }));
+
+    // a script that does not compile keeps the previous one
+    fs::write(&path, "fn on_external_message(").unwrap();
+    assert!(device.reload().is_err());
+    device.external(json!({ "action": "tick" }));
+    device.expect_external(json!({ "event": "count", "value": 31 }));
+    assert_eq!(device.state(), json!({ "count": 31 }));
+
+    fs::remove_file(&path).unwrap();
+}
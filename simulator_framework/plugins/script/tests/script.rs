@@ -0,0 +1,128 @@
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_interface::interface_for_server::Level;
+use plugin_test_kit::{PluginHarness, Port, RecordingInterface};
+use script_plugin::ScriptPlugin;
+
+use serde_json::json;
+
+use std::time::Duration;
+
+fn bna() -> PluginHarness<ScriptPlugin> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/bna.rhai");
+    PluginHarness::with_plugin(ScriptPlugin::from_path(path).unwrap())
+}
+
+fn armed() -> PluginHarness<ScriptPlugin> {
+    let mut device = bna();
+    device.external(json!({ "action": "enable" })).clear();
+    device
+}
+
+#[test]
+fn init_fills_the_state_before_the_first_message() {
+    let mut device = bna();
+
+    device.external(json!({ "action": "query_status" }));
+
+    device.expect_external(json!({ "event": "statusChange", "status": "DISABLED" }));
+    assert_eq!(device.state(), json!({ "status": "DISABLED", "notes": 0 }));
+}
+
+#[test]
+fn scripts_send_to_both_ports() {
+    let mut device = bna();
+
+    device.external(json!({ "action": "enable" }));
+
+    device.expect_js_event("statusChange").with("status", "ARMED");
+    device.expect_external_event("statusChange").with("status", "ARMED");
+    device.expect_no_more_messages();
+}
+
+#[test]
+fn every_third_note_is_rejected() {
+    let mut device = armed();
+
+    for value in ["10", "20"] {
+        device.js(json!({ "action": "read", "value": value }));
+        device.expect_external_event("read").with("value", value);
+        device.external(json!({ "action": "enable" })).clear();
+    }
+    device.js(json!({ "action": "read", "value": "50" }));
+
+    device.expect_js(json!({ "event": "rejected", "value": "50" }));
+    device.expect_no_more_messages();
+    assert_eq!(device.state()["notes"], json!(3));
+    assert_eq!(device.logs(), [(Level::INFO, "note 3 rejected".to_string())]);
+    assert_eq!(device.metric("script_notes_total", &[("value", "50")]), Some(1.0));
+}
+
+#[test]
+fn reads_carry_a_checksum() {
+    let mut device = armed();
+
+    device.js(json!({ "action": "read", "value": "1234" }));
+
+    device.expect_external(json!({ "event": "read", "value": "1234", "checksum": 0 }));
+}
+
+#[test]
+fn timers_fire_on_the_virtual_clock() {
+    let mut device = armed();
+    device.js(json!({ "action": "read", "value": "20" })).clear();
+
+    device.advance(Duration::from_secs(29)).expect_no_more_messages();
+    device.advance(Duration::from_secs(1));
+
+    device.expect_js(json!({ "event": "returned", "value": "20" }));
+    device.expect_external(json!({ "event": "returned", "value": "20" }));
+}
+
+#[test]
+fn cancelled_timers_do_not_fire() {
+    let mut device = armed();
+    device.js(json!({ "action": "read", "value": "20" }));
+    device.external(json!({ "action": "confirm_read" })).clear();
+
+    device.advance(Duration::from_secs(60));
+
+    device.expect_no_more_messages();
+}
+
+#[test]
+fn messages_the_script_does_not_handle_are_ignored() {
+    let mut device = armed();
+
+    device.external(json!({ "action": "dispense" }));
+    device.js(json!({ "action": "read" }));
+
+    device.expect_no_more_messages();
+}
+
+#[test]
+fn describe_returns_what_the_script_reports() {
+    let mut device = bna();
+
+    device.external(json!({ "action": "describe" }));
+
+    let describe = device.expect_external_event("describe").with("device_type", "bna").with("version", "1.0");
+    assert_eq!(describe.message()["actions"][4], json!({
+        "origin": "js",
+        "name": "read",
+        "fields": [{ "name": "value", "type": "String", "required": true }],
+    }));
+    assert_eq!(describe.message()["events"].as_array().unwrap().len(), 5);
+}
+
+#[test]
+fn invalid_json_is_answered_with_an_error() {
+    let mut plugin = ScriptPlugin::from_source("fn on_js_message(message) { send_to_js_clients(message); }").unwrap();
+    let interface = RecordingInterface::new();
+
+    plugin.handle_js_message(&interface, "{ not json".to_string());
+
+    let sent = interface.take();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, Port::Js);
+    assert!(sent[0].1.contains("invalid JSON"), "{:?}", sent);
+}
@@ -0,0 +1,444 @@
+// src/plugin.rs
+//
+// A device compiled to WebAssembly, taken from SIMULATOR_WASM_PLUGIN or device.wasm
+// next to the executable by new(), or handed over with open(), and run in a sandbox: every call into the guest gets a
+// budget of fuel and of wall time, and its memory is capped, see Limits. A guest that
+// traps or runs out of either is reported and started again from scratch, the host
+// carries on.
+//
+// Plain wasm32 modules and wasm32-wasi reactors (cdylib crates) are accepted. The guest
+// exports, all optional except memory and alloc:
+//
+//     memory
+//     alloc(len: i32) -> i32                       a buffer the host writes the next argument to
+//     init()                                       once, after instantiation
+//     handle_js_message(ptr: i32, len: i32)        the message as UTF-8 text
+//     handle_external_message(ptr: i32, len: i32)
+//     handle_timer(ptr: i32, len: i32)             the timer's name
+//     snapshot() -> i64                            ptr << 32 | len of its state as JSON
+//     restore(ptr: i32, len: i32)                  a snapshot to carry on from, after hot reload
+//     metadata() -> i64                            ptr << 32 | len of its metadata as JSON,
+//                                                  see plugin_interface::metadata
+//
+// and may import from the "simulator" module:
+//
+//     send_to_js_clients(ptr: i32, len: i32)
+//     send_to_external(ptr: i32, len: i32)
+//     now_ms() -> i64
+//     set_timer(name_ptr: i32, name_len: i32, delay_ms: i64)
+//     cancel_timer(name_ptr: i32, name_len: i32)
+//     log(level: i32, ptr: i32, len: i32)          1 (error) to 5 (trace), goes to the simulator's log
+//     count(name_ptr: i32, name_len: i32, labels_ptr: i32, labels_len: i32, by: f64)
+//     gauge(name_ptr: i32, name_len: i32, labels_ptr: i32, labels_len: i32, value: f64)
+//                                                  metrics of its own, labels as a JSON object
+//                                                  of strings (len 0 for none)
+
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_interface::interface_for_server::{self, CommunicationInterface, Level};
+use plugin_interface::metadata::Metadata;
+
+use serde_json::Value;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use wasmtime::{Caller, Config, Engine, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder};
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::WasiCtxBuilder;
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const WASM_PLUGIN_ENV: &str = "SIMULATOR_WASM_PLUGIN";
+
+// how often the engine's epoch moves, the resolution of Limits::time
+const TICK: Duration = Duration::from_millis(10);
+
+// What a single call into the guest may use
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub fuel: u64,
+    pub time: Duration,
+    // bytes of linear memory
+    pub memory: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            fuel: 10_000_000,
+            time: Duration::from_millis(200),
+            memory: 16 << 20,
+        }
+    }
+}
+
+// What the guest asked for during a call, carried out once the call returns
+enum Command {
+    ToJs(String),
+    ToExternal(String),
+    SetTimer(String, Duration),
+    CancelTimer(String),
+    Log(Level, String),
+    Count(String, Vec<(String, String)>, f64),
+    Gauge(String, Vec<(String, String)>, f64),
+}
+
+struct GuestState {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+    commands: Vec<Command>,
+    now: Duration,
+}
+
+struct Guest {
+    store: Store<GuestState>,
+    instance: Instance,
+    memory: Memory,
+}
+
+// Moves the engine's epoch forward until dropped
+struct Ticker {
+    stop: Arc<AtomicBool>,
+}
+
+impl Ticker {
+    fn start(engine: &Engine) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (engine, stopped) = (engine.clone(), stop.clone());
+        std::thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                std::thread::sleep(TICK);
+                engine.increment_epoch();
+            }
+        });
+        Ticker { stop }
+    }
+}
+
+impl Drop for Ticker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+pub struct WasmPlugin {
+    linker: Linker<GuestState>,
+    module: Option<Module>,
+    limits: Limits,
+    // None when the module was handed over directly
+    path: Option<PathBuf>,
+    // None until the module instantiates, and again after a trap
+    guest: RefCell<Option<Guest>>,
+    _ticker: Ticker,
+}
+
+impl WasmPlugin {
+    // `bytes` is a binary module or its text format
+    pub fn from_bytes(bytes: &[u8], limits: Limits) -> Result<Self, String> {
+        let mut plugin = WasmPlugin::empty(limits);
+        plugin.module = Some(Module::new(plugin.linker.engine(), bytes).map_err(|e| e.to_string())?);
+        *plugin.guest.get_mut() = Some(plugin.instantiate()?);
+        Ok(plugin)
+    }
+
+    // The module is loaded again from `path` on hot reload
+    pub fn from_file(path: impl AsRef<Path>, limits: Limits) -> Result<Self, String> {
+        let path = path.as_ref();
+        let error = |e: String| format!("{}: {}", path.display(), e);
+
+        let bytes = std::fs::read(path).map_err(|e| error(e.to_string()))?;
+        let mut plugin = WasmPlugin::from_bytes(&bytes, limits).map_err(error)?;
+        plugin.path = Some(path.to_path_buf());
+        Ok(plugin)
+    }
+
+    // Like from_file, but a module that does not load leaves the device idle, until hot
+    // reload finds one that does
+    pub fn open(path: impl Into<PathBuf>, limits: Limits) -> Self {
+        let path = path.into();
+        match WasmPlugin::from_file(&path, limits) {
+            Ok(plugin) => {
+                tracing::info!("WASM plugin loaded: {}", path.display());
+                plugin
+            }
+            // without a module the device does nothing, until hot reload finds one
+            Err(e) => {
+                tracing::warn!("WASM plugin not loaded: {}", e);
+                let mut plugin = WasmPlugin::empty(limits);
+                plugin.path = Some(path);
+                plugin
+            }
+        }
+    }
+
+    fn empty(limits: Limits) -> Self {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        config.epoch_interruption(true);
+        let engine = Engine::new(&config).expect("Failed to create the WebAssembly engine");
+
+        WasmPlugin {
+            _ticker: Ticker::start(&engine),
+            linker: build_linker(&engine),
+            module: None,
+            limits,
+            path: None,
+            guest: RefCell::new(None),
+        }
+    }
+
+    // A fresh budget for the next call
+    fn prepare(&self, store: &mut Store<GuestState>) {
+        // set_fuel only fails when fuel is not enabled
+        store.set_fuel(self.limits.fuel).unwrap();
+        store.set_epoch_deadline((self.limits.time.as_millis() / TICK.as_millis()).max(1) as u64);
+    }
+
+    fn instantiate(&self) -> Result<Guest, String> {
+        let Some(module) = &self.module else { return Err("no module loaded".to_string()) };
+
+        let state = GuestState {
+            wasi: WasiCtxBuilder::new().inherit_stdout().inherit_stderr().build_p1(),
+            limits: StoreLimitsBuilder::new().memory_size(self.limits.memory).build(),
+            commands: Vec::new(),
+            now: Duration::ZERO,
+        };
+        let mut store = Store::new(self.linker.engine(), state);
+        store.limiter(|state| &mut state.limits);
+        self.prepare(&mut store);
+
+        let instance = self.linker.instantiate(&mut store, module).map_err(|e| e.to_string())?;
+        let memory = instance.get_memory(&mut store, "memory").ok_or("the module exports no memory")?;
+        for function in ["_initialize", "init"] {
+            if let Ok(function) = instance.get_typed_func::<(), ()>(&mut store, function) {
+                function.call(&mut store, ()).map_err(|e| format!("init: {}", e))?;
+            }
+        }
+        Ok(Guest { store, instance, memory })
+    }
+
+    // Calls `function` with `argument`, returning what the guest asked for on the way,
+    // including before a trap
+    fn run(&mut self, function: &str, argument: &str, now: Duration) -> Vec<Command> {
+        if self.module.is_none() {
+            return Vec::new();
+        }
+        // after a trap the guest starts again from scratch
+        let guest = match self.guest.get_mut().take() {
+            Some(guest) => Ok(guest),
+            None => self.instantiate(),
+        };
+        let mut guest = match guest {
+            Ok(guest) => guest,
+            Err(e) => {
+                tracing::warn!("WASM plugin failed to start: {}", e);
+                return Vec::new();
+            }
+        };
+
+        guest.store.data_mut().now = now;
+        self.prepare(&mut guest.store);
+        let result = call_with_text(&mut guest, function, argument);
+
+        let commands = std::mem::take(&mut guest.store.data_mut().commands);
+        match result {
+            Ok(()) => *self.guest.get_mut() = Some(guest),
+            Err(e) => tracing::warn!("WASM plugin trapped in {}, restarting it: {:#}", function, e),
+        }
+        commands
+    }
+
+    // Calls an export returning ptr << 32 | len of some JSON, Null when there is none
+    fn read_json(&self, function: &str) -> Value {
+        let mut guest = self.guest.borrow_mut();
+        // asked before the first message, or right after a trap
+        if guest.is_none() && self.module.is_some() {
+            match self.instantiate() {
+                Ok(started) => *guest = Some(started),
+                Err(e) => tracing::warn!("WASM plugin failed to start: {}", e),
+            }
+        }
+        let Some(guest) = guest.as_mut() else { return Value::Null };
+        let Ok(export) = guest.instance.get_typed_func::<(), i64>(&mut guest.store, function) else {
+            return Value::Null;
+        };
+
+        self.prepare(&mut guest.store);
+        let Ok(packed) = export.call(&mut guest.store, ()) else { return Value::Null };
+        let (start, len) = ((packed as u64 >> 32) as usize, (packed as u64 & 0xffff_ffff) as usize);
+        guest
+            .memory
+            .data(&guest.store)
+            .get(start..start + len)
+            .and_then(|bytes| serde_json::from_slice(bytes).ok())
+            .unwrap_or(Value::Null)
+    }
+
+    fn call<I: CommunicationInterface>(&mut self, interface: &I, function: &str, argument: &str) {
+        for command in self.run(function, argument, interface.now()) {
+            match command {
+                Command::ToJs(text) => interface.send_to_js_clients(Message::Text(text)),
+                Command::ToExternal(text) => interface.send_to_external(Message::Text(text)),
+                Command::SetTimer(name, delay) => interface.set_timer(&name, delay),
+                Command::CancelTimer(name) => interface.cancel_timer(&name),
+                Command::Log(level, message) => interface.log(level, &message),
+                Command::Count(name, labels, by) => interface.count(&name, &borrowed(&labels), by),
+                Command::Gauge(name, labels, value) => interface.gauge(&name, &borrowed(&labels), value),
+            }
+        }
+    }
+}
+
+// Functions the guest does not export are skipped
+fn call_with_text(guest: &mut Guest, function: &str, text: &str) -> wasmtime::Result<()> {
+    let Ok(handler) = guest.instance.get_typed_func::<(i32, i32), ()>(&mut guest.store, function) else {
+        return Ok(());
+    };
+    let alloc = guest.instance.get_typed_func::<i32, i32>(&mut guest.store, "alloc")?;
+
+    let len = text.len() as i32;
+    let ptr = alloc.call(&mut guest.store, len)?;
+    guest.memory.write(&mut guest.store, ptr as u32 as usize, text.as_bytes())?;
+    handler.call(&mut guest.store, (ptr, len))
+}
+
+fn borrowed(labels: &[(String, String)]) -> Vec<(&str, &str)> {
+    labels.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect()
+}
+
+fn read_text(caller: &mut Caller<'_, GuestState>, ptr: i32, len: i32) -> wasmtime::Result<String> {
+    let memory = caller
+        .get_export("memory")
+        .and_then(|export| export.into_memory())
+        .ok_or_else(|| wasmtime::Error::msg("the module exports no memory"))?;
+    let (start, len) = (ptr as u32 as usize, len as u32 as usize);
+    let bytes = memory
+        .data(&caller)
+        .get(start..start + len)
+        .ok_or_else(|| wasmtime::Error::msg("text out of bounds"))?;
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn build_linker(engine: &Engine) -> Linker<GuestState> {
+    let mut linker = Linker::new(engine);
+    preview1::add_to_linker_sync(&mut linker, |state: &mut GuestState| &mut state.wasi)
+        .expect("Failed to add WASI to the linker");
+
+    // the names are unique, so defining them cannot fail
+    linker
+        .func_wrap("simulator", "send_to_js_clients", |mut caller: Caller<'_, GuestState>, ptr: i32, len: i32| {
+            let text = read_text(&mut caller, ptr, len)?;
+            caller.data_mut().commands.push(Command::ToJs(text));
+            Ok(())
+        })
+        .unwrap();
+    linker
+        .func_wrap("simulator", "send_to_external", |mut caller: Caller<'_, GuestState>, ptr: i32, len: i32| {
+            let text = read_text(&mut caller, ptr, len)?;
+            caller.data_mut().commands.push(Command::ToExternal(text));
+            Ok(())
+        })
+        .unwrap();
+    linker
+        .func_wrap("simulator", "now_ms", |caller: Caller<'_, GuestState>| caller.data().now.as_millis() as i64)
+        .unwrap();
+    linker
+        .func_wrap(
+            "simulator",
+            "set_timer",
+            |mut caller: Caller<'_, GuestState>, ptr: i32, len: i32, delay_ms: i64| {
+                let name = read_text(&mut caller, ptr, len)?;
+                let delay = Duration::from_millis(delay_ms.max(0) as u64);
+                caller.data_mut().commands.push(Command::SetTimer(name, delay));
+                Ok(())
+            },
+        )
+        .unwrap();
+    linker
+        .func_wrap("simulator", "cancel_timer", |mut caller: Caller<'_, GuestState>, ptr: i32, len: i32| {
+            let name = read_text(&mut caller, ptr, len)?;
+            caller.data_mut().commands.push(Command::CancelTimer(name));
+            Ok(())
+        })
+        .unwrap();
+    linker
+        .func_wrap("simulator", "log", |mut caller: Caller<'_, GuestState>, level: i32, ptr: i32, len: i32| {
+            let message = read_text(&mut caller, ptr, len)?;
+            let level = interface_for_server::level_from_number(level as i64).unwrap_or(Level::INFO);
+            caller.data_mut().commands.push(Command::Log(level, message));
+            Ok(())
+        })
+        .unwrap();
+    for kind in ["count", "gauge"] {
+        linker
+            .func_wrap(
+                "simulator",
+                kind,
+                move |mut caller: Caller<'_, GuestState>, name_ptr: i32, name_len: i32, labels_ptr: i32, labels_len: i32, value: f64| {
+                    let name = read_text(&mut caller, name_ptr, name_len)?;
+                    let labels = read_text(&mut caller, labels_ptr, labels_len)?;
+                    let command = match (kind, interface_for_server::labels_from_json(&labels)) {
+                        ("count", Ok(labels)) => Command::Count(name, labels, value),
+                        (_, Ok(labels)) => Command::Gauge(name, labels, value),
+                        (_, Err(e)) => Command::Log(Level::WARN, format!("Metric {} ignored, {}", name, e)),
+                    };
+                    caller.data_mut().commands.push(command);
+                    Ok(())
+                },
+            )
+            .unwrap();
+    }
+
+    linker
+}
+
+fn default_module_path() -> PathBuf {
+    if let Ok(path) = std::env::var(WASM_PLUGIN_ENV) {
+        return PathBuf::from(path);
+    }
+    let exe_path = std::env::current_exe().expect("Failed to get current executable path");
+    exe_path.parent().unwrap().join("device.wasm")
+}
+
+impl Plugin for WasmPlugin {
+
+    fn new() -> Self {
+        WasmPlugin::open(default_module_path(), Limits::default())
+    }
+
+    fn handle_js_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
+        self.call(interface, "handle_js_message", &text);
+    }
+
+    fn handle_external_message<I: CommunicationInterface>(&mut self, interface: &I, text: String) {
+        self.call(interface, "handle_external_message", &text);
+    }
+
+    fn handle_timer<I: CommunicationInterface>(&mut self, interface: &I, name: &str) {
+        self.call(interface, "handle_timer", name);
+    }
+
+    fn snapshot(&self) -> Value {
+        self.read_json("snapshot")
+    }
+
+    fn metadata(&self) -> Metadata {
+        serde_json::from_value(self.read_json("metadata")).unwrap_or_default()
+    }
+
+    fn artifact(&self) -> Option<PathBuf> {
+        self.path.clone()
+    }
+
+    fn reloaded(&self) -> Result<Self, String> {
+        let path = self.path.as_ref().ok_or("the module was not loaded from a file")?;
+        WasmPlugin::from_file(path, self.limits)
+    }
+
+    // nothing the guest sends while restoring goes out
+    fn restore(&mut self, snapshot: &Value) {
+        self.run("restore", &snapshot.to_string(), Duration::ZERO);
+    }
+
+}
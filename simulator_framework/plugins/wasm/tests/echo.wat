@@ -0,0 +1,43 @@
+;; A guest written by hand: external messages are echoed back to the host and start a
+;; one second timer, which sends a tick to the UI. JS messages never return. The state
+;; is the number of external messages seen.
+(module
+  (import "simulator" "send_to_js_clients" (func $send_to_js_clients (param i32 i32)))
+  (import "simulator" "send_to_external" (func $send_to_external (param i32 i32)))
+  (import "simulator" "set_timer" (func $set_timer (param i32 i32 i64)))
+
+  (memory (export "memory") 1)
+  (global $messages (mut i32) (i32.const 0))
+
+  (data (i32.const 0) "{\"event\":\"tick\"}")
+  (data (i32.const 16) "tick")
+  (data (i32.const 32) "{\"messages\":0}")
+  (data (i32.const 64) "{\"device_type\":\"echo\",\"model\":\"Echo guest\",\"version\":\"0.1.0\"}")
+
+  ;; every argument goes to the same buffer
+  (func (export "alloc") (param $len i32) (result i32)
+    (i32.const 1024))
+
+  (func (export "handle_external_message") (param $ptr i32) (param $len i32)
+    (global.set $messages (i32.add (global.get $messages) (i32.const 1)))
+    (call $send_to_external (local.get $ptr) (local.get $len))
+    (call $set_timer (i32.const 16) (i32.const 4) (i64.const 1000)))
+
+  (func (export "handle_js_message") (param $ptr i32) (param $len i32)
+    (loop $forever (br $forever)))
+
+  (func (export "handle_timer") (param $ptr i32) (param $len i32)
+    (call $send_to_js_clients (i32.const 0) (i32.const 16)))
+
+  ;; {"messages":N} for N below 10
+  (func (export "snapshot") (result i64)
+    (i32.store8 (i32.const 44) (i32.add (i32.const 48) (global.get $messages)))
+    (i64.const 137438953486)) ;; 32 << 32 | 14
+
+  (func (export "metadata") (result i64)
+    (i64.const 274877907005)) ;; 64 << 32 | 61
+
+  ;; the digit of {"messages":N}
+  (func (export "restore") (param $ptr i32) (param $len i32)
+    (global.set $messages (i32.sub (i32.load8_u (i32.add (local.get $ptr) (i32.const 12))) (i32.const 48))))
+)
@@ -0,0 +1,131 @@
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_test_kit::{PluginHarness, RecordingInterface};
+use wasm_plugin::{Limits, WasmPlugin};
+
+use serde_json::json;
+
+use std::time::{Duration, Instant};
+
+fn echo() -> PluginHarness<WasmPlugin> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/echo.wat");
+    PluginHarness::with_plugin(WasmPlugin::from_file(path, Limits::default()).unwrap())
+}
+
+#[test]
+fn messages_reach_the_guest_and_its_replies_the_host() {
+    let mut device = echo();
+
+    device.external(json!({ "action": "enable" }));
+
+    device.expect_external(json!({ "action": "enable" }));
+    device.expect_no_more_messages();
+    assert_eq!(device.state(), json!({ "messages": 1 }));
+}
+
+#[test]
+fn guests_set_timers_on_the_host() {
+    let mut device = echo();
+    device.external(json!({ "action": "enable" })).clear();
+
+    device.advance(Duration::from_millis(999)).expect_no_more_messages();
+    device.advance(Duration::from_millis(1));
+
+    device.expect_js(json!({ "event": "tick" }));
+}
+
+#[test]
+fn a_guest_that_never_returns_is_stopped_and_restarted() {
+    let mut device = echo();
+    device.external(json!({ "action": "enable" })).clear();
+
+    device.js(json!({ "action": "read" }));
+    device.external(json!({ "action": "disable" }));
+
+    device.expect_external(json!({ "action": "disable" }));
+    // started from scratch
+    assert_eq!(device.state(), json!({ "messages": 1 }));
+}
+
+#[test]
+fn reload_restores_the_guest_state() {
+    let mut device = echo();
+    device.external(json!({ "action": "enable" }));
+    device.external(json!({ "action": "enable" })).clear();
+
+    device.reload().unwrap();
+
+    assert_eq!(device.state(), json!({ "messages": 2 }));
+    device.external(json!({ "action": "disable" }));
+    assert_eq!(device.state(), json!({ "messages": 3 }));
+}
+
+#[test]
+fn the_guest_describes_itself() {
+    let mut device = echo();
+
+    device.external(json!({ "action": "describe" }));
+
+    device.expect_external_event("describe").with("device_type", "echo").with("model", "Echo guest");
+    device.expect_no_more_messages();
+}
+
+#[test]
+fn calls_are_limited_in_time_as_well_as_fuel() {
+    let limits = Limits { fuel: u64::MAX, time: Duration::from_millis(50), ..Limits::default() };
+    let module = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/echo.wat")).unwrap();
+    let mut device = WasmPlugin::from_bytes(&module, limits).unwrap();
+    let interface = RecordingInterface::new();
+
+    let started = Instant::now();
+    device.handle_js_message(&interface, "{}".to_string());
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+    device.handle_external_message(&interface, "{}".to_string());
+    assert_eq!(interface.take().len(), 1);
+}
+
+const GROW: &str = r#"
+(module
+  (import "simulator" "send_to_external" (func $send_to_external (param i32 i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "{\"grown\":true}{\"grown\":false}")
+  (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+  (func (export "handle_external_message") (param i32 i32)
+    (if (i32.eq (memory.grow (i32.const 64)) (i32.const -1))
+      (then (call $send_to_external (i32.const 14) (i32.const 15)))
+      (else (call $send_to_external (i32.const 0) (i32.const 14)))))
+)
+"#;
+
+#[test]
+fn memory_is_capped() {
+    let interface = RecordingInterface::new();
+    let mut small = WasmPlugin::from_bytes(GROW.as_bytes(), Limits { memory: 1 << 20, ..Limits::default() }).unwrap();
+    let mut large = WasmPlugin::from_bytes(GROW.as_bytes(), Limits::default()).unwrap();
+
+    small.handle_external_message(&interface, "{}".to_string());
+    large.handle_external_message(&interface, "{}".to_string());
+
+    let sent: Vec<String> = interface.take().into_iter().map(|(_, text)| text).collect();
+    assert_eq!(sent, vec![r#"{"grown":false}"#, r#"{"grown":true}"#]);
+}
+
+#[test]
+fn modules_that_do_not_fit_are_refused() {
+    let module = r#"(module (memory (export "memory") 32) (func (export "alloc") (param i32) (result i32) (i32.const 0)))"#;
+
+    assert!(WasmPlugin::from_bytes(module.as_bytes(), Limits { memory: 1 << 20, ..Limits::default() }).is_err());
+}
+
+#[test]
+fn a_rebuilt_module_that_does_not_load_is_refused() {
+    let path = std::env::temp_dir().join(format!("wasm_reload_{}.wat", std::process::id()));
+    std::fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/echo.wat"), &path).unwrap();
+    let device = WasmPlugin::from_file(&path, Limits::default()).unwrap();
+    assert_eq!(device.artifact(), Some(path.clone()));
+
+    std::fs::write(&path, "(module").unwrap();
+    assert!(device.reloaded().is_err());
+
+    std::fs::remove_file(&path).unwrap();
+}
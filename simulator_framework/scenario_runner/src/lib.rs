@@ -0,0 +1,38 @@
+// src/lib.rs
+
+use barcode_plugin::BarcodePlugin;
+use bna_plugin::BNAPlugin;
+use card_plugin::CardPlugin;
+use declarative_plugin::DeclarativePlugin;
+use default_plugin::DefaultPlugin;
+use script_plugin::ScriptPlugin;
+use wasm_plugin::{Limits, WasmPlugin};
+
+pub mod report;
+pub mod runner;
+pub mod scenario;
+pub mod target;
+
+use target::{InProcessTarget, Target};
+
+use std::path::Path;
+
+// Plugins a scenario can be run against in-process, by name
+pub const REGISTERED_PLUGINS: &[&str] = &["default", "barcode", "bna", "card", "declarative", "script", "wasm"];
+
+// `plugin_file` is the definition, script or module of the plugins that run one, see
+// Scenario::plugin_file
+pub fn in_process_target(plugin: &str, plugin_file: Option<&Path>) -> Result<Box<dyn Target>, String> {
+    let file = || plugin_file.ok_or_else(|| format!("the {} plugin needs plugin_file in the scenario", plugin));
+
+    Ok(match plugin {
+        "default" => Box::new(InProcessTarget::<DefaultPlugin>::new()),
+        "barcode" => Box::new(InProcessTarget::<BarcodePlugin>::new()),
+        "bna" => Box::new(InProcessTarget::<BNAPlugin>::new()),
+        "card" => Box::new(InProcessTarget::<CardPlugin>::new()),
+        "declarative" => Box::new(InProcessTarget::with_plugin(DeclarativePlugin::from_path(file()?)?)),
+        "script" => Box::new(InProcessTarget::with_plugin(ScriptPlugin::from_path(file()?)?)),
+        "wasm" => Box::new(InProcessTarget::with_plugin(WasmPlugin::from_file(file()?, Limits::default())?)),
+        _ => return Err(format!("unknown plugin '{}', registered plugins: {}", plugin, REGISTERED_PLUGINS.join(", "))),
+    })
+}
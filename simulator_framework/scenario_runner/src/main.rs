@@ -0,0 +1,142 @@
+// src/main.rs
+//
+// scenario_runner (--plugin <name> | [--js-url <url>] --external-url <url>)
+//                 [--junit <report.xml>] [--json <report.json>] <scenario.toml>...
+//
+// Runs every scenario against a fresh instance of the plugin, or against a running
+// simulator, and exits with 1 if any of them failed. Without --js-url the scenarios
+// cannot send ui messages.
+
+use scenario_runner::report::{json_summary, junit_xml};
+use scenario_runner::runner::{run_scenario, StepOutcome};
+use scenario_runner::in_process_target;
+use scenario_runner::scenario::{load_scenario, Scenario};
+use scenario_runner::target::{RemoteTarget, Target};
+
+use tracing_subscriber::filter::LevelFilter;
+
+use std::fs;
+use std::process::exit;
+
+const USAGE: &str = "usage: scenario_runner (--plugin <name> | [--js-url <url>] --external-url <url>) \
+                     [--junit <report.xml>] [--json <report.json>] <scenario.toml>...";
+
+struct Args {
+    plugin: Option<String>,
+    js_url: Option<String>,
+    external_url: Option<String>,
+    junit: Option<String>,
+    json: Option<String>,
+    scenarios: Vec<String>,
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        plugin: None,
+        js_url: None,
+        external_url: None,
+        junit: None,
+        json: None,
+        scenarios: Vec::new(),
+    };
+    let mut iter = std::env::args().skip(1);
+
+    while let Some(arg) = iter.next() {
+        let slot = match arg.as_str() {
+            "--plugin" => &mut args.plugin,
+            "--js-url" => &mut args.js_url,
+            "--external-url" => &mut args.external_url,
+            "--junit" => &mut args.junit,
+            "--json" => &mut args.json,
+            _ => {
+                args.scenarios.push(arg);
+                continue;
+            }
+        };
+        *slot = Some(iter.next().unwrap_or_else(|| {
+            eprintln!("{}", USAGE);
+            exit(2)
+        }));
+    }
+
+    args
+}
+
+async fn make_target(args: &Args, scenario: &Scenario) -> Result<Box<dyn Target>, String> {
+    match (&args.plugin, &args.external_url) {
+        (Some(plugin), _) => in_process_target(plugin, scenario.plugin_file.as_deref()),
+        (None, Some(external_url)) => {
+            Ok(Box::new(RemoteTarget::connect(args.js_url.as_deref(), external_url).await?))
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn write_report(path: &str, contents: &str) {
+    if let Err(e) = fs::write(path, contents) {
+        eprintln!("Unable to write report {}: {}", path, e);
+        exit(2);
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // what the plugins log goes to stderr, next to the report
+    tracing_subscriber::fmt().with_max_level(LevelFilter::WARN).with_writer(std::io::stderr).init();
+
+    let args = parse_args();
+    if args.scenarios.is_empty() {
+        eprintln!("{}", USAGE);
+        exit(2);
+    }
+
+    let mut results = Vec::new();
+
+    for path in &args.scenarios {
+        let scenario = match load_scenario(path) {
+            Ok(scenario) => scenario,
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(2);
+            }
+        };
+
+        let mut target = match make_target(&args, &scenario).await {
+            Ok(target) => target,
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(2);
+            }
+        };
+
+        let result = run_scenario(&scenario, target.as_mut()).await;
+
+        for step in &result.steps {
+            match &step.outcome {
+                StepOutcome::Passed => println!("  ok      {}", step.description),
+                StepOutcome::Failed(message) => println!("  FAILED  {}\n          {}", step.description, message),
+                StepOutcome::Skipped => println!("  skipped {}", step.description),
+            }
+        }
+
+        if result.passed() {
+            println!("PASS {} ({} ms)", result.name, result.duration.as_millis());
+        } else {
+            println!("FAIL {} ({} ms)", result.name, result.duration.as_millis());
+        }
+        results.push(result);
+    }
+
+    if let Some(path) = &args.junit {
+        write_report(path, &junit_xml(&results));
+    }
+    if let Some(path) = &args.json {
+        write_report(path, &serde_json::to_string_pretty(&json_summary(&results)).unwrap());
+    }
+
+    let failures = results.iter().filter(|r| !r.passed()).count();
+    println!("{} scenarios, {} failed", results.len(), failures);
+    if failures > 0 {
+        exit(1);
+    }
+}
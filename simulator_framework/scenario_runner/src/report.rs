@@ -0,0 +1,166 @@
+// src/report.rs
+//
+// Reports of scenario runs for CI (JUnit XML) and dashboards (JSON). In the JUnit
+// report every scenario is a test suite, with the message transcript as system-out,
+// holding one test case per step: a failed step is one failure.
+
+use plugin_manager::recorder::{Direction, Port};
+
+use serde_json::json;
+
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::runner::{ScenarioResult, StepOutcome};
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64())
+}
+
+// Characters XML 1.0 does not allow at all, not even escaped, are left out
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => {}
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn transcript_text(result: &ScenarioResult) -> String {
+    let mut text = String::new();
+    for record in &result.transcript {
+        let arrow = match record.direction {
+            Direction::Inbound => "->",
+            Direction::Outbound => "<-",
+        };
+        let port = match record.port {
+            Port::Js => "js",
+            Port::External => "external",
+        };
+        let _ = writeln!(text, "{} {} {:<8} {}", record.timestamp_ms, arrow, port, record.message);
+    }
+    text
+}
+
+pub fn junit_xml(results: &[ScenarioResult]) -> String {
+    let count = |outcome: fn(&StepOutcome) -> bool| -> usize {
+        results.iter().flat_map(|r| &r.steps).filter(|s| outcome(&s.outcome)).count()
+    };
+    let tests = results.iter().map(|r| r.steps.len()).sum::<usize>();
+    let failures = count(|o| matches!(o, StepOutcome::Failed(_)));
+    let skipped = count(|o| *o == StepOutcome::Skipped);
+    let total: Duration = results.iter().map(|r| r.duration).sum();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites name=\"scenarios\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{}\">",
+        tests, failures, skipped, seconds(total)
+    );
+
+    for result in results {
+        let name = escape_xml(&result.name);
+        let step_failures = result.steps.iter().filter(|s| matches!(s.outcome, StepOutcome::Failed(_))).count();
+        let step_skipped = result.steps.iter().filter(|s| s.outcome == StepOutcome::Skipped).count();
+
+        let _ = writeln!(
+            xml,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{}\">",
+            name,
+            result.steps.len(),
+            step_failures,
+            step_skipped,
+            seconds(result.duration)
+        );
+
+        for (index, step) in result.steps.iter().enumerate() {
+            let _ = write!(
+                xml,
+                "    <testcase classname=\"{}\" name=\"{}\" time=\"{}\"",
+                name,
+                escape_xml(&format!("step {}: {}", index + 1, step.description)),
+                seconds(step.duration)
+            );
+            match &step.outcome {
+                StepOutcome::Passed => {
+                    let _ = writeln!(xml, "/>");
+                }
+                StepOutcome::Failed(message) => {
+                    let _ = writeln!(xml, ">");
+                    let _ = writeln!(
+                        xml,
+                        "      <failure message=\"{}\">{}</failure>",
+                        escape_xml(message),
+                        escape_xml(&format!("{}: {}", step.description, message))
+                    );
+                    let _ = writeln!(xml, "    </testcase>");
+                }
+                StepOutcome::Skipped => {
+                    let _ = writeln!(xml, ">");
+                    let _ = writeln!(xml, "      <skipped/>");
+                    let _ = writeln!(xml, "    </testcase>");
+                }
+            }
+        }
+
+        let _ = writeln!(xml, "    <system-out>{}</system-out>", escape_xml(&transcript_text(result)));
+        let _ = writeln!(xml, "  </testsuite>");
+    }
+
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+pub fn json_summary(results: &[ScenarioResult]) -> serde_json::Value {
+    let scenarios: Vec<_> = results
+        .iter()
+        .map(|result| {
+            let steps: Vec<_> = result
+                .steps
+                .iter()
+                .map(|step| {
+                    let (outcome, message) = match &step.outcome {
+                        StepOutcome::Passed => ("passed", None),
+                        StepOutcome::Failed(message) => ("failed", Some(message.clone())),
+                        StepOutcome::Skipped => ("skipped", None),
+                    };
+                    json!({
+                        "description": step.description,
+                        "outcome": outcome,
+                        "message": message,
+                        "duration_ms": step.duration.as_millis() as u64,
+                    })
+                })
+                .collect();
+
+            json!({
+                "name": result.name,
+                "passed": result.passed(),
+                "duration_ms": result.duration.as_millis() as u64,
+                "failure": result.failure().map(|(_, message)| message),
+                "steps": steps,
+                "transcript": result.transcript,
+            })
+        })
+        .collect();
+
+    let failed = results.iter().filter(|r| !r.passed()).count();
+    let total: Duration = results.iter().map(|r| r.duration).sum();
+
+    json!({
+        "scenarios": results.len(),
+        "passed": results.len() - failed,
+        "failed": failed,
+        "duration_ms": total.as_millis() as u64,
+        "results": scenarios,
+    })
+}
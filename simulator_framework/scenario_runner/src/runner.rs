@@ -0,0 +1,123 @@
+// src/runner.rs
+
+use plugin_manager::recorder::{Direction, Port, TraceRecord};
+
+use tokio::time::Instant;
+
+use std::time::Duration;
+
+use crate::scenario::{Expectation, Scenario, Step};
+use crate::target::Target;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StepOutcome {
+    Passed,
+    Failed(String),
+    // steps after a failure are not executed
+    Skipped,
+}
+
+#[derive(Clone, Debug)]
+pub struct StepResult {
+    pub description: String,
+    pub duration: Duration,
+    pub outcome: StepOutcome,
+}
+
+#[derive(Clone, Debug)]
+pub struct ScenarioResult {
+    pub name: String,
+    pub duration: Duration,
+    pub steps: Vec<StepResult>,
+    // every message sent to and received from the target, directions as seen by the simulator
+    pub transcript: Vec<TraceRecord>,
+}
+
+impl ScenarioResult {
+    pub fn passed(&self) -> bool {
+        self.failure().is_none()
+    }
+
+    pub fn failure(&self) -> Option<(&StepResult, &str)> {
+        self.steps.iter().find_map(|step| match &step.outcome {
+            StepOutcome::Failed(message) => Some((step, message.as_str())),
+            _ => None,
+        })
+    }
+}
+
+pub async fn run_scenario(scenario: &Scenario, target: &mut dyn Target) -> ScenarioResult {
+    let started = Instant::now();
+    let mut transcript = Vec::new();
+    let mut steps = Vec::new();
+    let mut failed = false;
+
+    for step in &scenario.steps {
+        let description = step.describe();
+
+        if failed {
+            steps.push(StepResult { description, duration: Duration::ZERO, outcome: StepOutcome::Skipped });
+            continue;
+        }
+
+        let step_started = Instant::now();
+        let outcome = match run_step(step, target, &mut transcript).await {
+            Ok(()) => StepOutcome::Passed,
+            Err(message) => {
+                failed = true;
+                StepOutcome::Failed(message)
+            }
+        };
+
+        steps.push(StepResult { description, duration: step_started.elapsed(), outcome });
+    }
+
+    ScenarioResult {
+        name: scenario.name.clone(),
+        duration: started.elapsed(),
+        steps,
+        transcript,
+    }
+}
+
+async fn run_step(step: &Step, target: &mut dyn Target, transcript: &mut Vec<TraceRecord>) -> Result<(), String> {
+    match step {
+        Step::Ui(message) => send(target, Port::Js, message.to_string(), transcript).await,
+        Step::Host(message) => send(target, Port::External, message.to_string(), transcript).await,
+        Step::Expect(expectation) => expect(target, expectation, transcript).await,
+        Step::WaitMs(ms) => {
+            tokio::time::sleep(Duration::from_millis(*ms)).await;
+            Ok(())
+        }
+        Step::AdvanceMs(ms) => target.advance(Duration::from_millis(*ms)).await,
+    }
+}
+
+async fn send(target: &mut dyn Target, port: Port, text: String, transcript: &mut Vec<TraceRecord>) -> Result<(), String> {
+    transcript.push(TraceRecord::now(Direction::Inbound, port, 0, text.clone()));
+    target.send(port, text).await
+}
+
+// Messages that do not match are skipped, the step fails only if the match never shows up
+async fn expect(target: &mut dyn Target, expectation: &Expectation, transcript: &mut Vec<TraceRecord>) -> Result<(), String> {
+    let deadline = Instant::now() + expectation.timeout();
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+
+        match target.receive(remaining).await {
+            Some((port, text)) => {
+                transcript.push(TraceRecord::now(Direction::Outbound, port, 0, text.clone()));
+                if expectation.is_met_by(port, &text) {
+                    return Ok(());
+                }
+            }
+            None => {
+                return Err(format!(
+                    "no message matching {} on {:?} port within {} ms",
+                    expectation.message, expectation.port, expectation.timeout_ms
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+// src/scenario.rs
+//
+// Scenario files are TOML documents with a name and a list of steps, e.g.
+//
+//     name = "BNA accepts a 20"
+//
+//     [[steps]]
+//     host = { action = "enable" }
+//
+//     [[steps]]
+//     expect = { port = "external", message = { event = "statusChange", status = "ARMED" } }
+//
+//     [[steps]]
+//     ui = { action = "read", value = "20" }
+//
+// `ui` messages are sent on the js port as the simulator UI would send them, `host`
+// messages on the external port as the host application would. `expect` waits up to
+// `timeout_ms` for a message on `port` containing every field of `message`.
+// `wait_ms` sleeps in real time, `advance_ms` moves the plugin's virtual clock and
+// fires the timers that expire on the way (in-process runs only).
+//
+// The declarative, script and wasm plugins run the device definition, script or module
+// given by `plugin_file`, relative to the scenario file.
+
+use plugin_manager::recorder::Port;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    #[serde(default)]
+    pub plugin_file: Option<PathBuf>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    Ui(Value),
+    Host(Value),
+    Expect(Expectation),
+    WaitMs(u64),
+    AdvanceMs(u64),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Expectation {
+    pub port: Port,
+    pub message: Value,
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_timeout_ms() -> u64 {
+    1000
+}
+
+impl Expectation {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
+    pub fn is_met_by(&self, port: Port, text: &str) -> bool {
+        port == self.port
+            && serde_json::from_str::<Value>(text)
+                .map(|actual| contains(&self.message, &actual))
+                .unwrap_or(false)
+    }
+}
+
+impl Step {
+    pub fn describe(&self) -> String {
+        match self {
+            Step::Ui(message) => format!("ui {}", message),
+            Step::Host(message) => format!("host {}", message),
+            Step::Expect(expectation) => format!("expect {:?} {}", expectation.port, expectation.message),
+            Step::WaitMs(ms) => format!("wait {} ms", ms),
+            Step::AdvanceMs(ms) => format!("advance clock {} ms", ms),
+        }
+    }
+}
+
+// Objects match when every expected field is present and matches, anything else must be equal
+pub fn contains(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => expected
+            .iter()
+            .all(|(key, value)| actual.get(key).map(|a| contains(value, a)).unwrap_or(false)),
+        _ => expected == actual,
+    }
+}
+
+pub fn load_scenario(path: impl AsRef<Path>) -> Result<Scenario, String> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut scenario: Scenario = toml::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    scenario.plugin_file = scenario.plugin_file.map(|file| dir.join(file));
+    Ok(scenario)
+}
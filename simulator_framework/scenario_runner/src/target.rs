@@ -0,0 +1,148 @@
+// src/target.rs
+//
+// Where a scenario is executed: a plugin driven in-process, or a running
+// simulator reached through its external WebSocket port, and its js port if it serves one.
+
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_interface::clock::VirtualClock;
+use plugin_manager::capture::CapturingInterface;
+use plugin_manager::recorder::Port;
+use plugin_manager::PluginManager;
+
+use futures_util::stream::SplitSink;
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[async_trait::async_trait]
+pub trait Target: Send {
+    async fn send(&mut self, port: Port, text: String) -> Result<(), String>;
+
+    // Next message sent by the simulator on either port, None if nothing arrives in time
+    async fn receive(&mut self, timeout: Duration) -> Option<(Port, String)>;
+
+    // Moves the simulator's clock forward, firing the timers that expire on the way
+    async fn advance(&mut self, by: Duration) -> Result<(), String>;
+}
+
+pub struct InProcessTarget<P: Plugin> {
+    interface: Arc<CapturingInterface>,
+    plugin_manager: PluginManager<CapturingInterface, P>,
+    pending: VecDeque<(Port, String)>,
+}
+
+impl<P: Plugin> InProcessTarget<P> {
+    // the plugin runs on a virtual clock, only advance() moves it
+    pub fn new() -> Self {
+        let interface = Arc::new(CapturingInterface::new());
+        let plugin_manager = PluginManager::with_virtual_clock(interface.clone(), Arc::new(VirtualClock::new()));
+
+        InProcessTarget { interface, plugin_manager, pending: VecDeque::new() }
+    }
+
+    // For plugins built from a file instead of P::new()
+    pub fn with_plugin(plugin: P) -> Self {
+        let interface = Arc::new(CapturingInterface::new());
+        let plugin_manager = PluginManager::with_plugin(interface.clone(), plugin, Arc::new(VirtualClock::new()));
+
+        InProcessTarget { interface, plugin_manager, pending: VecDeque::new() }
+    }
+}
+
+impl<P: Plugin> Default for InProcessTarget<P> {
+    fn default() -> Self {
+        InProcessTarget::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: Plugin + Send> Target for InProcessTarget<P> {
+    async fn send(&mut self, port: Port, text: String) -> Result<(), String> {
+        match port {
+            Port::Js => self.plugin_manager.handle_js_message(text),
+            Port::External => self.plugin_manager.handle_external_message(text),
+        }
+        self.pending.extend(self.interface.take());
+        Ok(())
+    }
+
+    // the plugin answers synchronously, so there is never anything worth waiting for
+    async fn receive(&mut self, _timeout: Duration) -> Option<(Port, String)> {
+        self.pending.pop_front()
+    }
+
+    async fn advance(&mut self, by: Duration) -> Result<(), String> {
+        self.plugin_manager.advance(by);
+        self.pending.extend(self.interface.take());
+        Ok(())
+    }
+}
+
+type Writer = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+
+pub struct RemoteTarget {
+    // None without a js url: the scenario cannot send ui messages
+    js_writer: Option<Writer>,
+    external_writer: Writer,
+    received: UnboundedReceiver<(Port, String)>,
+}
+
+impl RemoteTarget {
+    // The simulator accepts one client per port: the scenario takes the place of the UI
+    // and of the host, so close them first. A second js client is refused, the
+    // connection is dropped before the handshake and connect() fails.
+    // Simulators that serve no js port are reached without a js url.
+    pub async fn connect(js_url: Option<&str>, external_url: &str) -> Result<Self, String> {
+        let (tx, received) = mpsc::unbounded_channel();
+
+        let js_writer = match js_url {
+            Some(url) => Some(Self::open(Port::Js, url, &tx).await?),
+            None => None,
+        };
+        let external_writer = Self::open(Port::External, external_url, &tx).await?;
+        Ok(RemoteTarget { js_writer, external_writer, received })
+    }
+
+    // what arrives on the connection goes to `tx`, tagged with its port
+    async fn open(port: Port, url: &str, tx: &mpsc::UnboundedSender<(Port, String)>) -> Result<Writer, String> {
+        let (ws_stream, _) = connect_async(url).await.map_err(|e| format!("{}: {}", url, e))?;
+        let (writer, mut reader) = ws_stream.split();
+
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(msg)) = reader.next().await {
+                if let Message::Text(text) = msg {
+                    if tx.send((port, text)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(writer)
+    }
+}
+
+#[async_trait::async_trait]
+impl Target for RemoteTarget {
+    async fn send(&mut self, port: Port, text: String) -> Result<(), String> {
+        let writer = match port {
+            Port::Js => self.js_writer.as_mut().ok_or("ui messages need the simulator's js port, see --js-url")?,
+            Port::External => &mut self.external_writer,
+        };
+        writer.send(Message::Text(text)).await.map_err(|e| e.to_string())
+    }
+
+    async fn receive(&mut self, timeout: Duration) -> Option<(Port, String)> {
+        tokio::time::timeout(timeout, self.received.recv()).await.ok().flatten()
+    }
+
+    async fn advance(&mut self, _by: Duration) -> Result<(), String> {
+        Err("a running simulator uses the real clock, advance_ms needs an in-process plugin".to_string())
+    }
+}
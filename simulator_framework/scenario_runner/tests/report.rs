@@ -0,0 +1,77 @@
+use scenario_runner::report::{json_summary, junit_xml};
+use scenario_runner::runner::{ScenarioResult, StepOutcome, StepResult};
+
+use plugin_manager::recorder::{Direction, Port, TraceRecord};
+
+use std::time::Duration;
+
+fn step(description: &str, outcome: StepOutcome) -> StepResult {
+    StepResult { description: description.to_string(), duration: Duration::from_millis(10), outcome }
+}
+
+fn failed_scenario() -> ScenarioResult {
+    ScenarioResult {
+        name: "bna <accepts> note".to_string(),
+        duration: Duration::from_millis(30),
+        steps: vec![
+            step("send enable", StepOutcome::Passed),
+            step("expect \"ARMED\"", StepOutcome::Failed("got \u{1b}[31mERROR\u{0}".to_string())),
+            step("send disable", StepOutcome::Skipped),
+        ],
+        transcript: vec![TraceRecord {
+            timestamp_ms: 1,
+            direction: Direction::Inbound,
+            port: Port::External,
+            client_id: 0,
+            message: "{\"action\":\"enable\"}\u{7}".to_string(),
+        }],
+    }
+}
+
+fn passed_scenario() -> ScenarioResult {
+    ScenarioResult {
+        name: "card".to_string(),
+        duration: Duration::from_millis(10),
+        steps: vec![step("send insert", StepOutcome::Passed)],
+        transcript: Vec::new(),
+    }
+}
+
+#[test]
+fn a_failed_step_is_one_failure() {
+    let xml = junit_xml(&[failed_scenario(), passed_scenario()]);
+
+    assert!(xml.contains("<testsuites name=\"scenarios\" tests=\"4\" failures=\"1\" skipped=\"1\""), "{}", xml);
+    assert!(xml.contains("<testsuite name=\"bna &lt;accepts&gt; note\" tests=\"3\" failures=\"1\" skipped=\"1\""), "{}", xml);
+    assert!(xml.contains("<testsuite name=\"card\" tests=\"1\" failures=\"0\" skipped=\"0\""), "{}", xml);
+    assert_eq!(xml.matches("<failure ").count(), 1);
+    assert_eq!(xml.matches("<skipped/>").count(), 1);
+    assert!(xml.contains("name=\"step 2: expect &quot;ARMED&quot;\""));
+}
+
+#[test]
+fn characters_xml_does_not_allow_are_left_out() {
+    let xml = junit_xml(&[failed_scenario()]);
+
+    assert!(!xml.chars().any(|c| c.is_control() && c != '\n'), "{:?}", xml);
+    assert!(xml.contains("<failure message=\"got [31mERROR\">"));
+    assert!(xml.contains("{&quot;action&quot;:&quot;enable&quot;}\n</system-out>"));
+}
+
+#[test]
+fn the_json_summary_counts_scenarios() {
+    let summary = json_summary(&[failed_scenario(), passed_scenario()]);
+
+    assert_eq!(summary["scenarios"], 2);
+    assert_eq!(summary["passed"], 1);
+    assert_eq!(summary["failed"], 1);
+    assert_eq!(summary["duration_ms"], 40);
+
+    let failed = &summary["results"][0];
+    assert_eq!(failed["passed"], false);
+    assert_eq!(failed["failure"], "got \u{1b}[31mERROR\u{0}");
+    let outcomes: Vec<_> = failed["steps"].as_array().unwrap().iter().map(|s| s["outcome"].as_str().unwrap()).collect();
+    assert_eq!(outcomes, ["passed", "failed", "skipped"]);
+    assert_eq!(failed["transcript"][0]["port"], "external");
+    assert_eq!(summary["results"][1]["failure"], serde_json::Value::Null);
+}
@@ -0,0 +1,68 @@
+use scenario_runner::in_process_target;
+use scenario_runner::runner::{run_scenario, ScenarioResult};
+use scenario_runner::scenario::load_scenario;
+use scenario_runner::target::{InProcessTarget, RemoteTarget, Target};
+
+use bna_plugin::BNAPlugin;
+use plugin_manager::recorder::Port;
+use simulator_server::SimulatorServer;
+
+use std::path::{Path, PathBuf};
+
+fn scenario_path(file: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios").join(file)
+}
+
+fn assert_passed(result: &ScenarioResult) {
+    assert!(result.passed(), "{}: {:?}", result.name, result.failure().map(|(step, message)| (&step.description, message)));
+    assert!(!result.steps.is_empty());
+}
+
+async fn run(file: &str) {
+    let scenario = load_scenario(scenario_path(file)).unwrap();
+
+    assert_passed(&run_scenario(&scenario, &mut InProcessTarget::<BNAPlugin>::new()).await);
+}
+
+#[tokio::test]
+async fn bna_accepts_note() {
+    run("bna_accepts_note.toml").await;
+}
+
+#[tokio::test]
+async fn bna_escrow_timeout() {
+    run("bna_escrow_timeout.toml").await;
+}
+
+#[tokio::test]
+async fn declarative_plugins_run_the_definition_named_by_the_scenario() {
+    let scenario = load_scenario(scenario_path("declarative_barcode.toml")).unwrap();
+    let mut target = in_process_target("declarative", scenario.plugin_file.as_deref()).unwrap();
+
+    assert_passed(&run_scenario(&scenario, target.as_mut()).await);
+}
+
+#[test]
+fn plugins_that_run_a_file_need_one() {
+    for plugin in ["declarative", "script", "wasm"] {
+        let error = in_process_target(plugin, None).err().unwrap();
+        assert!(error.contains("plugin_file"), "{}", error);
+    }
+    assert!(in_process_target("made_up", None).err().unwrap().contains("registered plugins"));
+}
+
+#[tokio::test]
+async fn a_simulator_without_a_js_port_is_reached_on_the_external_one() {
+    let simulator = SimulatorServer::builder().plugin::<BNAPlugin>().external_port(0).start().await.unwrap();
+    let url = format!("ws://{}", simulator.external_addr());
+
+    let mut target = RemoteTarget::connect(None, &url).await.unwrap();
+    target.send(Port::External, r#"{"action": "enable"}"#.to_string()).await.unwrap();
+    let error = target.send(Port::Js, r#"{"action": "read", "value": "20"}"#.to_string()).await.unwrap_err();
+
+    assert!(error.contains("js port"), "{}", error);
+    let (port, text) = target.receive(std::time::Duration::from_secs(5)).await.unwrap();
+    assert_eq!(port, Port::External);
+    assert!(text.contains("ARMED"), "{}", text);
+    simulator.shutdown().await;
+}
@@ -0,0 +1,153 @@
+// src/connection.rs
+//
+// The connection to the simulator's external port, kept open in a background task:
+// when it drops, requests still waiting for an answer fail and the task reconnects.
+
+use crate::{ClientError, ClientEvent, ClientOptions};
+
+use plugin_interface::protocol::{self, DeviceEvent, DeviceStatus};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc, oneshot, Notify};
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+pub(crate) type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// The protocol has no request ids: a request is answered by the first event of the
+// kind it expects, requests waiting for the same kind are answered in order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Expected {
+    StatusChange,
+    ConfirmRead,
+}
+
+impl Expected {
+    fn matches(&self, event: &DeviceEvent) -> bool {
+        matches!(
+            (self, event),
+            (Expected::StatusChange, DeviceEvent::StatusChange { .. }) | (Expected::ConfirmRead, DeviceEvent::ConfirmRead)
+        )
+    }
+}
+
+pub(crate) struct Pending {
+    pub(crate) id: u64,
+    pub(crate) expects: Expected,
+    pub(crate) reply: oneshot::Sender<Result<DeviceEvent, ClientError>>,
+}
+
+pub(crate) struct Shared {
+    pub(crate) url: String,
+    pub(crate) options: ClientOptions,
+    // None while disconnected
+    pub(crate) writer: Mutex<Option<mpsc::UnboundedSender<Message>>>,
+    pub(crate) pending: Mutex<VecDeque<Pending>>,
+    // reads not collected by wait_for_read yet
+    pub(crate) reads: Mutex<VecDeque<String>>,
+    // also notified when the connection drops
+    pub(crate) read_arrived: Notify,
+    pub(crate) last_status: Mutex<Option<DeviceStatus>>,
+    pub(crate) events: broadcast::Sender<ClientEvent>,
+}
+
+impl Shared {
+    fn dispatch(&self, text: &str) {
+        let Some(event) = protocol::parse::<DeviceEvent>(text) else {
+            return;
+        };
+
+        match &event {
+            DeviceEvent::StatusChange { status } => *self.last_status.lock().unwrap() = Some(*status),
+            DeviceEvent::Read { value } => {
+                self.reads.lock().unwrap().push_back(value.clone());
+                self.read_arrived.notify_waiters();
+            }
+            _ => (),
+        }
+
+        let answered = {
+            let mut pending = self.pending.lock().unwrap();
+            pending
+                .iter()
+                .position(|p| p.expects.matches(&event))
+                .and_then(|position| pending.remove(position))
+        };
+        if let Some(request) = answered {
+            let _ = request.reply.send(Ok(event.clone()));
+        }
+
+        // nobody listening is fine
+        let _ = self.events.send(ClientEvent::Device(event));
+    }
+
+    fn fail_pending(&self) {
+        for request in self.pending.lock().unwrap().drain(..) {
+            let _ = request.reply.send(Err(ClientError::Disconnected));
+        }
+    }
+}
+
+// A connection requests can already be sent on, see attach()
+pub(crate) struct Connection {
+    ws_stream: Socket,
+    outgoing: mpsc::UnboundedReceiver<Message>,
+}
+
+pub(crate) fn attach(shared: &Shared, ws_stream: Socket) -> Connection {
+    let (tx, outgoing) = mpsc::unbounded_channel();
+    *shared.writer.lock().unwrap() = Some(tx);
+    let _ = shared.events.send(ClientEvent::Connected);
+    Connection { ws_stream, outgoing }
+}
+
+pub(crate) async fn run(shared: Arc<Shared>, connection: Connection) {
+    let mut connection = Some(connection);
+    loop {
+        let current = match connection.take() {
+            Some(current) => current,
+            None => {
+                tokio::time::sleep(shared.options.reconnect_delay).await;
+                match connect_async(shared.url.as_str()).await {
+                    Ok((ws_stream, _)) => attach(&shared, ws_stream),
+                    Err(_) => continue,
+                }
+            }
+        };
+
+        serve(&shared, current).await;
+    }
+}
+
+// Returns when the connection is lost
+async fn serve(shared: &Shared, connection: Connection) {
+    let Connection { ws_stream, outgoing: mut rx } = connection;
+    let (mut write_to_socket, mut read_from_socket) = ws_stream.split();
+
+    loop {
+        tokio::select! {
+            outgoing = rx.recv() => match outgoing {
+                Some(msg) => {
+                    if write_to_socket.send(msg).await.is_err() {
+                        break;
+                    }
+                }
+                None => break,
+            },
+            incoming = read_from_socket.next() => match incoming {
+                Some(Ok(Message::Text(text))) => shared.dispatch(&text),
+                Some(Ok(_)) => (),
+                _ => break,
+            },
+        }
+    }
+
+    *shared.writer.lock().unwrap() = None;
+    shared.fail_pending();
+    shared.read_arrived.notify_waiters();
+    let _ = shared.events.send(ClientEvent::Disconnected);
+}
@@ -0,0 +1,213 @@
+// src/lib.rs
+//
+// Client for the external port of a simulator, the side a host application talks to:
+//
+//     let device = SimulatorClient::connect("ws://127.0.0.1:9021").await?;
+//     device.enable().await?;
+//     let note = device.wait_for_read().await?;
+//     device.confirm_read().await?;
+//
+// The connection is re-established automatically when it drops. Requests sent while
+// disconnected, or still waiting for an answer when the connection drops, fail with
+// ClientError::Disconnected, and so does wait_for_read(). Everything the device sends is also published on
+// events().
+//
+// The protocol has no request ids, so enable(), disable() and status() are answered
+// by the next statusChange: one the device sends on its own (after a read, or when
+// it goes in ERROR) answers the request in flight.
+
+use plugin_interface::protocol::{self, DeviceEvent, DeviceStatus, HostAction};
+
+use futures_util::stream::{self, BoxStream, StreamExt};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::{oneshot, Notify};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::connect_async;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+mod connection;
+
+use connection::{Expected, Pending, Shared};
+
+#[derive(Clone, Debug)]
+pub struct ClientOptions {
+    // how long a request waits for the device's answer
+    pub response_timeout: Duration,
+    // pause between reconnection attempts
+    pub reconnect_delay: Duration,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            response_timeout: Duration::from_secs(5),
+            reconnect_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClientEvent {
+    Connected,
+    Disconnected,
+    Device(DeviceEvent),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClientError {
+    // the first connection failed
+    Connect(String),
+    Disconnected,
+    // the device did not answer within response_timeout
+    Timeout,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Connect(e) => write!(f, "unable to connect: {}", e),
+            ClientError::Disconnected => write!(f, "not connected to the simulator"),
+            ClientError::Timeout => write!(f, "no answer from the simulator"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+pub struct SimulatorClient {
+    shared: Arc<Shared>,
+    next_request: AtomicU64,
+    task: JoinHandle<()>,
+}
+
+impl SimulatorClient {
+    pub async fn connect(url: &str) -> Result<Self, ClientError> {
+        Self::connect_with(url, ClientOptions::default()).await
+    }
+
+    pub async fn connect_with(url: &str, options: ClientOptions) -> Result<Self, ClientError> {
+        let (ws_stream, _) = connect_async(url).await.map_err(|e| ClientError::Connect(e.to_string()))?;
+
+        let shared = Arc::new(Shared {
+            url: url.to_string(),
+            options,
+            writer: Mutex::new(None),
+            pending: Mutex::new(VecDeque::new()),
+            reads: Mutex::new(VecDeque::new()),
+            read_arrived: Notify::new(),
+            last_status: Mutex::new(None),
+            events: broadcast::channel(256).0,
+        });
+
+        // attached before connect() returns, so requests can be sent right away
+        let connection = connection::attach(&shared, ws_stream);
+        let task = tokio::spawn(connection::run(shared.clone(), connection));
+
+        Ok(SimulatorClient { shared, next_request: AtomicU64::new(0), task })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared.writer.lock().unwrap().is_some()
+    }
+
+    // The status from the last statusChange received, None before the first one
+    pub fn last_status(&self) -> Option<DeviceStatus> {
+        *self.shared.last_status.lock().unwrap()
+    }
+
+    pub async fn enable(&self) -> Result<DeviceStatus, ClientError> {
+        self.request_status(HostAction::Enable).await
+    }
+
+    pub async fn disable(&self) -> Result<DeviceStatus, ClientError> {
+        self.request_status(HostAction::Disable).await
+    }
+
+    pub async fn status(&self) -> Result<DeviceStatus, ClientError> {
+        self.request_status(HostAction::QueryStatus).await
+    }
+
+    pub async fn confirm_read(&self) -> Result<(), ClientError> {
+        self.request(HostAction::ConfirmRead, Expected::ConfirmRead).await.map(|_| ())
+    }
+
+    // The oldest read not collected yet, waiting for one if there is none; reads
+    // already received are returned even after the connection drops
+    pub async fn wait_for_read(&self) -> Result<String, ClientError> {
+        loop {
+            let notified = self.shared.read_arrived.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if let Some(value) = self.shared.reads.lock().unwrap().pop_front() {
+                return Ok(value);
+            }
+            if !self.is_connected() {
+                return Err(ClientError::Disconnected);
+            }
+            notified.await;
+        }
+    }
+
+    // Everything that happens from now on; a subscriber that falls too far behind
+    // skips the events it missed
+    pub fn events(&self) -> BoxStream<'static, ClientEvent> {
+        stream::unfold(self.shared.events.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()
+    }
+
+    async fn request_status(&self, action: HostAction) -> Result<DeviceStatus, ClientError> {
+        match self.request(action, Expected::StatusChange).await? {
+            DeviceEvent::StatusChange { status } => Ok(status),
+            _ => unreachable!("answered by a statusChange"),
+        }
+    }
+
+    async fn request(&self, action: HostAction, expects: Expected) -> Result<DeviceEvent, ClientError> {
+        let id = self.next_request.fetch_add(1, Ordering::SeqCst);
+        let (reply, answer) = oneshot::channel();
+
+        {
+            let writer = self.shared.writer.lock().unwrap();
+            let writer = writer.as_ref().ok_or(ClientError::Disconnected)?;
+            // registered before sending, the answer can not arrive first
+            self.shared.pending.lock().unwrap().push_back(Pending { id, expects, reply });
+            if writer.send(protocol::to_message(&action)).is_err() {
+                self.forget(id);
+                return Err(ClientError::Disconnected);
+            }
+        }
+
+        match tokio::time::timeout(self.shared.options.response_timeout, answer).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(ClientError::Disconnected),
+            Err(_) => {
+                self.forget(id);
+                Err(ClientError::Timeout)
+            }
+        }
+    }
+
+    fn forget(&self, id: u64) {
+        self.shared.pending.lock().unwrap().retain(|p| p.id != id);
+    }
+}
+
+impl Drop for SimulatorClient {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,144 @@
+use bna_plugin::BNAPlugin;
+use plugin_interface::protocol::{DeviceEvent, DeviceStatus};
+use simulator_client::{ClientError, ClientEvent, ClientOptions, SimulatorClient};
+use simulator_server::{SimulatorHandle, SimulatorServer};
+
+use futures_util::StreamExt;
+use serde_json::json;
+
+use std::time::Duration;
+
+async fn start() -> SimulatorHandle<BNAPlugin> {
+    SimulatorServer::builder().plugin::<BNAPlugin>().bind_ephemeral().start().await.unwrap()
+}
+
+async fn connect(simulator: &SimulatorHandle<BNAPlugin>) -> SimulatorClient {
+    let options = ClientOptions {
+        response_timeout: Duration::from_millis(500),
+        reconnect_delay: Duration::from_millis(50),
+    };
+    SimulatorClient::connect_with(&format!("ws://{}", simulator.external_addr()), options).await.unwrap()
+}
+
+async fn read_note(simulator: &SimulatorHandle<BNAPlugin>, value: &str) {
+    simulator.inject_js_message(json!({ "action": "read", "value": value }).to_string()).await;
+}
+
+#[tokio::test]
+async fn enable_disable_and_status_return_the_device_status() {
+    let simulator = start().await;
+    let device = connect(&simulator).await;
+
+    assert_eq!(device.status().await, Ok(DeviceStatus::Disabled));
+    assert_eq!(device.enable().await, Ok(DeviceStatus::Armed));
+    assert_eq!(device.status().await, Ok(DeviceStatus::Armed));
+    assert_eq!(device.disable().await, Ok(DeviceStatus::Disabled));
+    assert_eq!(device.last_status(), Some(DeviceStatus::Disabled));
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn reads_are_queued_until_collected() {
+    let simulator = start().await;
+    let device = connect(&simulator).await;
+    device.enable().await.unwrap();
+
+    read_note(&simulator, "20").await;
+    device.enable().await.unwrap();
+    read_note(&simulator, "50").await;
+
+    assert_eq!(device.wait_for_read().await, Ok("20".to_string()));
+    assert_eq!(device.wait_for_read().await, Ok("50".to_string()));
+    device.confirm_read().await.unwrap();
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn events_stream_everything_the_device_sends() {
+    let simulator = start().await;
+    let device = connect(&simulator).await;
+    let mut events = device.events();
+
+    device.enable().await.unwrap();
+    read_note(&simulator, "10").await;
+
+    assert_eq!(
+        events.next().await,
+        Some(ClientEvent::Device(DeviceEvent::StatusChange { status: DeviceStatus::Armed }))
+    );
+    assert_eq!(
+        events.next().await,
+        Some(ClientEvent::Device(DeviceEvent::Read { value: "10".to_string() }))
+    );
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn unanswered_requests_time_out() {
+    let simulator = start().await;
+    let device = connect(&simulator).await;
+    let mut events = device.events();
+    simulator.inject_js_message(json!({ "action": "error" }).to_string()).await;
+    events.next().await;
+
+    // enable is ignored in ERROR
+    assert_eq!(device.enable().await, Err(ClientError::Timeout));
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn reconnects_when_the_simulator_comes_back() {
+    let simulator = start().await;
+    let addr = simulator.external_addr();
+    let device = connect(&simulator).await;
+    let mut events = device.events();
+
+    simulator.shutdown().await;
+    assert_eq!(events.next().await, Some(ClientEvent::Disconnected));
+    assert_eq!(device.status().await, Err(ClientError::Disconnected));
+
+    let simulator = SimulatorServer::builder()
+        .plugin::<BNAPlugin>()
+        .js_port(0)
+        .external_port(addr.port())
+        .start()
+        .await
+        .unwrap();
+    assert_eq!(events.next().await, Some(ClientEvent::Connected));
+    assert_eq!(device.enable().await, Ok(DeviceStatus::Armed));
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn waiting_for_a_read_fails_when_the_connection_drops() {
+    let simulator = start().await;
+    let device = connect(&simulator).await;
+    device.enable().await.unwrap();
+    read_note(&simulator, "20").await;
+    let mut events = device.events();
+    events.next().await;
+
+    let waiting = tokio::spawn(async move {
+        let first = device.wait_for_read().await;
+        (first, device.wait_for_read().await)
+    });
+    simulator.shutdown().await;
+
+    let (first, second) = tokio::time::timeout(Duration::from_secs(5), waiting).await.unwrap().unwrap();
+    assert_eq!(first, Ok("20".to_string()));
+    assert_eq!(second, Err(ClientError::Disconnected));
+}
+
+#[tokio::test]
+async fn connect_fails_without_a_simulator() {
+    let simulator = start().await;
+    let url = format!("ws://{}", simulator.external_addr());
+    simulator.shutdown().await;
+
+    assert!(matches!(SimulatorClient::connect(&url).await, Err(ClientError::Connect(_))));
+}
@@ -0,0 +1,152 @@
+// src/connection.rs
+//
+// The WebSocket servers: one client at a time on the js and external ports, every
+// text message is handed to the plugin and whatever the plugin sends goes back out.
+
+use crate::state::ServerState;
+use crate::Shutdown;
+
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_manager::fault;
+use plugin_manager::recorder::{Direction, Port};
+use plugin_manager::PluginManager;
+
+use futures_util::{SinkExt, StreamExt};
+use tracing::Instrument;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+use tokio_tungstenite::accept_async;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+pub(crate) type SharedPluginManager<P> = Arc<Mutex<PluginManager<ServerState, P>>>;
+
+// Accepts connections until the server shuts down, `handler` speaks the protocol
+// (WebSocket, or HTTP for the UI)
+pub(crate) async fn run_tcp_server<F, Fut>(listener: TcpListener, mut shutdown: Shutdown, handler: F)
+where
+    F: Fn(TcpStream) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                // non ti preoccupare di avere tokio::spawn annidati
+                Ok((stream, _)) => { tokio::spawn(handler(stream)); }
+                Err(_) => break,
+            },
+            _ = shutdown.wait_for(|stopped| *stopped) => break,
+        }
+    }
+}
+
+// each connection gets a span of its own, the plugin's messages are logged inside it
+#[tracing::instrument(
+    name = "connection",
+    skip_all,
+    fields(port = ?port, peer = ?stream.peer_addr().ok(), client_id = tracing::field::Empty)
+)]
+pub(crate) async fn handle_client<P: Plugin>(
+    port: Port,
+    state: Arc<ServerState>,
+    plugin_manager: SharedPluginManager<P>,
+    stream: TcpStream,
+    mut shutdown: Shutdown,
+) {
+    // ad ogni nuova connessione si finisce qui...
+    if state.is_connected(port) {
+        // Refuse connection if another client is already connected
+        tracing::warn!("Connection refused: Another {:?} client is already connected.", port);
+        return;
+    }
+
+    // accetta la connessione...
+    let ws_stream = match accept_async(stream).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            tracing::warn!("Error during WebSocket handshake: {}", e);
+            return; // Exit the function if the handshake fails
+        }
+    };
+
+    // se la connessione e' valida si prosegue da qui...
+
+    // si splitta il canale di comunicazione con l'OP in due (write e read)
+    let (mut write_to_socket, mut read_from_socket) = ws_stream.split();
+
+    // qui si crea un nuovo canale di comunication tra questo thread e il thread che gestisce la richiesta...
+    // in entrambe le direzioni i messaggi passano dalla fault injection
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let (inbound_tx, mut inbound_rx) = mpsc::unbounded_channel();
+    let inbound = fault::link(state.faults.clone(), inbound_tx);
+
+    // salva il lato tx del canale interno nella variabile apposita...
+    let client_id = state.connect(port, fault::link(state.faults.clone(), tx));
+    tracing::Span::current().record("client_id", client_id);
+    tracing::info!("Client connected");
+
+    // messaggi scambiati su questa connessione, in entrambe le direzioni
+    let message_count = Arc::new(AtomicU64::new(0));
+
+    // qui si fa partire un altro thread che sta in ascolto per la ricezione della risposta (interna),
+    // quando si riceve la risposta (generata da un altro thread) qui si manda la risposta all'OP
+    let writer_state = state.clone();
+    let writer_count = message_count.clone();
+    let writer = async move {
+        while let Some(msg) = rx.recv().await {
+            // registrato solo ora, quello che il client riceve davvero
+            if let Message::Text(text) = &msg {
+                writer_state.record(Direction::Outbound, port, client_id, text);
+            }
+
+            if write_to_socket.send(msg).await.is_err() {
+                // errore sul socket... annulla questa sessione...
+                break;
+            }
+
+            if writer_state.faults.should_disconnect(writer_count.fetch_add(1, Ordering::SeqCst) + 1) {
+                tracing::info!("Fault injection: closing the connection");
+                break;
+            }
+        }
+        let _ = write_to_socket.close().await;
+    };
+    tokio::spawn(writer.instrument(tracing::Span::current()));
+
+    // qui si va a gestire la richiesta dell'OP (su questo thread...),
+    // ed eventuali future richieste da questa connessione...
+    loop {
+        // il messaggio come arriva al plugin, dopo la fault injection
+        let msg = tokio::select! {
+            msg = read_from_socket.next() => {
+                let Some(Ok(msg)) = msg else { break };
+                if let Message::Text(_) = msg {
+                    let _ = inbound.send(msg);
+                }
+                continue;
+            }
+            Some(msg) = inbound_rx.recv() => msg,
+            _ = shutdown.wait_for(|stopped| *stopped) => break,
+        };
+
+        if let Message::Text(text) = msg {
+            state.record(Direction::Inbound, port, client_id, &text);
+
+            // Forward the message to the plugin manager for handling
+            let lock_on_plugin = plugin_manager.lock().await;
+            state.handle(&lock_on_plugin, port, text);
+
+            if state.faults.should_disconnect(message_count.fetch_add(1, Ordering::SeqCst) + 1) {
+                tracing::info!("Fault injection: closing the connection");
+                break;
+            }
+        }
+    }
+
+    tracing::info!("Connection closed");
+    // dropping the sender ends the writer task, which closes the socket
+    state.disconnect(port);
+}
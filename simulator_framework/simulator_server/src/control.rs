@@ -0,0 +1,146 @@
+// src/control.rs
+//
+// Control API for test tooling, one JSON command per message, e.g.
+//   {"command": "set_fault_profile", "profile": "lossy"}
+//   {"command": "set_fault_profile", "profile": {"drop_rate": 0.5}}
+//   {"command": "get_fault_profile"} / {"command": "list_fault_profiles"}
+//   {"command": "set_recording", "enabled": true}
+//   {"command": "describe"}, answered with the plugin's metadata
+//   {"command": "inspect"}, after which every message in or out of the js and external
+//     ports arrives on this connection as {"event": "inspect", "record": <TraceRecord>}
+//   {"command": "send", "direction": "inbound", "port": "external", "message": "..."},
+//     a hand-written message as either side, see SimulatorHandle::send_as
+
+use crate::connection::SharedPluginManager;
+use crate::state::ServerState;
+use crate::Shutdown;
+
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_manager::fault::FaultProfile;
+use plugin_manager::recorder::{Direction, Port, TraceRecord};
+use plugin_manager::PluginManager;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio_tungstenite::accept_async;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use std::sync::Arc;
+
+// `inspector` is the inspect subscription of the connection the command came from
+pub(crate) fn handle_control_command<P: Plugin>(
+    state: &ServerState,
+    plugin_manager: &PluginManager<ServerState, P>,
+    inspector: &mut Option<UnboundedReceiver<TraceRecord>>,
+    text: &str,
+) -> Value {
+    let request: Value = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => return json!({ "ok": false, "error": format!("Invalid JSON: {}", e) }),
+    };
+
+    match request.get("command").and_then(|v| v.as_str()) {
+        Some("set_fault_profile") => match request.get("profile") {
+            Some(Value::String(name)) => match state.select_fault_profile(name) {
+                Ok(()) => json!({ "ok": true }),
+                Err(e) => json!({ "ok": false, "error": e }),
+            },
+            Some(profile) => match serde_json::from_value::<FaultProfile>(profile.clone()) {
+                Ok(profile) => {
+                    state.faults.set_profile("custom", profile);
+                    json!({ "ok": true })
+                }
+                Err(e) => json!({ "ok": false, "error": format!("Invalid fault profile: {}", e) }),
+            },
+            None => json!({ "ok": false, "error": "Missing profile" }),
+        },
+        Some("get_fault_profile") => {
+            let (name, profile) = state.faults.profile();
+            json!({ "ok": true, "name": name, "profile": profile })
+        }
+        Some("list_fault_profiles") => json!({ "ok": true, "profiles": state.fault_profile_names() }),
+        Some("set_recording") => match request.get("enabled").and_then(|v| v.as_bool()) {
+            Some(enabled) => {
+                state.recorder.set_enabled(enabled);
+                json!({ "ok": true })
+            }
+            None => json!({ "ok": false, "error": "Missing enabled" }),
+        },
+        Some("describe") => json!({ "ok": true, "metadata": plugin_manager.metadata() }),
+        Some("inspect") => {
+            if inspector.is_none() {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                state.subscribe_inspector(sender);
+                *inspector = Some(receiver);
+            }
+            json!({ "ok": true })
+        }
+        Some("send") => {
+            let direction = request.get("direction").cloned().map(serde_json::from_value::<Direction>);
+            let port = request.get("port").cloned().map(serde_json::from_value::<Port>);
+            match (direction, port, request.get("message").and_then(|v| v.as_str())) {
+                (Some(Ok(direction)), Some(Ok(port)), Some(message)) => {
+                    state.deliver(plugin_manager, direction, port, message.to_string());
+                    json!({ "ok": true })
+                }
+                _ => json!({ "ok": false, "error": "Expected direction (inbound/outbound), port (js/external) and message" }),
+            }
+        }
+        Some(other) => json!({ "ok": false, "error": format!("Unknown command '{}'", other) }),
+        None => json!({ "ok": false, "error": "Missing command" }),
+    }
+}
+
+#[tracing::instrument(name = "control_connection", skip_all)]
+pub(crate) async fn handle_control_client<P: Plugin>(
+    state: Arc<ServerState>,
+    plugin_manager: SharedPluginManager<P>,
+    stream: TcpStream,
+    mut shutdown: Shutdown,
+) {
+    let ws_stream = match accept_async(stream).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            tracing::warn!("Error during WebSocket handshake: {}", e);
+            return;
+        }
+    };
+
+    // il canale di controllo risponde sempre sulla stessa connessione, non serve un thread di scrittura
+    let (mut write_to_socket, mut read_from_socket) = ws_stream.split();
+
+    let mut inspector = None;
+    loop {
+        let (msg, record) = tokio::select! {
+            msg = read_from_socket.next() => (msg, None),
+            Some(record) = next_record(&mut inspector) => (None, Some(record)),
+            _ = shutdown.wait_for(|stopped| *stopped) => break,
+        };
+
+        if let Some(record) = record {
+            let event = json!({ "event": "inspect", "record": record });
+            if write_to_socket.send(Message::Text(event.to_string())).await.is_err() {
+                break;
+            }
+            continue;
+        }
+        let Some(Ok(msg)) = msg else { break };
+
+        if let Message::Text(text) = msg {
+            let response = handle_control_command(&state, &*plugin_manager.lock().await, &mut inspector, &text);
+            if write_to_socket.send(Message::Text(response.to_string())).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+// never resolves until the connection asks to inspect
+async fn next_record(inspector: &mut Option<UnboundedReceiver<TraceRecord>>) -> Option<TraceRecord> {
+    match inspector {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,201 @@
+// src/http.rs
+//
+// The generic UI (assets/ui) over HTTP, for browsers where there is no Tauri window:
+//   GET /                     the page, from ServerBuilder::ui_dir or the copy built in here
+//   GET /api/ui               the plugin's UI description, see plugin_interface::ui
+//   GET /api/js-port          the port of the js WebSocket the page talks to the plugin on
+//   GET /api/control-port     the control port the message inspector uses, or null
+//   GET /api/fault-profiles   {"profiles": [...], "current": "..."}
+//   POST /api/fault-profile   selects the profile named in the body
+//   GET /metrics              the simulator's and the plugin's metrics, for Prometheus
+//
+// Without the js port only /metrics is served, the page would have nothing to talk to.
+//
+// Just what the page needs: one request per connection, no keep-alive.
+
+use crate::connection::SharedPluginManager;
+use crate::state::ServerState;
+
+use plugin_interface::interface_for_plugin::Plugin;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+const BUNDLED_UI: [(&str, &str); 3] = [
+    ("index.html", include_str!("../../assets/ui/index.html")),
+    ("script.js", include_str!("../../assets/ui/script.js")),
+    ("styles.css", include_str!("../../assets/ui/styles.css")),
+];
+
+// What the page is told and where it comes from
+pub(crate) struct Page {
+    // None when the js port is not served
+    pub(crate) js_port: Option<u16>,
+    pub(crate) control_port: Option<u16>,
+    pub(crate) ui_dir: Option<PathBuf>,
+}
+
+// larger requests are refused, the page never sends any
+const MAX_REQUEST: usize = 16 * 1024;
+// a client that has not sent its whole request by then is answered and dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(value: Value) -> Self {
+        Response { status: "200 OK", content_type: "application/json", body: value.to_string().into_bytes() }
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Response { status, content_type: "text/plain; charset=utf-8", body: message.as_bytes().to_vec() }
+    }
+}
+
+fn content_type(path: &str) -> &'static str {
+    match path.rsplit('.').next() {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        _ => "application/octet-stream",
+    }
+}
+
+// Files of the UI, never outside its directory
+fn static_file(ui_dir: Option<&Path>, path: &str) -> Response {
+    let path = match path.trim_start_matches('/') {
+        "" => "index.html",
+        path => path,
+    };
+
+    let body = match ui_dir {
+        Some(dir) => {
+            let relative = Path::new(path);
+            if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+                return Response::error("404 Not Found", "not found");
+            }
+            std::fs::read(dir.join(relative)).ok()
+        }
+        None => BUNDLED_UI.iter().find(|(name, _)| *name == path).map(|(_, text)| text.as_bytes().to_vec()),
+    };
+
+    match body {
+        Some(body) => Response { status: "200 OK", content_type: content_type(path), body },
+        None => Response::error("404 Not Found", "not found"),
+    }
+}
+
+async fn handle_request<P: Plugin>(
+    state: &ServerState,
+    plugin_manager: &SharedPluginManager<P>,
+    page: &Page,
+    method: &str,
+    path: &str,
+    body: &str,
+) -> Response {
+    // the query string is of no use here
+    let path = path.split('?').next().unwrap_or_default();
+
+    if (method, path) == ("GET", "/metrics") {
+        return Response {
+            status: "200 OK",
+            content_type: "text/plain; version=0.0.4; charset=utf-8",
+            body: state.metrics.render().into_bytes(),
+        };
+    }
+    if page.js_port.is_none() {
+        return Response::error("404 Not Found", "the UI is not served without the js port");
+    }
+
+    match (method, path) {
+        ("GET", "/api/ui") => Response::json(json!(plugin_manager.lock().await.ui())),
+        ("GET", "/api/js-port") => Response::json(json!(page.js_port)),
+        ("GET", "/api/control-port") => Response::json(json!(page.control_port)),
+        ("GET", "/api/fault-profiles") => Response::json(json!({
+            "profiles": state.fault_profile_names(),
+            "current": state.faults.profile().0,
+        })),
+        ("POST", "/api/fault-profile") => match state.select_fault_profile(body.trim()) {
+            Ok(()) => Response::json(json!({ "ok": true })),
+            Err(e) => Response::error("400 Bad Request", &e),
+        },
+        ("GET", _) => static_file(page.ui_dir.as_deref(), path),
+        _ => Response::error("405 Method Not Allowed", "method not allowed"),
+    }
+}
+
+// The request line and the body, once Content-Length bytes of it have arrived
+async fn read_request(stream: &mut TcpStream) -> Option<(String, String, String)> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            let head = String::from_utf8_lossy(&buffer[..end]).to_string();
+            let length = head
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+                .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            // a body that would not fit is refused before it is waited for
+            if length > MAX_REQUEST {
+                return None;
+            }
+            if end + 4 + length <= buffer.len() {
+                let mut request_line = head.lines().next()?.split_whitespace();
+                let method = request_line.next()?.to_string();
+                let path = request_line.next()?.to_string();
+                let body = String::from_utf8_lossy(&buffer[end + 4..end + 4 + length]).to_string();
+                return Some((method, path, body));
+            }
+        }
+        if buffer.len() > MAX_REQUEST {
+            return None;
+        }
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+}
+
+pub(crate) async fn handle_http_client<P: Plugin>(
+    state: Arc<ServerState>,
+    plugin_manager: SharedPluginManager<P>,
+    page: Arc<Page>,
+    mut stream: TcpStream,
+) {
+    let response = match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await {
+        Ok(Some((method, path, body))) => {
+            let response = handle_request(&state, &plugin_manager, &page, &method, &path, &body).await;
+            tracing::debug!(%method, %path, status = response.status, "HTTP request");
+            response
+        }
+        Ok(None) => Response::error("400 Bad Request", "bad request"),
+        Err(_) => Response::error("408 Request Timeout", "request timeout"),
+    };
+
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    if stream.write_all(head.as_bytes()).await.is_ok() {
+        let _ = stream.write_all(&response.body).await;
+    }
+    let _ = stream.shutdown().await;
+}
@@ -0,0 +1,491 @@
+// src/lib.rs
+//
+// The simulator's WebSocket servers as a library, so that the Tauri application and
+// Rust integration tests run the same code:
+//
+//     let simulator = SimulatorServer::builder()
+//         .plugin::<BNAPlugin>()
+//         .bind_ephemeral()
+//         .start()
+//         .await?;
+//
+//     connect_async(format!("ws://{}", simulator.external_addr())).await?;
+//     simulator.inject_js_message(r#"{"action": "read", "value": "20"}"#).await;
+//     assert_eq!(simulator.state().await["status"], "DISABLED");
+//     simulator.shutdown().await;
+//
+// With hot_reload(true) the plugin is replaced whenever its artifact is rebuilt, see
+// reload.rs.
+//
+// The js port is optional: a UI running in the same process can use inject_js_message
+// and subscribe_js instead of connecting to it. With http_port the metrics are served
+// for Prometheus on /metrics, and with the js port the generic UI too, to browsers that
+// talk to the plugin on it, see http.rs and state.rs.
+
+use plugin_interface::clock::VirtualClock;
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_interface::metadata::Metadata;
+use plugin_interface::ui::UiDescription;
+use plugin_manager::fault::FaultProfile;
+use plugin_manager::recorder::{Direction, Port, SessionRecorder, TraceRecord};
+use plugin_manager::PluginManager;
+
+use serde_json::Value;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+mod connection;
+mod control;
+mod http;
+pub mod logging;
+mod reload;
+mod state;
+
+use connection::SharedPluginManager;
+use state::ServerState;
+
+// true once the server has been asked to shut down
+pub(crate) type Shutdown = watch::Receiver<bool>;
+
+pub struct SimulatorServer;
+
+impl SimulatorServer {
+    pub fn builder() -> ServerBuilder<()> {
+        ServerBuilder {
+            bind_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            js_port: None,
+            external_port: None,
+            control_port: None,
+            http_port: None,
+            ui_dir: None,
+            trace_file: PathBuf::from("session_trace.jsonl"),
+            record: false,
+            fault_profiles: HashMap::new(),
+            fault_profile: None,
+            virtual_clock: None,
+            hot_reload: false,
+            validate_inbound: false,
+            plugin: None,
+        }
+    }
+}
+
+pub struct ServerBuilder<P> {
+    bind_address: IpAddr,
+    js_port: Option<u16>,
+    external_port: Option<u16>,
+    control_port: Option<u16>,
+    http_port: Option<u16>,
+    ui_dir: Option<PathBuf>,
+    trace_file: PathBuf,
+    record: bool,
+    fault_profiles: HashMap<String, FaultProfile>,
+    fault_profile: Option<String>,
+    virtual_clock: Option<Arc<VirtualClock>>,
+    hot_reload: bool,
+    validate_inbound: bool,
+    // None for P::new()
+    plugin: Option<P>,
+}
+
+impl<P> ServerBuilder<P> {
+    pub fn plugin<Q: Plugin>(self) -> ServerBuilder<Q> {
+        ServerBuilder {
+            bind_address: self.bind_address,
+            js_port: self.js_port,
+            external_port: self.external_port,
+            control_port: self.control_port,
+            http_port: self.http_port,
+            ui_dir: self.ui_dir,
+            trace_file: self.trace_file,
+            record: self.record,
+            fault_profiles: self.fault_profiles,
+            fault_profile: self.fault_profile,
+            virtual_clock: self.virtual_clock,
+            hot_reload: self.hot_reload,
+            validate_inbound: self.validate_inbound,
+            plugin: None,
+        }
+    }
+
+    // Runs this instance instead of Q::new(), for plugins built from a file or with
+    // settings of their own
+    pub fn plugin_instance<Q: Plugin>(self, plugin: Q) -> ServerBuilder<Q> {
+        let mut builder = self.plugin::<Q>();
+        builder.plugin = Some(plugin);
+        builder
+    }
+
+    // 127.0.0.1 unless told otherwise
+    pub fn bind_address(mut self, address: IpAddr) -> Self {
+        self.bind_address = address;
+        self
+    }
+
+    // Serves the js port for UIs in a browser or on another machine
+    pub fn js_port(mut self, port: u16) -> Self {
+        self.js_port = Some(port);
+        self
+    }
+
+    pub fn external_port(mut self, port: u16) -> Self {
+        self.external_port = Some(port);
+        self
+    }
+
+    // The control API is only served when a port is given
+    pub fn control_port(mut self, port: u16) -> Self {
+        self.control_port = Some(port);
+        self
+    }
+
+    // Serves the metrics, and the generic UI to browsers when the js port is set
+    pub fn http_port(mut self, port: u16) -> Self {
+        self.http_port = Some(port);
+        self
+    }
+
+    // Serves the UI files of this directory instead of the ones built into the server
+    pub fn ui_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.ui_dir = Some(dir.into());
+        self
+    }
+
+    // Lets the OS pick free ports (the control and HTTP ports too, if enabled); the
+    // handle returned by start() tells which ones
+    pub fn bind_ephemeral(mut self) -> Self {
+        self.js_port = Some(0);
+        self.external_port = Some(0);
+        self.control_port = self.control_port.map(|_| 0);
+        self.http_port = self.http_port.map(|_| 0);
+        self
+    }
+
+    pub fn trace_file(mut self, path: impl Into<PathBuf>, record: bool) -> Self {
+        self.trace_file = path.into();
+        self.record = record;
+        self
+    }
+
+    pub fn fault_profiles(mut self, profiles: HashMap<String, FaultProfile>) -> Self {
+        self.fault_profiles = profiles;
+        self
+    }
+
+    // Selects one of the fault_profiles from the start
+    pub fn fault_profile(mut self, name: impl Into<String>) -> Self {
+        self.fault_profile = Some(name.into());
+        self
+    }
+
+    // Runs the plugin on a virtual clock, its timers only fire on SimulatorHandle::advance
+    pub fn virtual_clock(mut self, clock: Arc<VirtualClock>) -> Self {
+        self.virtual_clock = Some(clock);
+        self
+    }
+
+    // Reloads the plugin when its artifact changes, for plugins that have one
+    pub fn hot_reload(mut self, enabled: bool) -> Self {
+        self.hot_reload = enabled;
+        self
+    }
+
+    // Answers inbound messages that do not match the plugin's metadata with an error,
+    // see plugin_manager::schema
+    pub fn validate_inbound(mut self, enabled: bool) -> Self {
+        self.validate_inbound = enabled;
+        self
+    }
+}
+
+impl<P: Plugin + Send + 'static> ServerBuilder<P> {
+    // Binds every port before returning, so clients can connect as soon as this resolves
+    pub async fn start(mut self) -> io::Result<SimulatorHandle<P>> {
+        let Some(external_port) = self.external_port else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the external port is required, set it or use bind_ephemeral()",
+            ));
+        };
+
+        let js_listener = match self.js_port {
+            Some(port) => Some(bind(self.bind_address, port).await?),
+            None => None,
+        };
+        let external_listener = bind(self.bind_address, external_port).await?;
+        let control_listener = match self.control_port {
+            Some(port) => Some(bind(self.bind_address, port).await?),
+            None => None,
+        };
+        let http_listener = match self.http_port {
+            Some(port) => Some(bind(self.bind_address, port).await?),
+            None => None,
+        };
+
+        let state = Arc::new(ServerState::new(
+            SessionRecorder::new(self.trace_file, self.record),
+            self.fault_profiles,
+        ));
+        if let Some(name) = &self.fault_profile {
+            state.select_fault_profile(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        }
+
+        let plugin = self.plugin.take().unwrap_or_else(P::new);
+        let mut plugin_manager = match &self.virtual_clock {
+            Some(clock) => PluginManager::with_plugin(state.clone(), plugin, clock.clone()),
+            None => PluginManager::from_plugin(state.clone(), plugin),
+        };
+        plugin_manager.set_inbound_validation(self.validate_inbound);
+        state.set_message_names(&plugin_manager.metadata());
+        let plugin_manager: SharedPluginManager<P> = Arc::new(Mutex::new(plugin_manager));
+
+        let (shutdown_tx, shutdown) = watch::channel(false);
+        let mut tasks = Vec::new();
+
+        let js_addr = match &js_listener {
+            Some(listener) => Some(listener.local_addr()?),
+            None => None,
+        };
+        let external_addr = external_listener.local_addr()?;
+        let control_addr = match &control_listener {
+            Some(listener) => Some(listener.local_addr()?),
+            None => None,
+        };
+        let http_addr = match &http_listener {
+            Some(listener) => Some(listener.local_addr()?),
+            None => None,
+        };
+
+        let listeners = js_listener.map(|listener| (Port::Js, listener)).into_iter().chain([(Port::External, external_listener)]);
+        for (port, listener) in listeners {
+            let state = state.clone();
+            let plugin_manager = plugin_manager.clone();
+            let client_shutdown = shutdown.clone();
+            tasks.push(tokio::spawn(connection::run_tcp_server(listener, shutdown.clone(), move |stream| {
+                connection::handle_client(port, state.clone(), plugin_manager.clone(), stream, client_shutdown.clone())
+            })));
+        }
+
+        if let Some(listener) = control_listener {
+            let state = state.clone();
+            let plugin_manager = plugin_manager.clone();
+            let client_shutdown = shutdown.clone();
+            tasks.push(tokio::spawn(connection::run_tcp_server(listener, shutdown.clone(), move |stream| {
+                control::handle_control_client(state.clone(), plugin_manager.clone(), stream, client_shutdown.clone())
+            })));
+        }
+
+        // la pagina si collega alla porta js effettiva, anche se scelta dal sistema
+        if let Some(listener) = http_listener {
+            let state = state.clone();
+            let plugin_manager = plugin_manager.clone();
+            let page = Arc::new(http::Page {
+                js_port: js_addr.map(|addr| addr.port()),
+                control_port: control_addr.map(|addr| addr.port()),
+                ui_dir: self.ui_dir.clone(),
+            });
+            tasks.push(tokio::spawn(connection::run_tcp_server(listener, shutdown.clone(), move |stream| {
+                http::handle_http_client(state.clone(), plugin_manager.clone(), page.clone(), stream)
+            })));
+        }
+
+        // i timer dei plugin vengono controllati ogni 10 ms (con il clock virtuale ci pensa advance)
+        if self.virtual_clock.is_none() {
+            let plugin_manager = plugin_manager.clone();
+            let mut shutdown = shutdown.clone();
+            tasks.push(tokio::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_millis(10));
+                loop {
+                    tokio::select! {
+                        _ = interval.tick() => (),
+                        _ = shutdown.wait_for(|stopped| *stopped) => break,
+                    }
+                    plugin_manager.lock().await.fire_due_timers();
+                }
+            }));
+        }
+
+        if self.hot_reload {
+            tasks.push(tokio::spawn(reload::watch_artifact(state.clone(), plugin_manager.clone(), shutdown.clone())));
+        }
+
+        match js_addr {
+            Some(js_addr) => tracing::info!("WebSocket server running on ws://{} (js) and ws://{} (external)", js_addr, external_addr),
+            None => tracing::info!("WebSocket server running on ws://{} (external)", external_addr),
+        }
+        if let Some(addr) = control_addr {
+            tracing::info!("Control API running on ws://{}", addr);
+        }
+        if let Some(addr) = http_addr {
+            match js_addr {
+                Some(_) => tracing::info!("Simulator UI and metrics served on http://{}", addr),
+                None => tracing::info!("Metrics served on http://{}/metrics", addr),
+            }
+        }
+
+        Ok(SimulatorHandle {
+            inner: Arc::new(Inner {
+                state,
+                plugin_manager,
+                js_addr,
+                external_addr,
+                control_addr,
+                http_addr,
+                shutdown: shutdown_tx,
+                tasks: std::sync::Mutex::new(tasks),
+            }),
+        })
+    }
+}
+
+async fn bind(address: IpAddr, port: u16) -> io::Result<TcpListener> {
+    TcpListener::bind(SocketAddr::new(address, port)).await
+}
+
+// A running simulator. Clones share the same server; it keeps running until
+// shutdown() is called or the runtime it was started on goes away.
+pub struct SimulatorHandle<P: Plugin> {
+    inner: Arc<Inner<P>>,
+}
+
+struct Inner<P: Plugin> {
+    state: Arc<ServerState>,
+    plugin_manager: SharedPluginManager<P>,
+    js_addr: Option<SocketAddr>,
+    external_addr: SocketAddr,
+    control_addr: Option<SocketAddr>,
+    http_addr: Option<SocketAddr>,
+    shutdown: watch::Sender<bool>,
+    tasks: std::sync::Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl<P: Plugin> Clone for SimulatorHandle<P> {
+    fn clone(&self) -> Self {
+        SimulatorHandle { inner: self.inner.clone() }
+    }
+}
+
+impl<P: Plugin> SimulatorHandle<P> {
+    // None when the js port is not served, see ServerBuilder::js_port
+    pub fn js_addr(&self) -> Option<SocketAddr> {
+        self.inner.js_addr
+    }
+
+    pub fn external_addr(&self) -> SocketAddr {
+        self.inner.external_addr
+    }
+
+    pub fn control_addr(&self) -> Option<SocketAddr> {
+        self.inner.control_addr
+    }
+
+    pub fn http_addr(&self) -> Option<SocketAddr> {
+        self.inner.http_addr
+    }
+
+    // Hands a message to the plugin as if the simulator UI had sent it on the js port
+    pub async fn inject_js_message(&self, text: impl Into<String>) {
+        self.send_as(Direction::Inbound, Port::Js, text).await;
+    }
+
+    // What the plugin sends on the js port, for a UI in the same process: every text
+    // message goes to each receiver, alongside the WebSocket client if one is connected
+    pub fn subscribe_js(&self) -> UnboundedReceiver<String> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.inner.state.subscribe_js(sender);
+        receiver
+    }
+
+    // Hands a message to the plugin as if the host had sent it on the external port
+    pub async fn inject_external_message(&self, text: impl Into<String>) {
+        self.send_as(Direction::Inbound, Port::External, text).await;
+    }
+
+    // A hand-written message as either side: Inbound as the client of `port`, Outbound
+    // as the plugin, to that client
+    pub async fn send_as(&self, direction: Direction, port: Port, text: impl Into<String>) {
+        let plugin_manager = self.inner.plugin_manager.lock().await;
+        self.inner.state.deliver(&plugin_manager, direction, port, text.into());
+    }
+
+    // Every message in or out of the js and external ports from now on, as the session
+    // recorder sees it (recording need not be on)
+    pub fn subscribe_inspector(&self) -> UnboundedReceiver<TraceRecord> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.inner.state.subscribe_inspector(sender);
+        receiver
+    }
+
+    // The plugin's state, see Plugin::snapshot
+    pub async fn state(&self) -> Value {
+        self.inner.plugin_manager.lock().await.snapshot()
+    }
+
+    // The simulator's and the plugin's metrics, as served on /metrics
+    pub fn metrics(&self) -> String {
+        self.inner.state.metrics.render()
+    }
+
+    // What the plugin reports about itself, see Plugin::metadata
+    pub async fn metadata(&self) -> Metadata {
+        self.inner.plugin_manager.lock().await.metadata()
+    }
+
+    // What the generic UI shows for the plugin, see Plugin::ui
+    pub async fn ui(&self) -> UiDescription {
+        self.inner.plugin_manager.lock().await.ui()
+    }
+
+    // Replaces the plugin with a new instance carrying its state, as hot reload does;
+    // an error leaves the current one in place
+    pub async fn reload(&self) -> Result<(), String> {
+        let plugin_manager = self.inner.plugin_manager.lock().await;
+        plugin_manager.reload()?;
+        self.inner.state.set_message_names(&plugin_manager.metadata());
+        Ok(())
+    }
+
+    // Moves a virtual clock forward, see ServerBuilder::virtual_clock
+    pub async fn advance(&self, by: Duration) {
+        self.inner.plugin_manager.lock().await.advance(by);
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.inner.state.recorder.is_enabled()
+    }
+
+    pub fn set_recording(&self, enabled: bool) {
+        self.inner.state.recorder.set_enabled(enabled);
+    }
+
+    pub fn fault_profile_names(&self) -> Vec<String> {
+        self.inner.state.fault_profile_names()
+    }
+
+    pub fn fault_profile(&self) -> String {
+        self.inner.state.faults.profile().0
+    }
+
+    pub fn set_fault_profile(&self, name: &str) -> Result<(), String> {
+        self.inner.state.select_fault_profile(name)
+    }
+
+    // Stops accepting connections, closes the connected clients and waits for the
+    // listeners to be released
+    pub async fn shutdown(&self) {
+        let _ = self.inner.shutdown.send(true);
+        let tasks: Vec<JoinHandle<()>> = self.inner.tasks.lock().unwrap().drain(..).collect();
+        for task in tasks {
+            let _ = task.await;
+        }
+    }
+}
@@ -0,0 +1,229 @@
+// src/logging.rs
+//
+// Where the simulator's diagnostics go, set up once per process:
+//
+//     logging::init(&LogConfig { filter: "info,simulator_server=debug".into(), ..Default::default() })?;
+//
+// filter takes EnvFilter directives, a level per module (RUST_LOG replaces it when set).
+// format is "text" or "json", for the console and the file alike. The file, if any, is
+// rotated once it grows past max_file_size, keeping max_files old ones next to it
+// (simulator.log.1 is the most recent). Without a console, release builds on Windows,
+// the default is simulator.log next to the executable.
+//
+// With otlp_endpoint the spans are also exported to an OpenTelemetry collector over
+// OTLP/HTTP, e.g. "http://localhost:4318/v1/traces", as service_name. Messages from the
+// host that carry a W3C traceparent are handled in a span of the host's trace. flush()
+// sends what is still buffered, call it before the process exits.
+
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use serde::Deserialize;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+static TRACER_PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    pub filter: String,
+    pub format: LogFormat,
+    pub console: bool,
+    pub file: Option<PathBuf>,
+    pub max_file_size: u64,
+    pub max_files: usize,
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+// windows_subsystem = "windows" leaves release builds without one
+fn has_console() -> bool {
+    !cfg!(all(windows, not(debug_assertions)))
+}
+
+fn default_log_file() -> Option<PathBuf> {
+    let exe_path = std::env::current_exe().ok()?;
+    Some(exe_path.parent()?.join("simulator.log"))
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            filter: "info".to_string(),
+            format: LogFormat::Text,
+            console: has_console(),
+            file: if has_console() { None } else { default_log_file() },
+            max_file_size: 10 * 1024 * 1024,
+            max_files: 5,
+            otlp_endpoint: None,
+            service_name: "simulator".to_string(),
+        }
+    }
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+fn layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(ansi);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().with_current_span(true).with_span_list(true).boxed(),
+    }
+}
+
+// Fails if the filter or the file are not usable, or if logging was already set up
+pub fn init(config: &LogConfig) -> Result<(), String> {
+    let filter = match std::env::var("RUST_LOG") {
+        Ok(directives) => EnvFilter::try_new(directives),
+        Err(_) => EnvFilter::try_new(&config.filter),
+    }
+    .map_err(|e| format!("Invalid log filter: {}", e))?;
+
+    let mut layers = Vec::new();
+    if config.console {
+        layers.push(layer(config.format, io::stdout, true));
+    }
+    if let Some(path) = &config.file {
+        let file = RollingFile::open(path.clone(), config.max_file_size, config.max_files)
+            .map_err(|e| format!("Unable to open log file {}: {}", path.display(), e))?;
+        layers.push(layer(config.format, Mutex::new(file), false));
+    }
+    let provider = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let provider = tracer_provider(endpoint, &config.service_name)?;
+            layers.push(tracing_opentelemetry::layer().with_tracer(provider.tracer("simulator")).boxed());
+            Some(provider)
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(layers.with_filter(filter))
+        .try_init()
+        .map_err(|e| format!("Logging already set up: {}", e))?;
+    if let Some(provider) = provider {
+        let _ = TRACER_PROVIDER.set(provider);
+    }
+    Ok(())
+}
+
+fn tracer_provider(endpoint: &str, service_name: &str) -> Result<SdkTracerProvider, String> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| format!("Unable to export spans to {}: {}", endpoint, e))?;
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(service_name.to_string()).build())
+        .build())
+}
+
+// Blocks until the spans recorded so far are exported, when they are
+pub fn flush() {
+    if let Some(provider) = TRACER_PROVIDER.get() {
+        if let Err(e) = provider.force_flush() {
+            eprintln!("Unable to export the spans: {}", e);
+        }
+    }
+}
+
+// A log file that is moved to <file>.1 (and the older ones up to <file>.<max_files>)
+// once it is larger than max_size. With max_files 0 it is just emptied.
+pub struct RollingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    // None only while rotating, Windows does not rename open files
+    file: Option<File>,
+    size: u64,
+}
+
+impl RollingFile {
+    pub fn open(path: PathBuf, max_size: u64, max_files: usize) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RollingFile { path, max_size, max_files, file: Some(file), size })
+    }
+
+    fn numbered(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        name.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        drop(self.file.take());
+        if self.max_files > 0 {
+            let _ = fs::remove_file(self.numbered(self.max_files));
+            for n in (1..self.max_files).rev() {
+                let _ = fs::rename(self.numbered(n), self.numbered(n + 1));
+            }
+            // if this fails the log goes on in the same file
+            let _ = fs::rename(&self.path, self.numbered(1));
+        }
+        let (file, size) = match append(&self.path) {
+            Ok(file) => {
+                // if this fails the file keeps growing, it is tried again on the next write
+                if self.max_files == 0 {
+                    let _ = file.set_len(0);
+                }
+                let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+                (file, size)
+            }
+            // the log goes on in the file just rotated rather than nowhere, rotating is
+            // tried again once max_size more is written
+            Err(_) => (append(&self.numbered(1))?, 0),
+        };
+        self.file = Some(file);
+        self.size = size;
+        Ok(())
+    }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+impl Write for RollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let file = self.file.as_mut().ok_or_else(|| io::Error::other("log file not open"))?;
+        let written = file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
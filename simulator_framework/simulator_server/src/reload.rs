@@ -0,0 +1,58 @@
+// src/reload.rs
+//
+// Hot reload: the plugin's artifact (see Plugin::artifact) is polled, and once a change
+// has settled, the build may still be writing the file, the plugin manager swaps in a
+// new instance restored from the old one. Connections are not touched. A build that
+// does not load is reported once and the old instance keeps running.
+
+use crate::connection::SharedPluginManager;
+use crate::state::ServerState;
+use crate::Shutdown;
+
+use plugin_interface::interface_for_plugin::{modified_time, Plugin};
+
+use std::sync::Arc;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub(crate) async fn watch_artifact<P: Plugin + Send + 'static>(
+    state: Arc<ServerState>,
+    plugin_manager: SharedPluginManager<P>,
+    mut shutdown: Shutdown,
+) {
+    let artifact = plugin_manager.lock().await.artifact();
+    let mut loaded = artifact.as_deref().and_then(modified_time);
+    // a change seen on the previous poll, reloaded if the file has not moved since
+    let mut changed = None;
+
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => (),
+            _ = shutdown.wait_for(|stopped| *stopped) => break,
+        }
+
+        let Some(path) = plugin_manager.lock().await.artifact() else { continue };
+        let modified = modified_time(&path);
+        if modified.is_none() || modified == loaded {
+            changed = None;
+            continue;
+        }
+        if changed != Some(modified) {
+            changed = Some(modified);
+            continue;
+        }
+
+        let manager = plugin_manager.lock().await;
+        match manager.reload() {
+            Ok(()) => {
+                state.set_message_names(&manager.metadata());
+                tracing::info!("Plugin reloaded from {}", path.display());
+            }
+            Err(e) => tracing::warn!("Plugin not reloaded, keeping the previous one: {}", e),
+        }
+        loaded = modified;
+        changed = None;
+    }
+}
@@ -0,0 +1,231 @@
+// src/state.rs
+//
+// What the servers share: the channel to the client connected on each port, the
+// in-process listeners of the js port, the session recorder, the message inspectors,
+// the fault injector and the metrics. This is the CommunicationInterface the plugin
+// sends through.
+//
+// The metrics the simulator keeps, next to the plugin's own:
+//   simulator_connections_total{port}                   connections accepted
+//   simulator_connected_clients{port}                   1 while a client is connected
+//   simulator_messages_total{port,direction,action}     by action, or event for outbound messages
+//   simulator_handler_duration_seconds{port,action}     time the plugin took on an inbound message
+//
+// action is one of the names in the plugin's metadata, or "unknown": clients must not be
+// able to make up new series.
+
+use plugin_interface::interface_for_server::CommunicationInterface;
+use plugin_manager::fault::{FaultInjector, FaultProfile};
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_interface::metadata::Metadata;
+use plugin_manager::metrics::{Kind, Metrics};
+use plugin_manager::recorder::{Direction, Port, SessionRecorder, TraceRecord};
+use plugin_manager::PluginManager;
+
+use serde_json::Value;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+// reserved to the simulator's own metrics
+const METRICS_PREFIX: &str = "simulator_";
+// answered, or sent, by the framework for every plugin
+const FRAMEWORK_MESSAGES: [&str; 2] = ["describe", "error"];
+
+pub struct ServerState {
+    external_client_tx: Mutex<Option<UnboundedSender<Message>>>, // Sender for external client
+    js_clients_tx: Mutex<Option<UnboundedSender<Message>>>,      // Sender for JS client
+    // UIs running in the same process (the Tauri window), they get the js port output
+    // without a WebSocket, see SimulatorHandle::subscribe_js
+    js_listeners: Mutex<Vec<UnboundedSender<String>>>,
+    // every recorded message is mirrored to these, see SimulatorHandle::subscribe_inspector
+    inspectors: Mutex<Vec<UnboundedSender<TraceRecord>>>,
+    // ids of the clients connected, never reused
+    next_client_id: AtomicU64,
+    pub(crate) recorder: SessionRecorder,
+    pub(crate) faults: Arc<FaultInjector>,
+    fault_profiles: HashMap<String, FaultProfile>,
+    pub(crate) metrics: Metrics,
+    // the actions and events used as the action label, see set_message_names
+    message_names: RwLock<HashSet<String>>,
+}
+
+impl ServerState {
+    pub(crate) fn new(recorder: SessionRecorder, fault_profiles: HashMap<String, FaultProfile>) -> Self {
+        let metrics = Metrics::new();
+        metrics.describe("simulator_connections_total", Kind::Counter, "Connections accepted, by port");
+        metrics.describe("simulator_connected_clients", Kind::Gauge, "Clients connected, by port");
+        metrics.describe("simulator_messages_total", Kind::Counter, "Messages exchanged, by port, direction and action or event");
+        metrics.describe("simulator_handler_duration_seconds", Kind::Histogram, "Time the plugin took to handle an inbound message");
+        for port in [Port::Js, Port::External] {
+            metrics.set("simulator_connected_clients", &[("port", port.as_str())], 0.0);
+        }
+
+        ServerState {
+            external_client_tx: Mutex::new(None),
+            js_clients_tx: Mutex::new(None),
+            js_listeners: Mutex::new(Vec::new()),
+            inspectors: Mutex::new(Vec::new()),
+            next_client_id: AtomicU64::new(1),
+            recorder,
+            faults: Arc::new(FaultInjector::default()),
+            fault_profiles,
+            metrics,
+            message_names: RwLock::new(FRAMEWORK_MESSAGES.iter().map(|name| name.to_string()).collect()),
+        }
+    }
+
+    // Called with the plugin's metadata when it is created and again when it is reloaded
+    pub(crate) fn set_message_names(&self, metadata: &Metadata) {
+        let actions = metadata.actions.iter().map(|action| action.name.to_string());
+        let events = metadata.events.iter().map(|event| event.name.to_string());
+        let framework = FRAMEWORK_MESSAGES.iter().map(|name| name.to_string());
+        *self.message_names.write().unwrap() = actions.chain(events).chain(framework).collect();
+    }
+
+    // The action of a message, or its event; "unknown" when it has neither or the
+    // plugin does not declare it
+    fn message_name(&self, text: &str) -> String {
+        let message: Value = serde_json::from_str(text).unwrap_or_default();
+        match message.get("action").or_else(|| message.get("event")).and_then(Value::as_str) {
+            Some(name) if self.message_names.read().unwrap().contains(name) => name.to_string(),
+            _ => "unknown".to_string(),
+        }
+    }
+
+    // "none" is always available and switches fault injection off
+    pub(crate) fn fault_profile_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.fault_profiles.keys().cloned().collect();
+        names.sort();
+        names.insert(0, "none".to_string());
+        names
+    }
+
+    pub(crate) fn select_fault_profile(&self, name: &str) -> Result<(), String> {
+        let profile = match name {
+            "none" => FaultProfile::default(),
+            _ => self.fault_profiles.get(name).cloned().ok_or_else(|| format!("Unknown fault profile '{}'", name))?,
+        };
+        tracing::info!("Fault profile: {}", name);
+        self.faults.set_profile(name, profile);
+        Ok(())
+    }
+
+    fn client(&self, port: Port) -> &Mutex<Option<UnboundedSender<Message>>> {
+        match port {
+            Port::Js => &self.js_clients_tx,
+            Port::External => &self.external_client_tx,
+        }
+    }
+
+    pub(crate) fn is_connected(&self, port: Port) -> bool {
+        self.client(port).lock().unwrap().is_some()
+    }
+
+    // Registers the client connected on `port` and returns its id
+    pub(crate) fn connect(&self, port: Port, sender: UnboundedSender<Message>) -> u64 {
+        let client_id = self.next_client_id.fetch_add(1, Ordering::SeqCst);
+        *self.client(port).lock().unwrap() = Some(sender);
+        self.metrics.increment("simulator_connections_total", &[("port", port.as_str())], 1.0);
+        self.metrics.set("simulator_connected_clients", &[("port", port.as_str())], 1.0);
+        client_id
+    }
+
+    pub(crate) fn disconnect(&self, port: Port) {
+        *self.client(port).lock().unwrap() = None;
+        self.metrics.set("simulator_connected_clients", &[("port", port.as_str())], 0.0);
+    }
+
+    pub(crate) fn subscribe_js(&self, listener: UnboundedSender<String>) {
+        self.js_listeners.lock().unwrap().push(listener);
+    }
+
+    pub(crate) fn subscribe_inspector(&self, inspector: UnboundedSender<TraceRecord>) {
+        self.inspectors.lock().unwrap().push(inspector);
+    }
+
+    // Writes the message to the trace, if recording, and shows it to the inspectors
+    pub(crate) fn record(&self, direction: Direction, port: Port, client_id: u64, text: &str) {
+        self.recorder.record(direction, port, client_id, text);
+        let labels = [("port", port.as_str()), ("direction", direction.as_str()), ("action", &self.message_name(text))];
+        self.metrics.increment("simulator_messages_total", &labels, 1.0);
+
+        let mut inspectors = self.inspectors.lock().unwrap();
+        if !inspectors.is_empty() {
+            let record = TraceRecord::now(direction, port, client_id, text.to_string());
+            inspectors.retain(|inspector| inspector.send(record.clone()).is_ok());
+        }
+    }
+
+    // A message sent by hand: inbound ones go to the plugin as if a client had sent them
+    // (client 0 is never assigned to a connection), outbound ones to the clients as if
+    // the plugin had
+    pub(crate) fn deliver<P: Plugin>(&self, plugin_manager: &PluginManager<ServerState, P>, direction: Direction, port: Port, text: String) {
+        match (direction, port) {
+            (Direction::Inbound, _) => {
+                self.record(direction, port, 0, &text);
+                self.handle(plugin_manager, port, text);
+            }
+            (Direction::Outbound, Port::Js) => self.send_to_js_clients(Message::Text(text)),
+            (Direction::Outbound, Port::External) => self.send_to_external(Message::Text(text)),
+        }
+    }
+
+    // Hands an inbound message to the plugin, timing how long it takes
+    pub(crate) fn handle<P: Plugin>(&self, plugin_manager: &PluginManager<ServerState, P>, port: Port, text: String) {
+        let action = self.message_name(&text);
+        let started = Instant::now();
+        match port {
+            Port::Js => plugin_manager.handle_js_message(text),
+            Port::External => plugin_manager.handle_external_message(text),
+        }
+        let labels = [("port", port.as_str()), ("action", action.as_str())];
+        self.metrics.observe("simulator_handler_duration_seconds", &labels, started.elapsed().as_secs_f64());
+    }
+
+    // through the fault injection, the connection records what actually goes out
+    fn send_to_client(&self, message: Message, port: Port) {
+        if let Some(sender) = &*self.client(port).lock().unwrap() {
+            let _ = sender.send(message);
+        }
+    }
+}
+
+impl CommunicationInterface for ServerState {
+    fn send_to_js_clients(&self, message: Message) {
+        if let Message::Text(text) = &message {
+            let mut listeners = self.js_listeners.lock().unwrap();
+            listeners.retain(|listener| listener.send(text.clone()).is_ok());
+            // recorded once, as client 0 like injected messages, when no WebSocket client is;
+            // the faults only apply to the connections
+            if !listeners.is_empty() && !self.is_connected(Port::Js) {
+                self.record(Direction::Outbound, Port::Js, 0, text);
+            }
+        }
+        self.send_to_client(message, Port::Js);
+    }
+
+    fn send_to_external(&self, message: Message) {
+        self.send_to_client(message, Port::External);
+    }
+
+    fn count(&self, name: &str, labels: &[(&str, &str)], by: f64) {
+        if name.starts_with(METRICS_PREFIX) {
+            tracing::warn!("Metric {} ignored, the {} prefix is the simulator's", name, METRICS_PREFIX);
+            return;
+        }
+        self.metrics.increment(name, labels, by);
+    }
+
+    fn gauge(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        if name.starts_with(METRICS_PREFIX) {
+            tracing::warn!("Metric {} ignored, the {} prefix is the simulator's", name, METRICS_PREFIX);
+            return;
+        }
+        self.metrics.set(name, labels, value);
+    }
+}
@@ -0,0 +1,88 @@
+use bna_plugin::BNAPlugin;
+use simulator_server::logging::{self, LogConfig, LogFormat, RollingFile};
+use simulator_server::SimulatorServer;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("simulator-logging-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn log_files_are_rotated() {
+    let dir = temp_dir("rotation");
+    let path = dir.join("simulator.log");
+    let mut file = RollingFile::open(path.clone(), 10, 2).unwrap();
+
+    for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+        file.write_all(line.as_bytes()).unwrap();
+    }
+
+    let read = |path: PathBuf| std::fs::read_to_string(path).unwrap();
+    assert_eq!(read(path.clone()), "fourth\n");
+    assert_eq!(read(dir.join("simulator.log.1")), "third\n");
+    assert_eq!(read(dir.join("simulator.log.2")), "second\n");
+    assert!(!dir.join("simulator.log.3").exists());
+}
+
+#[test]
+fn without_a_console_the_default_is_a_log_file() {
+    let config = LogConfig::default();
+
+    assert_eq!(config.file.is_some(), !config.console);
+    if let Some(file) = config.file {
+        assert_eq!(file.parent(), std::env::current_exe().unwrap().parent());
+    }
+}
+
+// the only test here that sets up logging, it is global to the process
+#[tokio::test]
+async fn plugin_logs_land_in_the_connection_span() {
+    let path = temp_dir("json").join("simulator.log");
+    logging::init(&LogConfig {
+        filter: "info".to_string(),
+        format: LogFormat::Json,
+        console: false,
+        file: Some(path.clone()),
+        ..LogConfig::default()
+    })
+    .unwrap();
+
+    let simulator = SimulatorServer::builder().plugin::<BNAPlugin>().bind_ephemeral().start().await.unwrap();
+    simulator.inject_js_message(json!({ "action": "error" }).to_string()).await;
+    let (mut host, _) = connect_async(format!("ws://{}", simulator.external_addr())).await.unwrap();
+    // the host cannot enable a device in ERROR, the plugin logs it
+    host.send(Message::Text(json!({ "action": "enable" }).to_string())).await.unwrap();
+
+    let mut logged = None;
+    for _ in 0..50 {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let text = std::fs::read_to_string(&path).unwrap();
+        logged = text
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .find(|record| record["target"] == "plugin");
+        if logged.is_some() {
+            break;
+        }
+    }
+    let logged = logged.expect("nothing logged by the plugin");
+
+    assert_eq!(logged["level"], "WARN");
+    let spans: Vec<&Value> = logged["spans"].as_array().unwrap().iter().map(|span| &span["name"]).collect();
+    assert_eq!(spans, ["connection", "plugin_message"]);
+    assert_eq!(logged["spans"][0]["port"], "External");
+
+    host.close(None).await.unwrap();
+    let _ = host.next().await;
+    simulator.shutdown().await;
+}
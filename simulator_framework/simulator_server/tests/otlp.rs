@@ -0,0 +1,82 @@
+use bna_plugin::BNAPlugin;
+use simulator_server::logging::{self, LogConfig};
+use simulator_server::SimulatorServer;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::json;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::time::Duration;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const HOST_SPAN_ID: &str = "00f067aa0ba902b7";
+
+// A collector that accepts every OTLP/HTTP request and hands over its body
+fn collector() -> (String, mpsc::Receiver<Vec<u8>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut reader = BufReader::new(stream.unwrap());
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let _ = reader.get_mut().write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+            let _ = sender.send(body);
+        }
+    });
+
+    (endpoint, receiver)
+}
+
+fn bytes(hex: &str) -> Vec<u8> {
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+}
+
+#[tokio::test]
+async fn host_traces_continue_in_the_simulator() {
+    let (endpoint, exported) = collector();
+    logging::init(&LogConfig { console: false, otlp_endpoint: Some(endpoint), ..Default::default() }).unwrap();
+
+    let simulator = SimulatorServer::builder().plugin::<BNAPlugin>().bind_ephemeral().start().await.unwrap();
+    let (mut host, _) = connect_async(format!("ws://{}", simulator.external_addr())).await.unwrap();
+
+    let traceparent = format!("00-{}-{}-01", TRACE_ID, HOST_SPAN_ID);
+    let enable = json!({ "action": "enable", "traceparent": traceparent });
+    host.send(Message::Text(enable.to_string())).await.unwrap();
+    // messages are handled in order: once the second is answered the span of the first is closed
+    host.send(Message::Text(json!({ "action": "query_status" }).to_string())).await.unwrap();
+    for _ in 0..2 {
+        let reply = tokio::time::timeout(Duration::from_secs(5), host.next()).await.unwrap().unwrap().unwrap();
+        assert!(reply.to_text().unwrap().contains("ARMED"));
+    }
+
+    tokio::task::spawn_blocking(logging::flush).await.unwrap();
+    let body = exported.recv_timeout(Duration::from_secs(5)).expect("no spans exported");
+
+    // the spans are protobuf, the ids are in there as raw bytes
+    let contains = |needle: &[u8]| body.windows(needle.len()).any(|w| w == needle);
+    assert!(contains(b"plugin_message"));
+    assert!(contains(&bytes(TRACE_ID)), "the span is not in the host's trace");
+    assert!(contains(&bytes(HOST_SPAN_ID)), "the span is not a child of the host's");
+
+    simulator.shutdown().await;
+}
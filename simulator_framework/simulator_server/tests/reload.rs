@@ -0,0 +1,98 @@
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_interface::interface_for_server::CommunicationInterface;
+use simulator_server::SimulatorServer;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+const ARTIFACT_ENV: &str = "VERSIONED_PLUGIN_ARTIFACT";
+
+// Answers every host message with the version read from its artifact when it was
+// created, and how many messages it has seen
+struct Versioned {
+    version: String,
+    messages: u64,
+}
+
+impl Plugin for Versioned {
+    fn new() -> Self {
+        let version = fs::read_to_string(std::env::var(ARTIFACT_ENV).unwrap()).unwrap();
+        Versioned { version, messages: 0 }
+    }
+
+    fn handle_js_message<I: CommunicationInterface>(&mut self, _interface: &I, _text: String) {}
+
+    fn handle_external_message<I: CommunicationInterface>(&mut self, interface: &I, _text: String) {
+        self.messages += 1;
+        let reply = json!({ "version": self.version, "messages": self.messages });
+        interface.send_to_external(Message::Text(reply.to_string()));
+    }
+
+    fn snapshot(&self) -> Value {
+        json!({ "messages": self.messages })
+    }
+
+    fn artifact(&self) -> Option<PathBuf> {
+        std::env::var(ARTIFACT_ENV).ok().map(PathBuf::from)
+    }
+
+    fn restore(&mut self, snapshot: &Value) {
+        self.messages = snapshot["messages"].as_u64().unwrap_or(0);
+    }
+}
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn ask(host: &mut Client) -> Value {
+    host.send(Message::Text("{}".to_string())).await.unwrap();
+    let reply = tokio::time::timeout(Duration::from_secs(5), host.next()).await.unwrap().unwrap().unwrap();
+    serde_json::from_str(reply.to_text().unwrap()).unwrap()
+}
+
+// the modification time is set explicitly, file systems with a coarse clock would
+// otherwise see no change
+fn write(path: &std::path::Path, contents: &str, age: Duration) {
+    fs::write(path, contents).unwrap();
+    File::options().write(true).open(path).unwrap().set_modified(SystemTime::now() - age).unwrap();
+}
+
+#[tokio::test]
+async fn a_rebuilt_plugin_takes_over_the_open_connections() {
+    let path = std::env::temp_dir().join(format!("versioned_plugin_{}", std::process::id()));
+    write(&path, "1", Duration::from_secs(60));
+    std::env::set_var(ARTIFACT_ENV, &path);
+
+    let simulator = SimulatorServer::builder()
+        .plugin::<Versioned>()
+        .bind_ephemeral()
+        .hot_reload(true)
+        .start()
+        .await
+        .unwrap();
+    let (mut host, _) = connect_async(format!("ws://{}", simulator.external_addr())).await.unwrap();
+    assert_eq!(ask(&mut host).await, json!({ "version": "1", "messages": 1 }));
+
+    write(&path, "2", Duration::ZERO);
+    let mut reply = ask(&mut host).await;
+    for _ in 0..50 {
+        if reply["version"] == "2" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        reply = ask(&mut host).await;
+    }
+
+    assert_eq!(reply["version"], "2");
+    // the count carried over from the old instance
+    assert!(reply["messages"].as_u64().unwrap() > 2);
+
+    simulator.shutdown().await;
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,494 @@
+use bna_plugin::BNAPlugin;
+use plugin_interface::clock::VirtualClock;
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_manager::fault::FaultProfile;
+use plugin_manager::recorder::{Direction, Port};
+use simulator_server::{SimulatorHandle, SimulatorServer};
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect(addr: std::net::SocketAddr) -> Client {
+    connect_async(format!("ws://{}", addr)).await.expect("connect").0
+}
+
+async fn send(client: &mut Client, message: Value) {
+    client.send(Message::Text(message.to_string())).await.unwrap();
+}
+
+async fn receive(client: &mut Client) -> Value {
+    let message = tokio::time::timeout(Duration::from_secs(5), client.next())
+        .await
+        .expect("no message from the simulator")
+        .expect("connection closed")
+        .unwrap();
+    serde_json::from_str(message.to_text().unwrap()).unwrap()
+}
+
+// (status line, body) of a plain HTTP/1.1 request
+async fn http(addr: std::net::SocketAddr, method: &str, path: &str, body: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!("{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n\r\n{}", method, path, addr, body.len(), body);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.lines().next().unwrap().to_string(), body.to_string())
+}
+
+async fn start() -> SimulatorHandle<BNAPlugin> {
+    SimulatorServer::builder().plugin::<BNAPlugin>().bind_ephemeral().start().await.unwrap()
+}
+
+#[tokio::test]
+async fn host_talks_to_the_plugin_over_websocket() {
+    let simulator = start().await;
+    let mut host = connect(simulator.external_addr()).await;
+
+    send(&mut host, json!({ "action": "enable" })).await;
+
+    assert_eq!(receive(&mut host).await, json!({ "event": "statusChange", "status": "ARMED" }));
+    assert_eq!(simulator.state().await["status"], "ARMED");
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn the_server_runs_the_plugin_it_is_given() {
+    let mut bna = BNAPlugin::new();
+    bna.restore(&json!({ "status": "ARMED", "value": "50", "escrow": false }));
+
+    let simulator = SimulatorServer::builder().plugin_instance(bna).bind_ephemeral().start().await.unwrap();
+
+    assert_eq!(simulator.state().await, json!({ "status": "ARMED", "value": "50", "escrow": false }));
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn injected_ui_actions_reach_the_host() {
+    let simulator = start().await;
+    let mut host = connect(simulator.external_addr()).await;
+    send(&mut host, json!({ "action": "enable" })).await;
+    receive(&mut host).await;
+
+    simulator.inject_js_message(json!({ "action": "read", "value": "20" }).to_string()).await;
+
+    assert_eq!(receive(&mut host).await, json!({ "event": "read", "value": "20" }));
+    assert_eq!(receive(&mut host).await["status"], "DISABLED");
+    assert_eq!(simulator.state().await, json!({ "status": "DISABLED", "value": "20", "escrow": true }));
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn ui_client_receives_status_changes() {
+    let simulator = start().await;
+    let mut ui = connect(simulator.js_addr().unwrap()).await;
+    let mut host = connect(simulator.external_addr()).await;
+
+    send(&mut host, json!({ "action": "enable" })).await;
+
+    assert_eq!(receive(&mut ui).await["status"], "ARMED");
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn an_in_process_ui_needs_no_js_port() {
+    let simulator = SimulatorServer::builder().plugin::<BNAPlugin>().external_port(0).start().await.unwrap();
+    assert!(simulator.js_addr().is_none());
+    let mut ui = simulator.subscribe_js();
+    let mut host = connect(simulator.external_addr()).await;
+
+    send(&mut host, json!({ "action": "enable" })).await;
+    receive(&mut host).await;
+    let text = tokio::time::timeout(Duration::from_secs(5), ui.recv()).await.unwrap().unwrap();
+    assert_eq!(serde_json::from_str::<Value>(&text).unwrap()["status"], "ARMED");
+
+    simulator.inject_js_message(json!({ "action": "read", "value": "20" }).to_string()).await;
+    assert_eq!(receive(&mut host).await, json!({ "event": "read", "value": "20" }));
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn virtual_clock_drives_the_plugin_timers() {
+    let clock = Arc::new(VirtualClock::new());
+    let simulator = SimulatorServer::builder()
+        .plugin::<BNAPlugin>()
+        .bind_ephemeral()
+        .virtual_clock(clock)
+        .start()
+        .await
+        .unwrap();
+    let mut host = connect(simulator.external_addr()).await;
+    send(&mut host, json!({ "action": "enable" })).await;
+    receive(&mut host).await;
+    simulator.inject_js_message(json!({ "action": "read", "value": "50" }).to_string()).await;
+    receive(&mut host).await;
+    receive(&mut host).await;
+
+    simulator.advance(Duration::from_secs(30)).await;
+
+    assert_eq!(receive(&mut host).await, json!({ "event": "returned", "value": "50" }));
+    assert_eq!(simulator.state().await["escrow"], false);
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn control_port_is_bound_when_requested() {
+    let simulator = SimulatorServer::builder()
+        .plugin::<BNAPlugin>()
+        .control_port(0)
+        .bind_ephemeral()
+        .start()
+        .await
+        .unwrap();
+    let mut control = connect(simulator.control_addr().unwrap()).await;
+
+    send(&mut control, json!({ "command": "list_fault_profiles" })).await;
+
+    assert_eq!(receive(&mut control).await, json!({ "ok": true, "profiles": ["none"] }));
+
+    send(&mut control, json!({ "command": "describe" })).await;
+
+    let described = receive(&mut control).await;
+    assert_eq!(described["metadata"]["device_type"], "bna");
+    assert_eq!(described["metadata"], serde_json::to_value(simulator.metadata().await).unwrap());
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn browsers_get_the_ui_over_http() {
+    let simulator = SimulatorServer::builder()
+        .plugin::<BNAPlugin>()
+        .http_port(0)
+        .bind_ephemeral()
+        .start()
+        .await
+        .unwrap();
+    let addr = simulator.http_addr().unwrap();
+
+    let (status, page) = http(addr, "GET", "/", "").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(page.contains("script.js"));
+
+    let (_, description) = http(addr, "GET", "/api/ui", "").await;
+    assert_eq!(serde_json::from_str::<Value>(&description).unwrap(), serde_json::to_value(simulator.ui().await).unwrap());
+
+    // the page then talks to the plugin on the js port it is told about
+    let (_, js_port) = http(addr, "GET", "/api/js-port", "").await;
+    assert_eq!(js_port, simulator.js_addr().unwrap().port().to_string());
+
+    let (status, _) = http(addr, "POST", "/api/fault-profile", "unknown").await;
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn metrics_are_served_for_prometheus() {
+    let simulator = SimulatorServer::builder()
+        .plugin::<BNAPlugin>()
+        .http_port(0)
+        .bind_ephemeral()
+        .start()
+        .await
+        .unwrap();
+    let mut host = connect(simulator.external_addr()).await;
+
+    send(&mut host, json!({ "action": "enable" })).await;
+    receive(&mut host).await;
+    simulator.inject_js_message(r#"{"action": "read", "value": "20"}"#).await;
+    receive(&mut host).await;
+
+    let (status, metrics) = http(simulator.http_addr().unwrap(), "GET", "/metrics", "").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(metrics, simulator.metrics());
+    for line in [
+        "# TYPE simulator_connections_total counter",
+        r#"simulator_connections_total{port="external"} 1"#,
+        r#"simulator_connected_clients{port="external"} 1"#,
+        r#"simulator_connected_clients{port="js"} 0"#,
+        r#"simulator_messages_total{action="enable",direction="inbound",port="external"} 1"#,
+        r#"simulator_messages_total{action="read",direction="outbound",port="external"} 1"#,
+        r#"simulator_handler_duration_seconds_count{action="read",port="js"} 1"#,
+        r#"bna_reads_total{denomination="20"} 1"#,
+    ] {
+        assert!(metrics.lines().any(|l| l == line), "{} not in\n{}", line, metrics);
+    }
+
+    // names the plugin does not declare do not make series of their own
+    send(&mut host, json!({ "action": "made_up_1" })).await;
+    send(&mut host, json!({ "action": "made_up_2" })).await;
+    // both answered with an error
+    let mut errors = 0;
+    while errors < 2 {
+        if receive(&mut host).await["event"] == "error" {
+            errors += 1;
+        }
+    }
+    let metrics = simulator.metrics();
+    assert!(!metrics.contains("made_up"), "{}", metrics);
+    for line in [
+        r#"simulator_messages_total{action="unknown",direction="inbound",port="external"} 2"#,
+        r#"simulator_messages_total{action="error",direction="outbound",port="external"} 2"#,
+    ] {
+        assert!(metrics.lines().any(|l| l == line), "{} not in\n{}", line, metrics);
+    }
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn http_clients_that_stall_are_dropped() {
+    let simulator = SimulatorServer::builder()
+        .plugin::<BNAPlugin>()
+        .http_port(0)
+        .bind_ephemeral()
+        .start()
+        .await
+        .unwrap();
+    let mut stream = TcpStream::connect(simulator.http_addr().unwrap()).await.unwrap();
+
+    stream.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(10), stream.read_to_string(&mut response)).await.unwrap().unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 408 Request Timeout"), "{}", response);
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn http_requests_with_a_body_too_large_are_refused() {
+    let simulator = SimulatorServer::builder()
+        .plugin::<BNAPlugin>()
+        .http_port(0)
+        .bind_ephemeral()
+        .start()
+        .await
+        .unwrap();
+    let addr = simulator.http_addr().unwrap();
+
+    for length in [u64::MAX, 1 << 20] {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("POST /api/fault-profile HTTP/1.1\r\nContent-Length: {}\r\n\r\nslow", length);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response)).await.unwrap().unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request"), "{}", response);
+    }
+    // and the server carries on
+    assert_eq!(http(addr, "GET", "/api/ui", "").await.0, "HTTP/1.1 200 OK");
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn the_ui_can_be_served_from_a_directory() {
+    let simulator = SimulatorServer::builder()
+        .plugin::<BNAPlugin>()
+        .http_port(0)
+        .ui_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/../assets/ui"))
+        .bind_ephemeral()
+        .start()
+        .await
+        .unwrap();
+    let addr = simulator.http_addr().unwrap();
+
+    assert_eq!(http(addr, "GET", "/styles.css", "").await.0, "HTTP/1.1 200 OK");
+    assert_eq!(http(addr, "GET", "/../Cargo.toml", "").await.0, "HTTP/1.1 404 Not Found");
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn without_the_js_port_only_the_metrics_are_served() {
+    let simulator = SimulatorServer::builder().plugin::<BNAPlugin>().external_port(0).http_port(0).start().await.unwrap();
+    let addr = simulator.http_addr().unwrap();
+
+    let (status, metrics) = http(addr, "GET", "/metrics", "").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(metrics.contains("simulator_connected_clients"));
+    assert_eq!(http(addr, "GET", "/", "").await.0, "HTTP/1.1 404 Not Found");
+    assert_eq!(http(addr, "GET", "/api/js-port", "").await.0, "HTTP/1.1 404 Not Found");
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn the_inspector_sees_both_directions() {
+    let simulator = start().await;
+    let mut inspector = simulator.subscribe_inspector();
+    let mut host = connect(simulator.external_addr()).await;
+
+    send(&mut host, json!({ "action": "enable" })).await;
+    receive(&mut host).await;
+    // a hand-written message as the device reaches the host like the plugin's own
+    simulator.send_as(Direction::Outbound, Port::External, r#"{"event":"returned"}"#).await;
+    assert_eq!(receive(&mut host).await, json!({ "event": "returned" }));
+
+    let mut seen = Vec::new();
+    while seen.len() < 3 {
+        let record = tokio::time::timeout(Duration::from_secs(5), inspector.recv()).await.unwrap().unwrap();
+        assert_eq!(record.port, Port::External);
+        seen.push((record.direction, serde_json::from_str::<Value>(&record.message).unwrap()));
+    }
+    assert_eq!(
+        seen,
+        [
+            (Direction::Inbound, json!({ "action": "enable" })),
+            (Direction::Outbound, json!({ "event": "statusChange", "status": "ARMED" })),
+            (Direction::Outbound, json!({ "event": "returned" })),
+        ]
+    );
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn faults_apply_to_both_ports_and_directions() {
+    let garbled = FaultProfile { corrupt_rate: 1.0, seed: Some(11), ..FaultProfile::default() };
+    let simulator = SimulatorServer::builder()
+        .plugin::<BNAPlugin>()
+        .fault_profiles(HashMap::from([("garbled".to_string(), garbled)]))
+        .fault_profile("garbled")
+        .bind_ephemeral()
+        .start()
+        .await
+        .unwrap();
+    let mut inspector = simulator.subscribe_inspector();
+
+    for (port, addr) in [(Port::External, simulator.external_addr()), (Port::Js, simulator.js_addr().unwrap())] {
+        let mut client = connect(addr).await;
+        let sent = json!({ "action": "enable" }).to_string();
+        client.send(Message::Text(sent.clone())).await.unwrap();
+
+        // the plugin got a corrupted message and its error reply was corrupted too
+        let reply = tokio::time::timeout(Duration::from_secs(5), client.next()).await.unwrap().unwrap().unwrap();
+        assert!(reply.to_text().unwrap().ends_with("\u{0}#!"), "{:?}", reply);
+
+        // the trace has the messages as they were delivered
+        let inbound = inspector.recv().await.unwrap();
+        assert_eq!((inbound.port, inbound.direction), (port, Direction::Inbound));
+        assert_ne!(inbound.message, sent);
+        assert!(inbound.message.ends_with("\u{0}#!"));
+        let outbound = inspector.recv().await.unwrap();
+        assert_eq!((outbound.port, outbound.direction), (port, Direction::Outbound));
+        assert_eq!(outbound.message, reply.to_text().unwrap());
+    }
+    assert_eq!(simulator.state().await["status"], "DISABLED");
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn the_control_port_streams_the_inspector() {
+    let simulator = SimulatorServer::builder()
+        .plugin::<BNAPlugin>()
+        .control_port(0)
+        .bind_ephemeral()
+        .start()
+        .await
+        .unwrap();
+    let mut control = connect(simulator.control_addr().unwrap()).await;
+    let mut host = connect(simulator.external_addr()).await;
+    // once answered the host is surely registered
+    send(&mut host, json!({ "action": "describe" })).await;
+    receive(&mut host).await;
+
+    send(&mut control, json!({ "command": "inspect" })).await;
+    assert_eq!(receive(&mut control).await, json!({ "ok": true }));
+
+    // as the host, without a host connection of its own
+    let enable = json!({ "action": "enable" }).to_string();
+    send(&mut control, json!({ "command": "send", "direction": "inbound", "port": "external", "message": enable })).await;
+
+    let mut replies = Vec::new();
+    while replies.len() < 3 {
+        replies.push(receive(&mut control).await);
+    }
+    assert!(replies.contains(&json!({ "ok": true })));
+    let inspected: Vec<&Value> = replies.iter().filter(|reply| reply["event"] == "inspect").map(|reply| &reply["record"]).collect();
+    assert_eq!(inspected[0]["direction"], "inbound");
+    assert_eq!(inspected[0]["message"], enable);
+    assert_eq!(inspected[1]["direction"], "outbound");
+    assert_eq!(receive(&mut host).await["status"], "ARMED");
+
+    send(&mut control, json!({ "command": "send", "direction": "sideways", "port": "js", "message": "{}" })).await;
+    assert_eq!(receive(&mut control).await["ok"], false);
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn hosts_can_ask_what_the_device_supports() {
+    let simulator = start().await;
+    let mut host = connect(simulator.external_addr()).await;
+
+    send(&mut host, json!({ "action": "describe" })).await;
+
+    let describe = receive(&mut host).await;
+    assert_eq!(describe["event"], "describe");
+    assert_eq!(describe["model"], "BNA simulator");
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn inbound_messages_can_be_validated() {
+    let simulator = SimulatorServer::builder()
+        .plugin::<BNAPlugin>()
+        .validate_inbound(true)
+        .bind_ephemeral()
+        .start()
+        .await
+        .unwrap();
+    let mut host = connect(simulator.external_addr()).await;
+    let mut ui = connect(simulator.js_addr().unwrap()).await;
+
+    send(&mut ui, json!({ "action": "read", "value": 20 })).await;
+    let rejected = receive(&mut ui).await;
+    assert_eq!(rejected["event"], "error");
+    assert!(rejected["message"].as_str().unwrap().starts_with("read: 20 is not of type"), "{}", rejected);
+
+    send(&mut host, json!({ "action": "enable" })).await;
+    assert_eq!(receive(&mut host).await, json!({ "event": "statusChange", "status": "ARMED" }));
+    assert_eq!(simulator.state().await["value"], "");
+
+    simulator.shutdown().await;
+}
+
+#[tokio::test]
+async fn shutdown_releases_the_ports() {
+    let simulator = start().await;
+    let addr = simulator.external_addr();
+    let mut host = connect(addr).await;
+
+    simulator.shutdown().await;
+
+    let closed = tokio::time::timeout(Duration::from_secs(5), host.next()).await.unwrap();
+    assert!(!matches!(closed, Some(Ok(Message::Text(_)))));
+    assert!(connect_async(format!("ws://{}", addr)).await.is_err());
+}
+
+#[tokio::test]
+async fn the_external_port_is_required() {
+    let result = SimulatorServer::builder().plugin::<BNAPlugin>().start().await;
+
+    assert!(result.is_err());
+}
@@ -1,23 +1,23 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use plugin_interface::interface_for_server::CommunicationInterface;
-use plugin_interface::interface_for_plugin::Plugin;
-
-use tauri::command;
-use tokio::net::TcpListener;
-use tokio_tungstenite::accept_async;
-use tokio_tungstenite::tungstenite::protocol::Message;
-use futures_util::{SinkExt, StreamExt};
+use tauri::{command, Manager};
 
-use std::sync::Arc;
-use tokio::sync::{Mutex, mpsc::{self, UnboundedSender}};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
 use serde::Deserialize;
 use std::fs::File;
 use std::io::Read;
-use tokio::task::JoinHandle;
 
-use plugin_manager::PluginManager;
+use plugin_interface::interface_for_plugin::Plugin;
+use plugin_interface::metadata::Metadata;
+use plugin_interface::ui::UiDescription;
+use plugin_manager::fault::FaultProfile;
+use plugin_manager::recorder::{self, Direction, Port};
+use plugin_manager::replay::{self, ReplayOptions};
+use plugin_manager::schema;
+use simulator_server::logging::{self, LogConfig};
+use simulator_server::{ServerBuilder, SimulatorHandle, SimulatorServer};
 
 #[cfg(feature = "feature-barcode")]
 use barcode_plugin::BarcodePlugin; // or another plugin
@@ -36,11 +36,96 @@ use card_plugin::CardPlugin;
 #[cfg(feature = "feature-card")]
 type SelectedPlugin = CardPlugin;
 
+#[cfg(feature = "feature-declarative")]
+use declarative_plugin::DeclarativePlugin;
+#[cfg(feature = "feature-declarative")]
+type SelectedPlugin = DeclarativePlugin;
+
+#[cfg(feature = "feature-script")]
+use script_plugin::ScriptPlugin;
+#[cfg(feature = "feature-script")]
+type SelectedPlugin = ScriptPlugin;
+
+#[cfg(feature = "feature-dynamic")]
+use plugin_loader::DynamicPlugin;
+#[cfg(feature = "feature-dynamic")]
+type SelectedPlugin = DynamicPlugin;
+
+#[cfg(feature = "feature-wasm")]
+use wasm_plugin::WasmPlugin;
+#[cfg(feature = "feature-wasm")]
+type SelectedPlugin = WasmPlugin;
+
 
 #[derive(Deserialize)]
 struct Config {
-    js_port: u16,
+    // WebSocket port for UIs outside the application, the bundled one uses send_js_message
+    #[serde(default)]
+    js_port: Option<u16>,
     external_port: u16,
+    // session recording, see plugin_manager::recorder
+    #[serde(default)]
+    record: bool,
+    #[serde(default)]
+    trace_file: Option<String>,
+    // WebSocket port for test tooling, see handle_control_command
+    #[serde(default)]
+    control_port: Option<u16>,
+    // fault injection on the WebSocket connections, see plugin_manager::fault
+    #[serde(default)]
+    fault_profile: Option<String>,
+    #[serde(default)]
+    fault_profiles: HashMap<String, FaultProfile>,
+    // device definition file for the declarative plugin, relative to the executable
+    #[serde(default)]
+    #[cfg_attr(not(feature = "feature-declarative"), allow(dead_code))]
+    device_definition: Option<String>,
+    // Rhai script for the script plugin, relative to the executable
+    #[serde(default)]
+    #[cfg_attr(not(feature = "feature-script"), allow(dead_code))]
+    script: Option<String>,
+    // shared library plugins, see plugin_loader: the directory relative to the executable
+    // (plugins by default) and the name of the plugin to run, needed when there are several
+    #[serde(default)]
+    #[cfg_attr(not(feature = "feature-dynamic"), allow(dead_code))]
+    plugins_dir: Option<String>,
+    #[serde(default)]
+    #[cfg_attr(not(feature = "feature-dynamic"), allow(dead_code))]
+    plugin: Option<String>,
+    // WebAssembly module for the wasm plugin, relative to the executable
+    #[serde(default)]
+    #[cfg_attr(not(feature = "feature-wasm"), allow(dead_code))]
+    wasm_module: Option<String>,
+    // reload the plugin when it is rebuilt, see simulator_server::ServerBuilder::hot_reload
+    #[serde(default)]
+    hot_reload: bool,
+    // answer inbound messages that do not match the plugin's schema with an error
+    #[serde(default)]
+    validate_inbound: bool,
+    // serve the UI to browsers, see simulator_server::ServerBuilder::http_port; ui_dir
+    // (relative to the executable) replaces the UI built into the server
+    #[serde(default)]
+    http_port: Option<u16>,
+    #[serde(default)]
+    ui_dir: Option<String>,
+    // address the servers listen on, 127.0.0.1 by default; 0.0.0.0 opens them to the network
+    #[serde(default)]
+    bind_address: Option<IpAddr>,
+    // diagnostics, see simulator_server::logging; the file is relative to the executable,
+    // otlp_endpoint sends the spans to an OpenTelemetry collector
+    #[serde(default)]
+    log: LogConfig,
+}
+
+impl Config {
+    fn trace_path(&self) -> std::path::PathBuf {
+        let exe_path = std::env::current_exe().expect("Failed to get current executable path");
+        let exe_dir = exe_path.parent().unwrap();
+        match &self.trace_file {
+            Some(file) => exe_dir.join(file),
+            None => exe_dir.join("session_trace.jsonl"),
+        }
+    }
 }
 
 fn load_config() -> Config {
@@ -53,226 +138,384 @@ fn load_config() -> Config {
 }
 
 #[command]
-fn get_js_port() -> u16 {
+fn get_js_port() -> Option<u16> {
     let config = load_config();
     config.js_port
 }
 
-// Define the `AppState` without `PluginManager`
-#[derive(Clone)]
-struct AppState {
-    external_client_tx: Arc<Mutex<Option<UnboundedSender<Message>>>>,  // Sender for external client
-    js_clients_tx: Arc<Mutex<Option<UnboundedSender<Message>>>>, // Broadcast sender for JS clients
+type Simulator = SimulatorHandle<SelectedPlugin>;
+
+#[command]
+fn set_recording(simulator: tauri::State<'_, Simulator>, enabled: bool) {
+    simulator.set_recording(enabled);
 }
-impl AppState{
-
-    fn send_to_client(&self, message: Message, client_channel: &Arc<Mutex<Option<UnboundedSender<Message>>>>)
-    {
-        // This function is called synchronously, so we use `block_in_place`
-        // to run async code in a blocking context.
-
-        tokio::task::block_in_place(|| {
-            let rt = tokio::runtime::Handle::current();
-            rt.block_on(async {
-                let client_channel_mut = client_channel.lock().await;
-                if let Some(sender) = &*client_channel_mut {
-                    let _ = sender.send(message);
-                }
-            });
-        });
-    }
+
+#[command]
+fn is_recording(simulator: tauri::State<'_, Simulator>) -> bool {
+    simulator.is_recording()
 }
 
-impl CommunicationInterface for AppState 
-{
-    fn send_to_js_clients(&self, message: Message) 
-    {
-        self.send_to_client(message, &self.js_clients_tx);
-    }
+#[command]
+fn list_fault_profiles(simulator: tauri::State<'_, Simulator>) -> Vec<String> {
+    simulator.fault_profile_names()
+}
 
-    fn send_to_external(&self, message: Message) 
-    {
-        self.send_to_client(message, &self.external_client_tx);
-    }
+#[command]
+fn get_fault_profile(simulator: tauri::State<'_, Simulator>) -> String {
+    simulator.fault_profile()
 }
 
+#[command]
+fn set_fault_profile(simulator: tauri::State<'_, Simulator>, name: String) -> Result<(), String> {
+    simulator.set_fault_profile(&name)
+}
 
-#[tokio::main]
-async fn main() {
-    tauri::Builder::default()
-        .setup(move |_app| {
+// The commands below wait for the plugin, which may be busy with a message: they are
+// async so the wait happens off the main thread. Async commands borrowing State have
+// to return a Result.
 
-            let state = Arc::new(AppState {
-                js_clients_tx: Arc::new(Mutex::new(None)),
-                external_client_tx: Arc::new(Mutex::new(None)),
-            });
+// what the plugin supports, as {"action": "describe"} on the external port
+#[command]
+async fn describe(simulator: tauri::State<'_, Simulator>) -> Result<Metadata, String> {
+    Ok(simulator.metadata().await)
+}
 
-            let plugin_manager = Arc::new(Mutex::new(PluginManager::<AppState, SelectedPlugin>::new(state.clone())));
-
-            let config = load_config();
-
-            // Spawning the WebSocket server with JS client handler
-            tauri::async_runtime::spawn(start_websocket_server(
-                config.js_port,
-                {
-                    let state = state.clone();
-                    let plugin_manager = plugin_manager.clone();
-                    // non ti preoccupare di avere tokio::spawn annidati
-                    move |stream| tokio::spawn(handle_js_client(state.clone(), plugin_manager.clone(), stream))
-                },
-            ));
-            
-
-            // Spawning the WebSocket server with External client handler
-            tauri::async_runtime::spawn(start_websocket_server(
-                config.external_port,
-                {
-                    let state = state.clone();
-                    let plugin_manager = plugin_manager.clone();
-                    // non ti preoccupare di avere tokio::spawn annidati
-                    move |stream| tokio::spawn(handle_external_client(state.clone(), plugin_manager.clone(), stream))
-                },
-            ));
+// a message from the bundled UI, as if sent on the js port; the plugin's answers come
+// back as "js_message" events
+#[command]
+async fn send_js_message(simulator: tauri::State<'_, Simulator>, message: String) -> Result<(), String> {
+    simulator.inject_js_message(message).await;
+    Ok(())
+}
 
-            Ok(())
-        })
-        .invoke_handler(tauri::generate_handler![get_js_port])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+// a hand-written message from the inspector panel, as either side of either port
+#[command]
+async fn inspector_send(simulator: tauri::State<'_, Simulator>, direction: Direction, port: Port, message: String) -> Result<(), String> {
+    simulator.send_as(direction, port, message).await;
+    Ok(())
 }
 
+// the controls assets/ui builds its page from
+#[command]
+async fn get_ui_description(simulator: tauri::State<'_, Simulator>) -> Result<UiDescription, String> {
+    Ok(simulator.ui().await)
+}
 
 
-// The merged WebSocket server function with simplified parameters
-async fn start_websocket_server<F>(port: u16, handler: F)
-where
-    F: Fn(tokio::net::TcpStream) -> JoinHandle<()>,
-{
-    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))
-        .await
-        .expect("Failed to bind WebSocket server");
-    println!("WebSocket server running on ws://127.0.0.1:{}", port);
+const REPLAY_USAGE: &str = "usage: (--replay-plugin <trace> | --replay-host <trace>) [--speed <factor>], factor being a number >= 0";
+
+fn arg_value(args: &[String], flag: &str) -> Option<String> {
+    args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1)).cloned()
+}
 
-    while let Ok((stream, _)) = listener.accept().await {
-        // non ti preoccupare di avere tokio::spawn annidati
-        tokio::spawn(handler(stream));
+// Release builds have no console of their own on Windows (windows_subsystem above), so
+// the report and the errors of the command line modes go to the one they were started
+// from, if any
+fn attach_console() {
+    #[cfg(all(windows, not(debug_assertions)))]
+    unsafe {
+        use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+        AttachConsole(ATTACH_PARENT_PROCESS);
     }
 }
 
-async fn handle_js_client<P: Plugin>(state: Arc<AppState>, plugin_manager: Arc<Mutex<PluginManager<AppState, P>>>, stream: tokio::net::TcpStream) {
-   // ad ogni nuova connessione si finisce qui...
-   if state.js_clients_tx.lock().await.is_some() {
-    // Refuse connection if another external client is already connected
-    println!("Connection refused: Another external client is already connected.");
-    return;
+// Replay mode, runs instead of the simulator when one of these is given:
+//   --replay-plugin <trace>  feeds the recorded inbound messages to the plugin and diffs its answers
+//   --replay-host <trace>    plays the device side of the trace to the host connecting on external_port
+//                            (on bind_address, like the simulator)
+//   --speed <factor>         1.0 keeps the recorded timing (default), 0 replays without waiting
+async fn run_replay(args: &[String]) -> Option<i32> {
+    let (trace_path, to_host) = match (arg_value(args, "--replay-plugin"), arg_value(args, "--replay-host")) {
+        (Some(path), _) => (path, false),
+        (None, Some(path)) => (path, true),
+        (None, None) => return None,
+    };
+    attach_console();
+
+    let mut options = ReplayOptions::default();
+    if let Some(speed) = arg_value(args, "--speed") {
+        match speed.parse::<f64>() {
+            Ok(speed) if speed.is_finite() && speed >= 0.0 => options.speed = speed,
+            _ => {
+                println!("Invalid --speed {}\n{}", speed, REPLAY_USAGE);
+                return Some(2);
+            }
+        }
     }
 
-    // accetta la connessione...
-    let ws_stream = match accept_async(stream).await {
-        Ok(ws) => ws,
+    let records = match recorder::read_trace(&trace_path) {
+        Ok(records) => records,
         Err(e) => {
-            println!("Error during WebSocket handshake: {}", e);
-            return; // Exit the function if the handshake fails
+            println!("Unable to read trace {}: {}", trace_path, e);
+            return Some(2);
         }
     };
 
-    // se la connessione e' valida si prosegue da qui...
+    let report = if to_host {
+        let config = load_config();
+        let address = SocketAddr::new(config.bind_address.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)), config.external_port);
+        match replay::replay_to_host(&records, address, &options).await {
+            Ok(report) => report,
+            Err(e) => {
+                println!("Replay failed: {}", e);
+                return Some(2);
+            }
+        }
+    } else {
+        replay::replay_against(selected_plugin(&load_config()), &records, &options).await
+    };
 
-    // si splitta il canale di comunicazione con l'OP in due (write e read)
-    let (mut write_to_socket, mut read_from_socket) = ws_stream.split();
+    println!("{}", report);
+    Some(if report.is_success() { 0 } else { 1 })
+}
 
-    // qui si crea un nuovo canale di comunication tra questo thread e il thread che gestisce la richiesta...
-    let (tx, mut rx) = mpsc::unbounded_channel();
+// --export-schema <dir> writes the plugin's protocol there instead of running the simulator:
+// schema.json with a JSON Schema for every action and event, asyncapi-js.json and
+// asyncapi-external.json with an AsyncAPI document for each port (the js one only when
+// js_port is set)
+fn run_export_schema(args: &[String]) -> Option<i32> {
+    let dir = std::path::PathBuf::from(arg_value(args, "--export-schema")?);
+    attach_console();
+
+    let config = load_config();
+    let metadata = selected_plugin(&config).metadata();
+    let mut documents = vec![
+        ("schema.json", schema::json_schema(&metadata)),
+        ("asyncapi-external.json", schema::asyncapi(&metadata, Port::External, &format!("localhost:{}", config.external_port))),
+    ];
+    if let Some(js_port) = config.js_port {
+        documents.push(("asyncapi-js.json", schema::asyncapi(&metadata, Port::Js, &format!("localhost:{}", js_port))));
+    }
 
-    // salva il lato tx del canale interno nella variabile apposita...
-    *state.js_clients_tx.lock().await = Some(tx);
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        println!("Unable to create {}: {}", dir.display(), e);
+        return Some(2);
+    }
+    for (file, document) in documents {
+        let path = dir.join(file);
+        let text = serde_json::to_string_pretty(&document).expect("schemas always serialize");
+        if let Err(e) = std::fs::write(&path, text) {
+            println!("Unable to write {}: {}", path.display(), e);
+            return Some(2);
+        }
+        println!("Written {}", path.display());
+    }
+    Some(0)
+}
 
-    // qui si fa partire un altro thread che sta in ascolto per la ricezione della risposta (interna),
-    // quando si riceve la risposta (generata da un altro thread) qui si manda la risposta all'OP
-    tokio::spawn(async move {
-        while let Some(msg) = rx.recv().await
-        {
-            if write_to_socket.send(msg).await.is_err() 
-            {
-                // errore sul socket... annulla questa sessione...
-                break;
+#[cfg(feature = "feature-dynamic")]
+fn find_plugin_library(config: &Config) -> Option<plugin_loader::PluginLibrary> {
+    let exe_path = std::env::current_exe().expect("Failed to get current executable path");
+    let dir = exe_path.parent().unwrap().join(config.plugins_dir.as_deref().unwrap_or("plugins"));
+
+    let mut found = Vec::new();
+    for library in plugin_loader::discover(&dir) {
+        match library {
+            Ok(library) => {
+                tracing::info!("Plugin found: {} ({})", library.name(), library.path().unwrap().display());
+                found.push(library);
             }
+            Err(e) => tracing::warn!("Plugin not loaded: {}", e),
+        }
+    }
+
+    let position = match &config.plugin {
+        Some(name) => found.iter().position(|library| library.name() == name),
+        None if found.len() == 1 => Some(0),
+        None => None,
+    };
+    if position.is_none() {
+        tracing::warn!("No plugin selected from {}, check \"plugin\" in config.json", dir.display());
+    }
+    position.map(|i| found.swap_remove(i))
+}
+
+// The plugin to run, built from the definition the config names, if any...
+#[cfg(feature = "feature-declarative")]
+fn selected_plugin(config: &Config) -> SelectedPlugin {
+    match &config.device_definition {
+        Some(file) => {
+            let exe_path = std::env::current_exe().expect("Failed to get current executable path");
+            DeclarativePlugin::open(exe_path.parent().unwrap().join(file))
+        }
+        None => DeclarativePlugin::new(),
+    }
+}
+
+// ... or from the script
+#[cfg(feature = "feature-script")]
+fn selected_plugin(config: &Config) -> SelectedPlugin {
+    match &config.script {
+        Some(file) => {
+            let exe_path = std::env::current_exe().expect("Failed to get current executable path");
+            ScriptPlugin::open(exe_path.parent().unwrap().join(file))
         }
-    });
-
-    // qui si va a gestire la richiesta dell'OP (su questo thread...), 
-    // ed eventuali future richieste da questa connessione...
-    while let Some(Ok(msg)) = read_from_socket.next().await 
-    {
-        if let Message::Text(text) = msg 
-        {
-            // Forward the message to the plugin manager for handling
-            // Forward the message to the plugin manager for handling
-            let lock_on_plugin = plugin_manager.lock().await;
-            lock_on_plugin.handle_js_message(text);
+        None => ScriptPlugin::new(),
+    }
+}
+
+// ... or from the module
+#[cfg(feature = "feature-wasm")]
+fn selected_plugin(config: &Config) -> SelectedPlugin {
+    match &config.wasm_module {
+        Some(file) => {
+            let exe_path = std::env::current_exe().expect("Failed to get current executable path");
+            WasmPlugin::open(exe_path.parent().unwrap().join(file), wasm_plugin::Limits::default())
         }
+        None => WasmPlugin::new(),
+    }
+}
+
+// ... or from the library found in the plugins directory
+#[cfg(feature = "feature-dynamic")]
+fn selected_plugin(config: &Config) -> SelectedPlugin {
+    match find_plugin_library(config) {
+        Some(library) => DynamicPlugin::with_library(std::sync::Arc::new(library)),
+        None => DynamicPlugin::new(),
     }
+}
+
+#[cfg(not(any(
+    feature = "feature-declarative",
+    feature = "feature-script",
+    feature = "feature-wasm",
+    feature = "feature-dynamic"
+)))]
+fn selected_plugin(_config: &Config) -> SelectedPlugin {
+    SelectedPlugin::new()
+}
 
-    println!("Connection closed");
-    *state.js_clients_tx.lock().await = None;
+// i server WebSocket (js, external ed eventualmente control e http) stanno in simulator_server
+fn server_builder(config: &Config) -> ServerBuilder<SelectedPlugin> {
+    let mut builder = SimulatorServer::builder()
+        .plugin_instance(selected_plugin(config))
+        .external_port(config.external_port)
+        .trace_file(config.trace_path(), config.record)
+        .fault_profiles(config.fault_profiles.clone())
+        .hot_reload(config.hot_reload)
+        .validate_inbound(config.validate_inbound);
+    if let Some(address) = config.bind_address {
+        builder = builder.bind_address(address);
+    }
+    if let Some(js_port) = config.js_port {
+        builder = builder.js_port(js_port);
+    }
+    if let Some(control_port) = config.control_port {
+        builder = builder.control_port(control_port);
+    }
+    if let Some(http_port) = config.http_port {
+        builder = builder.http_port(http_port);
+    }
+    if let Some(dir) = &config.ui_dir {
+        let exe_path = std::env::current_exe().expect("Failed to get current executable path");
+        builder = builder.ui_dir(exe_path.parent().unwrap().join(dir));
+    }
+    if let Some(name) = &config.fault_profile {
+        builder = builder.fault_profile(name);
+    }
+    builder
 }
 
-async fn handle_external_client<P: Plugin>(state: Arc<AppState>, plugin_manager: Arc<Mutex<PluginManager<AppState, P>>>, stream: tokio::net::TcpStream) 
-{
-    // ad ogni nuova connessione si finisce qui...
-    if state.external_client_tx.lock().await.is_some() {
-        // Refuse connection if another external client is already connected
-        println!("Connection refused: Another external client is already connected.");
-        return;
+// --headless runs the servers without a window, until Ctrl+C; the UI is then only
+// reachable over http_port
+async fn run_headless(args: &[String]) -> Option<i32> {
+    if !args.iter().any(|a| a == "--headless") {
+        return None;
     }
 
-    // accetta la connessione...
-    let ws_stream = match accept_async(stream).await {
-        Ok(ws) => ws,
+    let simulator = match server_builder(&load_config()).start().await {
+        Ok(simulator) => simulator,
         Err(e) => {
-            println!("Error during WebSocket handshake: {}", e);
-            return; // Exit the function if the handshake fails
+            tracing::error!("Unable to start the simulator: {}", e);
+            return Some(2);
         }
     };
-    
-    // se la connessione e' valida si prosegue da qui...
-
-    // si splitta il canale di comunicazione con l'OP in due (write e read)
-    let (mut write_to_socket, mut read_from_socket) = ws_stream.split();
-
-    // qui si crea un nuovo canale di comunication tra questo thread e il thread che gestisce la richiesta...
-    let (tx, mut rx) = mpsc::unbounded_channel();
-
-    // salva il lato tx del canale interno nella variabile apposita...
-    *state.external_client_tx.lock().await = Some(tx);
-
-    // qui si fa partire un altro thread che sta in ascolto per la ricezione della risposta (interna),
-    // quando si riceve la risposta (generata da un altro thread) qui si manda la risposta all'OP
-    tokio::spawn(async move {
-        while let Some(msg) = rx.recv().await
-        {
-            if write_to_socket.send(msg).await.is_err() 
-            {
-                // errore sul socket... annulla questa sessione...
-                break;
-            }
-        }
-    });
-
-    // qui si va a gestire la richiesta dell'OP (su questo thread...), 
-    // ed eventuali future richieste da questa connessione...
-    while let Some(Ok(msg)) = read_from_socket.next().await 
-    {
-        if let Message::Text(text) = msg 
-        {
-            // Forward the message to the plugin manager for handling
-              // Forward the message to the plugin manager for handling
-              let lock_on_plugin = plugin_manager.lock().await;
-              lock_on_plugin.handle_external_message(text);
-        }
+    let _ = tokio::signal::ctrl_c().await;
+    simulator.shutdown().await;
+    Some(0)
+}
+
+// Release builds have no console on Windows, they log to simulator.log unless the
+// config says otherwise, see LogConfig::default; a relative file is next to the
+// executable
+fn init_logging(config: &Config) {
+    let exe_path = std::env::current_exe().expect("Failed to get current executable path");
+    let exe_dir = exe_path.parent().unwrap();
+
+    let mut log = config.log.clone();
+    log.file = log.file.map(|file| exe_dir.join(file));
+    if let Err(e) = logging::init(&log) {
+        eprintln!("{}", e);
+    }
+}
+
+// Exports the spans still buffered, see logging::flush
+fn exit(code: i32) -> ! {
+    logging::flush();
+    std::process::exit(code);
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    // before the replay and the schema export, which log too
+    let config = load_config();
+    init_logging(&config);
+
+    if let Some(code) = run_export_schema(&args) {
+        exit(code);
     }
-    
-    println!("Connection closed");
-    *state.external_client_tx.lock().await = None;
+    if let Some(code) = tauri::async_runtime::block_on(run_replay(&args)) {
+        exit(code);
+    }
+    if let Some(code) = tauri::async_runtime::block_on(run_headless(&args)) {
+        exit(code);
+    }
+
+    tauri::Builder::default()
+        .setup(move |app| {
+
+            let simulator = tauri::async_runtime::block_on(server_builder(&load_config()).start())?;
+
+            // l'output del plugin sulla porta js arriva alla UI come evento, senza WebSocket
+            let mut js_messages = simulator.subscribe_js();
+            let app_handle = app.handle();
+            tauri::async_runtime::spawn(async move {
+                while let Some(text) = js_messages.recv().await {
+                    let _ = app_handle.emit_all("js_message", text);
+                }
+            });
+
+            // e tutto il traffico delle due porte arriva al pannello inspector
+            let mut inspected = simulator.subscribe_inspector();
+            let app_handle = app.handle();
+            tauri::async_runtime::spawn(async move {
+                while let Some(record) = inspected.recv().await {
+                    let _ = app_handle.emit_all("inspector", record);
+                }
+            });
+
+            app.manage(simulator);
+
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            get_js_port,
+            set_recording,
+            is_recording,
+            list_fault_profiles,
+            get_fault_profile,
+            set_fault_profile,
+            describe,
+            get_ui_description,
+            send_js_message,
+            inspector_send
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|_, event| {
+            if let tauri::RunEvent::Exit = event {
+                logging::flush();
+            }
+        });
 }
-    
\ No newline at end of file